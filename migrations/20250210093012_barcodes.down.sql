@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_users_card_number;
+ALTER TABLE Users DROP COLUMN card_number;
+
+DROP INDEX IF EXISTS idx_book_copies_barcode;
+ALTER TABLE BookCopies DROP COLUMN barcode;
@@ -0,0 +1,8 @@
+-- Scannable identifiers for book copies and library cards.
+-- Values are generated by the application (they carry a check digit),
+-- existing rows are filled in on startup.
+ALTER TABLE BookCopies ADD COLUMN barcode TEXT;
+CREATE UNIQUE INDEX idx_book_copies_barcode ON BookCopies(barcode);
+
+ALTER TABLE Users ADD COLUMN card_number TEXT;
+CREATE UNIQUE INDEX idx_users_card_number ON Users(card_number);
@@ -0,0 +1,29 @@
+CREATE TABLE Reservations_old (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    copy_id INTEGER NOT NULL,
+    book_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    reservation_date DATE NOT NULL DEFAULT CURRENT_DATE,
+    status TEXT CHECK(status IN ('pending','active', 'expired', 'cancelled', 'declined')) DEFAULT 'pending',
+    updated_at TIMESTAMP,
+    FOREIGN KEY (book_id, copy_id) REFERENCES BookCopies(book_id, id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
+INSERT INTO Reservations_old
+SELECT id, copy_id, book_id, user_id, reservation_date,
+       CASE status WHEN 'fulfilled' THEN 'expired' ELSE status END,
+       updated_at
+FROM Reservations;
+DROP TABLE Reservations;
+ALTER TABLE Reservations_old RENAME TO Reservations;
+
+CREATE TRIGGER update_reservations_timestamp
+AFTER UPDATE ON Reservations
+FOR EACH ROW
+BEGIN
+    UPDATE Reservations
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
+
+ALTER TABLE Borrowing DROP COLUMN renewals;
@@ -0,0 +1,29 @@
+-- Number of times a loan has been renewed
+ALTER TABLE Borrowing ADD COLUMN renewals INTEGER NOT NULL DEFAULT 0;
+
+-- Reservations are marked 'fulfilled' once the copy is checked out to the
+-- patron who placed them. SQLite can't alter a CHECK constraint so the
+-- table is rebuilt, nothing references it so this is safe with foreign keys on.
+CREATE TABLE Reservations_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    copy_id INTEGER NOT NULL,
+    book_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    reservation_date DATE NOT NULL DEFAULT CURRENT_DATE,
+    status TEXT CHECK(status IN ('pending','active', 'expired', 'cancelled', 'declined', 'fulfilled')) DEFAULT 'pending',
+    updated_at TIMESTAMP,
+    FOREIGN KEY (book_id, copy_id) REFERENCES BookCopies(book_id, id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
+INSERT INTO Reservations_new SELECT * FROM Reservations;
+DROP TABLE Reservations;
+ALTER TABLE Reservations_new RENAME TO Reservations;
+
+CREATE TRIGGER update_reservations_timestamp
+AFTER UPDATE ON Reservations
+FOR EACH ROW
+BEGIN
+    UPDATE Reservations
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
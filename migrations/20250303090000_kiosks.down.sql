@@ -0,0 +1,6 @@
+ALTER TABLE Users DROP COLUMN pin;
+
+DROP INDEX IF EXISTS idx_kiosk_activity_kiosk;
+DROP TABLE IF EXISTS KioskActivity;
+DROP TRIGGER IF EXISTS update_kiosks_timestamp;
+DROP TABLE IF EXISTS Kiosks;
@@ -0,0 +1,36 @@
+-- Unattended self-checkout stations. Each one gets its own JWT, marking a
+-- kiosk inactive revokes its token.
+CREATE TABLE Kiosks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    location TEXT,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    updated_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TRIGGER update_kiosks_timestamp
+AFTER UPDATE ON Kiosks
+FOR EACH ROW
+BEGIN
+    UPDATE Kiosks
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
+
+-- Everything a kiosk was asked to do, including refused PINs
+CREATE TABLE KioskActivity (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kiosk_id INTEGER NOT NULL,
+    user_id INTEGER,
+    action TEXT NOT NULL,
+    barcode TEXT,
+    result TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (kiosk_id) REFERENCES Kiosks(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES Users(id) ON DELETE SET NULL
+);
+CREATE INDEX idx_kiosk_activity_kiosk ON KioskActivity(kiosk_id, created_at);
+
+-- Argon2 hash of the PIN patrons enter at a kiosk with their card
+ALTER TABLE Users ADD COLUMN pin TEXT;
@@ -0,0 +1,25 @@
+-- Columns used by foreign keys can't be dropped. Rebuilding the tables would
+-- cascade to the ones referencing BookCopies since migrations run inside a
+-- transaction with foreign keys on, so the references are taken out of the
+-- stored schema first.
+DROP TRIGGER IF EXISTS set_copy_branch_on_update;
+DROP TRIGGER IF EXISTS set_copy_branch_on_insert;
+DROP INDEX IF EXISTS idx_book_copies_branch;
+
+PRAGMA writable_schema = ON;
+UPDATE sqlite_schema
+SET sql = replace(
+    replace(sql, 'branch_id INTEGER REFERENCES Branches(id) ON DELETE SET NULL', 'branch_id INTEGER'),
+    'shelf_location_id INTEGER REFERENCES ShelfLocations(id) ON DELETE SET NULL', 'shelf_location_id INTEGER'
+)
+WHERE type = 'table' AND name IN ('BookCopies', 'Reservations');
+PRAGMA writable_schema = RESET;
+
+ALTER TABLE Reservations DROP COLUMN pickup_branch_id;
+ALTER TABLE BookCopies DROP COLUMN shelf_location_id;
+ALTER TABLE BookCopies DROP COLUMN branch_id;
+
+DROP TRIGGER IF EXISTS update_shelf_locations_timestamp;
+DROP TRIGGER IF EXISTS update_branches_timestamp;
+DROP TABLE IF EXISTS ShelfLocations;
+DROP TABLE IF EXISTS Branches;
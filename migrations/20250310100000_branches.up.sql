@@ -0,0 +1,66 @@
+-- Library branches and the shelves in them
+CREATE TABLE Branches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT UNIQUE NOT NULL,
+    code TEXT UNIQUE,
+    address TEXT,
+    updated_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE ShelfLocations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    branch_id INTEGER NOT NULL,
+    floor TEXT,
+    name TEXT NOT NULL,
+    updated_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (branch_id) REFERENCES Branches(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_shelf_locations_branch ON ShelfLocations(branch_id);
+
+CREATE TRIGGER update_branches_timestamp
+AFTER UPDATE ON Branches
+FOR EACH ROW
+BEGIN
+    UPDATE Branches
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
+CREATE TRIGGER update_shelf_locations_timestamp
+AFTER UPDATE ON ShelfLocations
+FOR EACH ROW
+BEGIN
+    UPDATE ShelfLocations
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
+
+-- The free text `location` stays for notes, copies now point at their
+-- branch and, once shelved, their shelf
+ALTER TABLE BookCopies ADD COLUMN branch_id INTEGER REFERENCES Branches(id) ON DELETE SET NULL;
+ALTER TABLE BookCopies ADD COLUMN shelf_location_id INTEGER REFERENCES ShelfLocations(id) ON DELETE SET NULL;
+CREATE INDEX idx_book_copies_branch ON BookCopies(branch_id);
+
+-- A shelf belongs to one branch, keep the copy's branch in line with it
+CREATE TRIGGER set_copy_branch_on_insert
+AFTER INSERT ON BookCopies
+FOR EACH ROW
+WHEN NEW.shelf_location_id IS NOT NULL
+BEGIN
+    UPDATE BookCopies
+    SET branch_id = (SELECT branch_id FROM ShelfLocations WHERE id = NEW.shelf_location_id)
+    WHERE rowid = NEW.rowid;
+END;
+CREATE TRIGGER set_copy_branch_on_update
+AFTER UPDATE OF shelf_location_id ON BookCopies
+FOR EACH ROW
+WHEN NEW.shelf_location_id IS NOT NULL
+BEGIN
+    UPDATE BookCopies
+    SET branch_id = (SELECT branch_id FROM ShelfLocations WHERE id = NEW.shelf_location_id)
+    WHERE rowid = NEW.rowid;
+END;
+
+-- Where the patron collects a hold
+ALTER TABLE Reservations ADD COLUMN pickup_branch_id INTEGER REFERENCES Branches(id) ON DELETE SET NULL;
@@ -0,0 +1,12 @@
+-- Copies still in transit are put back as available.
+DROP TRIGGER IF EXISTS update_transfers_timestamp;
+DROP INDEX IF EXISTS idx_transfers_copy;
+DROP TABLE IF EXISTS Transfers;
+
+UPDATE BookCopies SET status = 'available' WHERE status = 'in_transit';
+
+PRAGMA writable_schema = ON;
+UPDATE sqlite_schema
+SET sql = replace(sql, '''reserved'', ''in_transit'')', '''reserved'')')
+WHERE type = 'table' AND name = 'BookCopies';
+PRAGMA writable_schema = RESET;
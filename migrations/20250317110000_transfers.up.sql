@@ -0,0 +1,40 @@
+-- Copies get an 'in_transit' status while moving between branches. SQLite
+-- can't alter a CHECK constraint, and rebuilding BookCopies would cascade to
+-- the tables referencing it since migrations run inside a transaction with
+-- foreign keys on. Widening the constraint in the stored schema is safe as
+-- every existing row already satisfies it.
+PRAGMA writable_schema = ON;
+UPDATE sqlite_schema
+SET sql = replace(sql, '''reserved'')', '''reserved'', ''in_transit'')')
+WHERE type = 'table' AND name = 'BookCopies';
+PRAGMA writable_schema = RESET;
+
+-- A copy sent from one branch to another
+CREATE TABLE Transfers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    book_id INTEGER NOT NULL,
+    copy_id INTEGER NOT NULL,
+    from_branch_id INTEGER NOT NULL,
+    to_branch_id INTEGER NOT NULL,
+    status TEXT CHECK(status IN ('in_transit', 'received', 'cancelled')) DEFAULT 'in_transit',
+    sent_by INTEGER,
+    received_by INTEGER,
+    sent_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    received_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    FOREIGN KEY (book_id, copy_id) REFERENCES BookCopies(book_id, id) ON DELETE CASCADE,
+    FOREIGN KEY (from_branch_id) REFERENCES Branches(id) ON DELETE CASCADE,
+    FOREIGN KEY (to_branch_id) REFERENCES Branches(id) ON DELETE CASCADE,
+    FOREIGN KEY (sent_by) REFERENCES Users(id) ON DELETE SET NULL,
+    FOREIGN KEY (received_by) REFERENCES Users(id) ON DELETE SET NULL
+);
+CREATE INDEX idx_transfers_copy ON Transfers(book_id, copy_id);
+
+CREATE TRIGGER update_transfers_timestamp
+AFTER UPDATE ON Transfers
+FOR EACH ROW
+BEGIN
+    UPDATE Transfers
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
@@ -0,0 +1,25 @@
+-- Lost loans are put back on loan and copies in the removed states back on
+-- the shelf.
+ALTER TABLE Fines DROP COLUMN reason;
+ALTER TABLE Books DROP COLUMN replacement_cost;
+ALTER TABLE BookCopies DROP COLUMN status_reason;
+
+UPDATE BookCopies SET status = 'borrowed'
+WHERE status = 'lost' AND EXISTS (
+    SELECT 1 FROM Borrowing
+    WHERE Borrowing.book_id = BookCopies.book_id
+        AND Borrowing.copy_id = BookCopies.id
+        AND Borrowing.status = 'lost'
+);
+UPDATE BookCopies SET status = 'available'
+WHERE status IN ('lost', 'damaged', 'in_repair', 'missing', 'withdrawn');
+UPDATE Borrowing SET status = 'borrowed' WHERE status = 'lost';
+
+PRAGMA writable_schema = ON;
+UPDATE sqlite_schema
+SET sql = replace(sql, '''in_transit'', ''lost'', ''damaged'', ''in_repair'', ''missing'', ''withdrawn'')', '''in_transit'')')
+WHERE type = 'table' AND name = 'BookCopies';
+UPDATE sqlite_schema
+SET sql = replace(sql, '''late'', ''lost'')', '''late'')')
+WHERE type = 'table' AND name = 'Borrowing';
+PRAGMA writable_schema = RESET;
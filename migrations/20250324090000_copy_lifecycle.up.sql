@@ -0,0 +1,19 @@
+-- Copies that are off the shelf for good or for a while, and loans that
+-- ended with the item lost. The CHECK constraints are widened in place, see
+-- 20250317110000_transfers.
+PRAGMA writable_schema = ON;
+UPDATE sqlite_schema
+SET sql = replace(sql, '''in_transit'')', '''in_transit'', ''lost'', ''damaged'', ''in_repair'', ''missing'', ''withdrawn'')')
+WHERE type = 'table' AND name = 'BookCopies';
+UPDATE sqlite_schema
+SET sql = replace(sql, '''late'')', '''late'', ''lost'')')
+WHERE type = 'table' AND name = 'Borrowing';
+PRAGMA writable_schema = RESET;
+
+-- Why a copy is in its current status, e.g. "water damage"
+ALTER TABLE BookCopies ADD COLUMN status_reason TEXT;
+
+-- Billed to the borrower when a copy is declared lost
+ALTER TABLE Books ADD COLUMN replacement_cost REAL;
+
+ALTER TABLE Fines ADD COLUMN reason TEXT CHECK(reason IN ('overdue', 'replacement')) DEFAULT 'overdue';
@@ -0,0 +1,3 @@
+DROP TRIGGER IF EXISTS update_stocktakes_timestamp;
+DROP TABLE IF EXISTS StocktakeScans;
+DROP TABLE IF EXISTS Stocktakes;
@@ -0,0 +1,37 @@
+-- Shelf audits, copies scanned at a branch (or one of its shelves) are
+-- compared with the copies expected there
+CREATE TABLE Stocktakes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    branch_id INTEGER NOT NULL,
+    shelf_location_id INTEGER,
+    status TEXT CHECK(status IN ('open', 'closed')) DEFAULT 'open',
+    started_by INTEGER,
+    started_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    closed_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    FOREIGN KEY (branch_id) REFERENCES Branches(id) ON DELETE CASCADE,
+    FOREIGN KEY (shelf_location_id) REFERENCES ShelfLocations(id) ON DELETE CASCADE,
+    FOREIGN KEY (started_by) REFERENCES Users(id) ON DELETE SET NULL
+);
+
+-- Barcodes that match no copy are kept with an empty copy
+CREATE TABLE StocktakeScans (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    stocktake_id INTEGER NOT NULL,
+    barcode TEXT NOT NULL,
+    book_id INTEGER,
+    copy_id INTEGER,
+    scanned_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (stocktake_id, barcode),
+    FOREIGN KEY (stocktake_id) REFERENCES Stocktakes(id) ON DELETE CASCADE,
+    FOREIGN KEY (book_id, copy_id) REFERENCES BookCopies(book_id, id) ON DELETE SET NULL
+);
+
+CREATE TRIGGER update_stocktakes_timestamp
+AFTER UPDATE ON Stocktakes
+FOR EACH ROW
+BEGIN
+    UPDATE Stocktakes
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
@@ -0,0 +1,6 @@
+DROP INDEX IF EXISTS idx_books_call_number_sort;
+
+ALTER TABLE BookCopies DROP COLUMN call_number_sort;
+ALTER TABLE BookCopies DROP COLUMN call_number;
+ALTER TABLE Books DROP COLUMN call_number_sort;
+ALTER TABLE Books DROP COLUMN call_number;
@@ -0,0 +1,8 @@
+-- Call numbers, with a copy's own overriding its book's. The sort keys are
+-- computed by the application so rows sort in shelf order.
+ALTER TABLE Books ADD COLUMN call_number TEXT;
+ALTER TABLE Books ADD COLUMN call_number_sort TEXT;
+ALTER TABLE BookCopies ADD COLUMN call_number TEXT;
+ALTER TABLE BookCopies ADD COLUMN call_number_sort TEXT;
+
+CREATE INDEX idx_books_call_number_sort ON Books(call_number_sort);
@@ -0,0 +1,4 @@
+DROP TRIGGER IF EXISTS update_contributors_timestamp;
+DROP INDEX IF EXISTS idx_book_contributors_contributor;
+DROP TABLE IF EXISTS BookContributors;
+DROP TABLE IF EXISTS Contributors;
@@ -0,0 +1,53 @@
+-- People credited on a book. `Books.author` stays as the display string.
+CREATE TABLE Contributors (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    updated_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE BookContributors (
+    book_id INTEGER NOT NULL,
+    contributor_id INTEGER NOT NULL,
+    role TEXT CHECK(role IN ('author', 'editor', 'translator', 'illustrator')) DEFAULT 'author',
+    -- Order the contributors are credited in
+    position INTEGER NOT NULL DEFAULT 1,
+    PRIMARY KEY (book_id, contributor_id, role),
+    FOREIGN KEY (book_id) REFERENCES Books(id) ON DELETE CASCADE,
+    FOREIGN KEY (contributor_id) REFERENCES Contributors(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_book_contributors_contributor ON BookContributors(contributor_id);
+
+CREATE TRIGGER update_contributors_timestamp
+AFTER UPDATE ON Contributors
+FOR EACH ROW
+BEGIN
+    UPDATE Contributors
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
+
+-- Existing authors, "A; B", "A & B" and "A and B" name several
+CREATE TEMP TABLE AuthorNames AS
+WITH RECURSIVE split(book_id, position, name, rest) AS (
+    SELECT id, 0, '', replace(replace(author, ' & ', ';'), ' and ', ';') || ';'
+    FROM Books
+    UNION ALL
+    SELECT book_id,
+        position + 1,
+        trim(substr(rest, 1, instr(rest, ';') - 1)),
+        substr(rest, instr(rest, ';') + 1)
+    FROM split
+    WHERE rest <> ''
+)
+SELECT book_id, position, name FROM split WHERE name <> '';
+
+INSERT OR IGNORE INTO Contributors (name)
+SELECT name FROM AuthorNames ORDER BY book_id, position;
+
+INSERT OR IGNORE INTO BookContributors (book_id, contributor_id, role, position)
+SELECT AuthorNames.book_id, Contributors.id, 'author', AuthorNames.position
+FROM AuthorNames
+INNER JOIN Contributors ON Contributors.name = AuthorNames.name;
+
+DROP TABLE AuthorNames;
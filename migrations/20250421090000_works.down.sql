@@ -0,0 +1,15 @@
+-- See 20250310100000_branches.down.sql for why the reference is taken out of
+-- the stored schema instead of rebuilding Books.
+DROP TRIGGER IF EXISTS update_works_timestamp;
+DROP INDEX IF EXISTS idx_books_work;
+
+PRAGMA writable_schema = ON;
+UPDATE sqlite_schema
+SET sql = replace(sql, 'work_id INTEGER REFERENCES Works(id) ON DELETE SET NULL', 'work_id INTEGER')
+WHERE type = 'table' AND name = 'Books';
+PRAGMA writable_schema = RESET;
+
+ALTER TABLE Reservations DROP COLUMN any_edition;
+ALTER TABLE Books DROP COLUMN work_id;
+
+DROP TABLE IF EXISTS Works;
@@ -0,0 +1,22 @@
+-- Works group the editions of a title. Books outside a work stand alone.
+CREATE TABLE Works (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    updated_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE Books ADD COLUMN work_id INTEGER REFERENCES Works(id) ON DELETE SET NULL;
+CREATE INDEX idx_books_work ON Books(work_id);
+
+-- Holds that any edition in the work can satisfy
+ALTER TABLE Reservations ADD COLUMN any_edition BOOLEAN NOT NULL DEFAULT 0;
+
+CREATE TRIGGER update_works_timestamp
+AFTER UPDATE ON Works
+FOR EACH ROW
+BEGIN
+    UPDATE Works
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
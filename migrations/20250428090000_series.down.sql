@@ -0,0 +1,5 @@
+DROP TRIGGER IF EXISTS update_series_timestamp;
+DROP INDEX IF EXISTS idx_series_books_volume;
+DROP INDEX IF EXISTS idx_series_books_book;
+DROP TABLE IF EXISTS SeriesBooks;
+DROP TABLE IF EXISTS Series;
@@ -0,0 +1,29 @@
+CREATE TABLE Series (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    description TEXT,
+    updated_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Books in a series, in volume order. Volumes may be fractional (2.5 for a
+-- novella set between the second and third books).
+CREATE TABLE SeriesBooks (
+    series_id INTEGER NOT NULL,
+    book_id INTEGER NOT NULL,
+    volume REAL NOT NULL,
+    PRIMARY KEY (series_id, book_id),
+    FOREIGN KEY (series_id) REFERENCES Series(id) ON DELETE CASCADE,
+    FOREIGN KEY (book_id) REFERENCES Books(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_series_books_book ON SeriesBooks(book_id);
+CREATE INDEX idx_series_books_volume ON SeriesBooks(series_id, volume);
+
+CREATE TRIGGER update_series_timestamp
+AFTER UPDATE ON Series
+FOR EACH ROW
+BEGIN
+    UPDATE Series
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
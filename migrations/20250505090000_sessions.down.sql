@@ -0,0 +1,4 @@
+DROP TRIGGER IF EXISTS update_sessions_timestamp;
+DROP TABLE IF EXISTS RevokedTokens;
+DROP INDEX IF EXISTS idx_sessions_user;
+DROP TABLE IF EXISTS Sessions;
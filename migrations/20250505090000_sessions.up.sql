@@ -0,0 +1,30 @@
+-- Logins, each renewed with a refresh token. Refresh tokens read
+-- "<session id>.<secret>", only a hash of the current secret is kept.
+CREATE TABLE Sessions (
+    id TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    refresh_hash TEXT NOT NULL,
+    -- Last access token handed out, revoked with the session
+    access_jti TEXT,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    updated_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_sessions_user ON Sessions(user_id);
+
+-- Access tokens refused before they expire
+CREATE TABLE RevokedTokens (
+    jti TEXT PRIMARY KEY,
+    expires_at TIMESTAMP NOT NULL
+);
+
+CREATE TRIGGER update_sessions_timestamp
+AFTER UPDATE ON Sessions
+FOR EACH ROW
+BEGIN
+    UPDATE Sessions
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
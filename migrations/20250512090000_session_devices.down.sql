@@ -0,0 +1,3 @@
+ALTER TABLE Sessions DROP COLUMN last_seen_at;
+ALTER TABLE Sessions DROP COLUMN ip;
+ALTER TABLE Sessions DROP COLUMN user_agent;
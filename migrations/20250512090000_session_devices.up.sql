@@ -0,0 +1,5 @@
+-- Where each login is from, shown to members managing their sessions
+ALTER TABLE Sessions ADD COLUMN user_agent TEXT;
+ALTER TABLE Sessions ADD COLUMN ip TEXT;
+-- Refreshed along with the tokens
+ALTER TABLE Sessions ADD COLUMN last_seen_at TIMESTAMP;
@@ -0,0 +1,13 @@
+-- Reminders sent about a loan, so each stage is only mailed once per due date
+CREATE TABLE Notices (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    borrowing_id INTEGER NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('due_soon', 'due_today', 'overdue')),
+    days INTEGER NOT NULL DEFAULT 0,
+    due_date DATE NOT NULL,
+    outbox_id INTEGER,
+    sent_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (borrowing_id, due_date, kind, days),
+    FOREIGN KEY (borrowing_id) REFERENCES Borrowing(id) ON DELETE CASCADE,
+    FOREIGN KEY (outbox_id) REFERENCES Outbox(id) ON DELETE SET NULL
+);
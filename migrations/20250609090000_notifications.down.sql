@@ -0,0 +1,4 @@
+ALTER TABLE Reservations DROP COLUMN ready_date;
+ALTER TABLE Users DROP COLUMN email_notifications;
+DROP INDEX IF EXISTS idx_notifications_user;
+DROP TABLE IF EXISTS Notifications;
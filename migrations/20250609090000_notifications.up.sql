@@ -0,0 +1,19 @@
+-- In-app messages about a member's holds, loans and fines
+CREATE TABLE Notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('hold_ready', 'hold_expiring', 'loan_overdue', 'fine_issued', 'reservation_declined')),
+    -- reservation, loan or fine the notification is about
+    entity_id INTEGER,
+    message TEXT NOT NULL,
+    read_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_notifications_user ON Notifications(user_id, read_at);
+
+-- Members choose whether notifications are emailed to them as well
+ALTER TABLE Users ADD COLUMN email_notifications BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Day a hold was put aside for collection
+ALTER TABLE Reservations ADD COLUMN ready_date DATE;
@@ -0,0 +1,2 @@
+ALTER TABLE Users DROP COLUMN verification_sent_at;
+ALTER TABLE Users DROP COLUMN email_verified_at;
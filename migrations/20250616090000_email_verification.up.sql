@@ -0,0 +1,6 @@
+-- Accounts need a confirmed address before borrowing
+ALTER TABLE Users ADD COLUMN email_verified_at TIMESTAMP;
+ALTER TABLE Users ADD COLUMN verification_sent_at TIMESTAMP;
+
+-- accounts from before verification existed are trusted
+UPDATE Users SET email_verified_at = CURRENT_TIMESTAMP;
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_recovery_codes_user;
+DROP TABLE IF EXISTS RecoveryCodes;
+DROP TABLE IF EXISTS TwoFactors;
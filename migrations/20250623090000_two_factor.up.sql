@@ -0,0 +1,20 @@
+-- TOTP second factor, enabled once the first code from the app checks out
+CREATE TABLE TwoFactors (
+    user_id INTEGER PRIMARY KEY,
+    secret TEXT NOT NULL,
+    enabled_at TIMESTAMP,
+    -- time step of the last code accepted, so it can't be replayed
+    last_step INTEGER,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
+
+-- Single use codes for a lost authenticator, only their hash is kept
+CREATE TABLE RecoveryCodes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    code_hash TEXT NOT NULL,
+    used_at TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES Users(id) ON DELETE CASCADE
+);
+CREATE INDEX idx_recovery_codes_user ON RecoveryCodes(user_id);
@@ -0,0 +1,10 @@
+-- Failed logins by username and by client address, for backoff and lockout
+CREATE TABLE LoginThrottles (
+    scope TEXT NOT NULL CHECK (scope IN ('username', 'ip')),
+    key TEXT NOT NULL,
+    failures INTEGER NOT NULL,
+    last_failed_at TIMESTAMP NOT NULL,
+    blocked_until TIMESTAMP,
+    locked_until TIMESTAMP,
+    PRIMARY KEY (scope, key)
+);
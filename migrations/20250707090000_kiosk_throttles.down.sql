@@ -0,0 +1,13 @@
+CREATE TABLE LoginThrottles_new (
+    scope TEXT NOT NULL CHECK (scope IN ('username', 'ip')),
+    key TEXT NOT NULL,
+    failures INTEGER NOT NULL,
+    last_failed_at TIMESTAMP NOT NULL,
+    blocked_until TIMESTAMP,
+    locked_until TIMESTAMP,
+    PRIMARY KEY (scope, key)
+);
+INSERT INTO LoginThrottles_new
+SELECT * FROM LoginThrottles WHERE scope IN ('username', 'ip');
+DROP TABLE LoginThrottles;
+ALTER TABLE LoginThrottles_new RENAME TO LoginThrottles;
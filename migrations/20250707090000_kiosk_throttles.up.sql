@@ -0,0 +1,13 @@
+-- Kiosk sign ins are throttled by card number and by kiosk as well
+CREATE TABLE LoginThrottles_new (
+    scope TEXT NOT NULL CHECK (scope IN ('username', 'ip', 'card', 'kiosk')),
+    key TEXT NOT NULL,
+    failures INTEGER NOT NULL,
+    last_failed_at TIMESTAMP NOT NULL,
+    blocked_until TIMESTAMP,
+    locked_until TIMESTAMP,
+    PRIMARY KEY (scope, key)
+);
+INSERT INTO LoginThrottles_new SELECT * FROM LoginThrottles;
+DROP TABLE LoginThrottles;
+ALTER TABLE LoginThrottles_new RENAME TO LoginThrottles;
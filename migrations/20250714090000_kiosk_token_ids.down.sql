@@ -0,0 +1 @@
+ALTER TABLE Kiosks DROP COLUMN token_id;
@@ -0,0 +1,2 @@
+-- Id of the one kiosk token still valid, replaced when a token is reissued
+ALTER TABLE Kiosks ADD COLUMN token_id TEXT;
@@ -0,0 +1,10 @@
+-- Whether staff have to sign in with a second factor, set by admins. There
+-- is only ever the one row.
+CREATE TABLE TwoFactorPolicy (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    require_staff BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_by INTEGER,
+    updated_at TIMESTAMP,
+    FOREIGN KEY (updated_by) REFERENCES Users(id) ON DELETE SET NULL
+);
+INSERT INTO TwoFactorPolicy (id) VALUES (1);
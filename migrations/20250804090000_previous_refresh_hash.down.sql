@@ -0,0 +1 @@
+ALTER TABLE Sessions DROP COLUMN previous_refresh_hash;
@@ -0,0 +1,2 @@
+-- The refresh token swapped out last, using it again means it was stolen
+ALTER TABLE Sessions ADD COLUMN previous_refresh_hash TEXT;
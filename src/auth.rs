@@ -1,3 +1,6 @@
+use std::sync::LazyLock;
+
+use argon2::password_hash::rand_core::RngCore;
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
@@ -12,15 +15,20 @@ use axum_extra::{
     headers::{authorization::Bearer, Authorization},
     TypedHeader,
 };
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use sha2::{Digest, Sha256};
 use tower_cookies::Cookies;
+use tracing::error;
+use uuid::Uuid;
 
 use crate::{
     error::{Error, Result},
     extractors::json::Json,
-    model::user::UserRole,
+    jwt::JwtKeys,
+    model::{session::Session, user::UserRole, Engine},
+    state::AppState,
 };
 
 #[derive(Serialize, Deserialize)]
@@ -29,6 +37,21 @@ pub struct Claims {
     pub user_id: i64,
     pub role: UserRole,
     pub exp: usize,
+    /// Token id, checked against the revocation list
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    /// Login session the token was issued for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+}
+
+/// Handed out at login and on refresh.
+#[derive(Serialize)]
+pub struct TokenPair {
+    pub token: String,
+    pub refresh_token: String,
+    /// Seconds until `token` expires
+    pub expires_in: i64,
 }
 
 impl<S> FromRequestParts<S> for Claims
@@ -55,14 +78,30 @@ where
                     .ok_or(AuthError::MissingCredentials)?
             }
         };
-        let jwt_secret = std::env::var("JWT_SECRET").unwrap_or("secret".to_string());
+        // the routes' state is generic here, it comes in as an extension
+        let state = parts
+            .extensions
+            .get::<AppState<Engine>>()
+            .ok_or(AuthError::InvalidToken)?;
         // Decode the user data
         use jsonwebtoken::errors::ErrorKind as E;
-        let claims = decode_jwt(&token, &jwt_secret).map_err(|e| match e.kind() {
+        let claims = decode_jwt(&token, &state.keys).map_err(|e| match e.kind() {
             E::ExpiredSignature => AuthError::ExpiredToken,
             _ => AuthError::InvalidToken,
         })?;
 
+        // session tokens can be revoked before they expire
+        if let (Some(jti), Some(sid)) = (&claims.jti, &claims.sid) {
+            match Session::is_revoked(state, jti, sid).await {
+                Ok(false) => {}
+                Ok(true) => return Err(AuthError::RevokedToken),
+                Err(e) => {
+                    error!("{e}");
+                    return Err(AuthError::InvalidToken);
+                }
+            }
+        }
+
         Ok(claims)
     }
 }
@@ -72,6 +111,7 @@ pub enum AuthError {
     MissingCredentials,
     InvalidToken,
     ExpiredToken,
+    RevokedToken,
 }
 
 impl IntoResponse for AuthError {
@@ -80,6 +120,10 @@ impl IntoResponse for AuthError {
             AuthError::MissingCredentials => (StatusCode::FORBIDDEN, "Missing credentials"),
             AuthError::InvalidToken => (StatusCode::FORBIDDEN, "Invalid token"),
             AuthError::ExpiredToken => (StatusCode::FORBIDDEN, "Expired token. Please login again"),
+            AuthError::RevokedToken => (
+                StatusCode::FORBIDDEN,
+                "Session has ended. Please login again",
+            ),
         };
         let body = Json(json!({
             "error": error_message,
@@ -88,25 +132,117 @@ impl IntoResponse for AuthError {
     }
 }
 
-pub fn generate_jwt(claims: &Claims, jwt_secret: &str) -> Result<String> {
-    let token = encode(
-        &Header::default(),
-        claims,
-        &EncodingKey::from_secret(jwt_secret.as_bytes()),
-    )?;
+pub fn generate_jwt(claims: &Claims, keys: &JwtKeys) -> Result<String> {
+    let token = keys.encode(claims)?;
     Ok(token)
 }
 
 pub fn decode_jwt(
     token: &str,
-    jwt_secret: &str,
+    keys: &JwtKeys,
 ) -> core::result::Result<Claims, jsonwebtoken::errors::Error> {
-    let data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &Validation::default(),
+    keys.decode(token)
+}
+
+/// Proves whoever holds it reads the mail sent to `email`. Changing the
+/// address makes earlier tokens useless.
+#[derive(Serialize, Deserialize)]
+struct EmailClaims {
+    sub: i64,
+    email: String,
+    /// Keeps these apart from tokens signed for anything else
+    purpose: String,
+    exp: usize,
+}
+
+const VERIFY_EMAIL: &str = "verify_email";
+
+/// Signed token for the link in a verification mail.
+pub fn email_token(state: &AppState<Engine>, user_id: i64, email: &str) -> Result<String> {
+    let expires_in = state.config.auth.verification_hours * 3600;
+    let token = state.keys.encode(&EmailClaims {
+        sub: user_id,
+        email: email.to_string(),
+        purpose: VERIFY_EMAIL.to_string(),
+        exp: (Utc::now().timestamp() + expires_in) as usize,
+    })?;
+    Ok(token)
+}
+
+/// User id and address of a verification token, `None` when it's expired
+/// or not one.
+pub fn decode_email_token(state: &AppState<Engine>, token: &str) -> Option<(i64, String)> {
+    let claims: EmailClaims = state.keys.decode(token).ok()?;
+    (claims.purpose == VERIFY_EMAIL).then_some((claims.sub, claims.email))
+}
+
+/// Stands in for the password between the two steps of a login with a
+/// second factor.
+#[derive(Serialize, Deserialize)]
+struct ChallengeClaims {
+    sub: i64,
+    purpose: String,
+    exp: usize,
+}
+
+const TWO_FACTOR: &str = "two_factor";
+
+/// Token handed out when the password checks out but a second factor is
+/// still to come.
+pub fn two_factor_challenge(state: &AppState<Engine>, user_id: i64) -> Result<String> {
+    let expires_in = state.config.auth.two_factor_minutes * 60;
+    let token = state.keys.encode(&ChallengeClaims {
+        sub: user_id,
+        purpose: TWO_FACTOR.to_string(),
+        exp: (Utc::now().timestamp() + expires_in) as usize,
+    })?;
+    Ok(token)
+}
+
+/// User id of a login challenge, `None` when it's expired or not one.
+pub fn decode_two_factor_challenge(state: &AppState<Engine>, token: &str) -> Option<i64> {
+    let claims: ChallengeClaims = state.keys.decode(token).ok()?;
+    (claims.purpose == TWO_FACTOR).then_some(claims.sub)
+}
+
+/// Short lived access token for a session, the session remembers it so
+/// logging out revokes it.
+pub async fn access_token(
+    state: &AppState<Engine>,
+    user_id: i64,
+    role: UserRole,
+    session_id: &str,
+) -> Result<(String, i64)> {
+    let expires_in = state.config.auth.access_token_minutes * 60;
+    let jti = Uuid::new_v4().to_string();
+    let token = generate_jwt(
+        &Claims {
+            user_id,
+            role,
+            exp: (Utc::now().timestamp() + expires_in) as usize,
+            jti: Some(jti.clone()),
+            sid: Some(session_id.to_string()),
+        },
+        &state.keys,
     )?;
-    Ok(data.claims)
+    Session::set_access(state, session_id, &jti).await?;
+    Ok((token, expires_in))
+}
+
+/// Random secret for refresh and other one-time tokens, hex encoded.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex(&bytes)
+}
+
+/// What gets stored of a token, so a leaked table can't be replayed.
+pub fn token_hash(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
 }
 
 pub fn hash(password: &str) -> Result<String> {
@@ -118,6 +254,14 @@ pub fn hash(password: &str) -> Result<String> {
     Ok(hashed.to_string())
 }
 
+/// Checked against when there's no such user, so the answer takes as long
+/// as for a wrong password and doesn't tell usernames apart.
+pub fn dummy_hash() -> &'static str {
+    static DUMMY_HASH: LazyLock<String> =
+        LazyLock::new(|| hash(&random_token()).expect("hashing a random password"));
+    &DUMMY_HASH
+}
+
 pub fn verify(password_hash: &str, password: &str) -> Result<()> {
     let parsed_hash = PasswordHash::new(password_hash).map_err(|e| Error::Argon2(e.to_string()))?;
     Argon2::default()
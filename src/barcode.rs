@@ -0,0 +1,144 @@
+//! Barcodes for book copies and library cards.
+//!
+//! Numbers follow the usual library layout: a configurable prefix, a zero
+//! padded serial and a trailing Luhn (mod 10) check digit, so scanners and
+//! staff can catch mistyped codes.
+
+use barcoders::sym::code128::Code128;
+
+/// Height of the bars in an SVG, in modules.
+const BAR_HEIGHT: usize = 50;
+/// Blank space required on both sides of a Code128 symbol, in modules.
+const QUIET_ZONE: usize = 10;
+/// Space below the bars for the human readable text, in modules.
+const TEXT_HEIGHT: usize = 14;
+
+/// Luhn check digit for the digits in `payload`, other characters are ignored.
+pub fn check_digit(payload: &str) -> u32 {
+    let sum: u32 = payload
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, d)| match i % 2 {
+            0 => match d * 2 {
+                d if d > 9 => d - 9,
+                d => d,
+            },
+            _ => d,
+        })
+        .sum();
+    (10 - sum % 10) % 10
+}
+
+/// Build a code of `length` characters from `prefix` and `serial`.
+///
+/// The serial is zero padded to fill the space between the prefix and the
+/// check digit, it is never truncated.
+pub fn generate(prefix: &str, serial: i64, length: usize) -> String {
+    let width = length.saturating_sub(prefix.len() + 1);
+    let payload = format!("{prefix}{serial:0width$}");
+    let check = check_digit(&payload);
+    format!("{payload}{check}")
+}
+
+/// Whether the last digit of `code` is a valid check digit for the rest.
+pub fn is_valid(code: &str) -> bool {
+    match code.char_indices().last() {
+        Some((i, c)) => c.to_digit(10) == Some(check_digit(&code[..i])),
+        None => false,
+    }
+}
+
+/// Modules (1 = bar, 0 = space) of `code` encoded as Code128.
+pub fn code128(code: &str) -> barcoders::error::Result<Vec<u8>> {
+    // Character set C packs digit pairs, use it when the code allows.
+    let set = if code.len().is_multiple_of(2) && code.chars().all(|c| c.is_ascii_digit()) {
+        'Ć'
+    } else {
+        'Ɓ'
+    };
+    Ok(Code128::new(format!("{set}{code}"))?.encode())
+}
+
+/// Render `code` as a Code128 SVG with the text printed underneath.
+pub fn code128_svg(code: &str) -> barcoders::error::Result<String> {
+    let modules = code128(code)?;
+    let width = modules.len() + QUIET_ZONE * 2;
+    let height = BAR_HEIGHT + TEXT_HEIGHT;
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {width} {height}" width="{w}" height="{h}">"#,
+        w = width * 2,
+        h = height * 2,
+    );
+    svg.push_str(&format!(
+        r#"<rect width="{width}" height="{height}" fill="white"/>"#
+    ));
+    for (x, w) in bars(&modules) {
+        svg.push_str(&format!(
+            r#"<rect x="{}" y="0" width="{w}" height="{BAR_HEIGHT}" fill="black"/>"#,
+            x + QUIET_ZONE
+        ));
+    }
+    svg.push_str(&format!(
+        r#"<text x="{}" y="{}" font-family="monospace" font-size="10" text-anchor="middle">{}</text>"#,
+        width / 2,
+        height - 2,
+        escape(code)
+    ));
+    svg.push_str("</svg>");
+
+    Ok(svg)
+}
+
+/// Start offset and width of each run of bars in `modules`.
+pub fn bars(modules: &[u8]) -> Vec<(usize, usize)> {
+    let mut bars: Vec<(usize, usize)> = vec![];
+    for (i, m) in modules.iter().enumerate() {
+        if *m == 1 {
+            match bars.last_mut() {
+                Some((x, w)) if *x + *w == i => *w += 1,
+                _ => bars.push((i, 1)),
+            }
+        }
+    }
+    bars
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn luhn_check_digit() {
+        // Well known Luhn example
+        assert_eq!(check_digit("7992739871"), 3);
+        assert!(is_valid("79927398713"));
+        assert!(!is_valid("79927398710"));
+    }
+
+    #[test]
+    fn generating_codes() {
+        let code = generate("3", 42, 14);
+
+        assert_eq!(code.len(), 14);
+        assert!(code.starts_with("3000000000042"));
+        assert!(is_valid(&code));
+    }
+
+    #[test]
+    fn rendering_svg() {
+        let svg = code128_svg("30000000000420").unwrap();
+
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("30000000000420"));
+        assert!(code128_svg("é").is_err());
+    }
+}
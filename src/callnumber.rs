@@ -0,0 +1,235 @@
+//! Call numbers and the order books sit on the shelf in.
+//!
+//! Dewey Decimal (`823.914 ROW`) and Library of Congress (`QA76.73 .R87
+//! 2018`) call numbers don't sort as text: `QA9` is shelved before `QA76`
+//! and `823.9` after `823.12`. The sort keys built here do, so they can be
+//! stored and indexed next to the call number.
+
+/// Width class numbers are padded to in sort keys.
+const DEWEY_CLASS_WIDTH: usize = 3;
+const LC_CLASS_WIDTH: usize = 4;
+/// LC classes are one to three letters.
+const LC_LETTERS: usize = 3;
+
+/// A Dewey Decimal call number, e.g. `823.914 ROW`.
+#[derive(Debug, PartialEq)]
+pub struct Dewey {
+    pub class: u32,
+    /// Digits after the point, without trailing zeros
+    pub decimal: String,
+    /// Cutter, year, volume...
+    pub rest: Vec<String>,
+}
+
+impl Dewey {
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim().to_uppercase();
+        let (number, rest) = s.split_once(char::is_whitespace).unwrap_or((&s, ""));
+        let (class, decimal) = number.split_once('.').unwrap_or((number, ""));
+        if class.is_empty() || class.len() > DEWEY_CLASS_WIDTH || !is_digits(class) {
+            return None;
+        }
+        if !is_digits(decimal) {
+            return None;
+        }
+
+        Some(Self {
+            class: class.parse().ok()?,
+            decimal: decimal.trim_end_matches('0').to_string(),
+            rest: rest.split_whitespace().map(str::to_string).collect(),
+        })
+    }
+
+    pub fn sort_key(&self) -> String {
+        // the decimal is compared digit by digit, so it follows the class
+        // without a separator: 823 < 823.12 < 823.9
+        let mut key = format!(
+            "{:0width$}{}",
+            self.class,
+            self.decimal,
+            width = DEWEY_CLASS_WIDTH
+        );
+        for part in &self.rest {
+            key.push(' ');
+            key.push_str(part);
+        }
+        key
+    }
+}
+
+/// A Library of Congress call number, e.g. `QA76.73.R87 K53 2018`.
+#[derive(Debug, PartialEq)]
+pub struct Lc {
+    /// One to three letters
+    pub letters: String,
+    pub class: u32,
+    /// Digits after the point, without trailing zeros
+    pub decimal: String,
+    /// Cutter numbers, a letter followed by digits read as a decimal
+    pub cutters: Vec<String>,
+    /// Year, volume...
+    pub rest: Vec<String>,
+}
+
+impl Lc {
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim().to_uppercase();
+
+        let letters: String = s.chars().take_while(char::is_ascii_alphabetic).collect();
+        if letters.is_empty() || letters.len() > LC_LETTERS {
+            return None;
+        }
+        let s = s[letters.len()..].trim_start();
+
+        let class: String = s.chars().take_while(char::is_ascii_digit).collect();
+        if class.is_empty() || class.len() > LC_CLASS_WIDTH {
+            return None;
+        }
+        let mut s = &s[class.len()..];
+
+        // a point followed by digits continues the class, by a letter it
+        // starts the first cutter
+        let mut decimal = "";
+        if let Some(after) = s.strip_prefix('.') {
+            let digits = after.len() - after.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            if digits > 0 {
+                decimal = &after[..digits];
+                s = &after[digits..];
+            }
+        }
+
+        let mut cutters = vec![];
+        let mut rest = vec![];
+        for part in s.split(|c: char| c.is_whitespace() || c == '.') {
+            if part.is_empty() {
+                continue;
+            }
+            if rest.is_empty() && is_cutter(part) {
+                cutters.push(part.to_string());
+            } else {
+                rest.push(part.to_string());
+            }
+        }
+
+        Some(Self {
+            letters,
+            class: class.parse().ok()?,
+            decimal: decimal.trim_end_matches('0').to_string(),
+            cutters,
+            rest,
+        })
+    }
+
+    pub fn sort_key(&self) -> String {
+        // letters are padded with spaces so `Q` goes before `QA`
+        let mut key = format!(
+            "{:<letters$}{:0width$}{}",
+            self.letters,
+            self.class,
+            self.decimal,
+            letters = LC_LETTERS,
+            width = LC_CLASS_WIDTH
+        );
+        for part in self.cutters.iter().chain(&self.rest) {
+            key.push(' ');
+            key.push_str(part);
+        }
+        key
+    }
+}
+
+/// Key putting `call_number` in shelf order when sorted as text.
+///
+/// Dewey and LC numbers are recognised, anything else is compared as is
+/// (ignoring case and extra spaces).
+pub fn sort_key(call_number: &str) -> String {
+    if let Some(dewey) = Dewey::parse(call_number) {
+        return dewey.sort_key();
+    }
+    if let Some(lc) = Lc::parse(call_number) {
+        return lc.sort_key();
+    }
+    call_number
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_uppercase()
+}
+
+fn is_digits(s: &str) -> bool {
+    s.chars().all(|c| c.is_ascii_digit())
+}
+
+fn is_cutter(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.next().is_some_and(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn shelf_order(call_numbers: &[&str]) -> Vec<String> {
+        let mut sorted: Vec<_> = call_numbers.iter().map(|c| c.to_string()).collect();
+        sorted.sort_by_key(|c| sort_key(c));
+        sorted
+    }
+
+    #[test]
+    fn parsing_dewey() {
+        let dewey = Dewey::parse("823.9140 row").unwrap();
+        assert_eq!(dewey.class, 823);
+        assert_eq!(dewey.decimal, "914");
+        assert_eq!(dewey.rest, ["ROW"]);
+
+        assert!(Dewey::parse("5").is_some());
+        assert!(Dewey::parse("8234.1").is_none());
+        assert!(Dewey::parse("QA76").is_none());
+    }
+
+    #[test]
+    fn parsing_lc() {
+        let lc = Lc::parse("QA76.73.R87 K53 2018").unwrap();
+        assert_eq!(lc.letters, "QA");
+        assert_eq!((lc.class, lc.decimal.as_str()), (76, "73"));
+        assert_eq!(lc.cutters, ["R87", "K53"]);
+        assert_eq!(lc.rest, ["2018"]);
+
+        let lc = Lc::parse("PS 3545 .I345").unwrap();
+        assert_eq!((lc.class, lc.decimal.as_str()), (3545, ""));
+        assert_eq!(lc.cutters, ["I345"]);
+
+        assert!(Lc::parse("QA").is_none());
+        assert!(Lc::parse("QABC76").is_none());
+    }
+
+    #[test]
+    fn sorting_in_shelf_order() {
+        assert_eq!(
+            shelf_order(&["823.9 ADA", "823.12 BRO", "5 SMI", "823 AUS", "823.914 ROW"]),
+            ["5 SMI", "823 AUS", "823.12 BRO", "823.9 ADA", "823.914 ROW"]
+        );
+        assert_eq!(
+            shelf_order(&[
+                "QA76.73.R87",
+                "QA9 .B5",
+                "Q180 .A1",
+                "QA76.8 .A2",
+                "QA76 .Z9"
+            ]),
+            [
+                "Q180 .A1",
+                "QA9 .B5",
+                "QA76 .Z9",
+                "QA76.73.R87",
+                "QA76.8 .A2"
+            ]
+        );
+        // cutters are decimals
+        assert_eq!(
+            shelf_order(&["PS3545 .I9", "PS3545 .I345"]),
+            ["PS3545 .I345", "PS3545 .I9"]
+        );
+    }
+}
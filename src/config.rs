@@ -0,0 +1,413 @@
+use std::{env, path::PathBuf};
+
+/// Application settings, read from the environment on startup.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Where the app is reached, for links sent out by email
+    pub app_url: String,
+    pub media: MediaConfig,
+    pub barcode: BarcodeConfig,
+    pub circulation: CirculationConfig,
+    pub auth: AuthConfig,
+    pub mail: MailConfig,
+    pub notices: NoticeConfig,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Self {
+            app_url: env::var("APP_URL").unwrap_or("http://localhost:3000".to_string()),
+            media: MediaConfig::from_env(),
+            barcode: BarcodeConfig::from_env(),
+            circulation: CirculationConfig::from_env(),
+            auth: AuthConfig::from_env(),
+            mail: MailConfig::from_env(),
+            notices: NoticeConfig::from_env(),
+        }
+    }
+}
+
+/// Layout of generated copy barcodes and library card numbers.
+#[derive(Debug, Clone)]
+pub struct BarcodeConfig {
+    pub copy_prefix: String,
+    pub card_prefix: String,
+    /// Total length including prefix and check digit.
+    pub length: usize,
+}
+
+impl Default for BarcodeConfig {
+    fn default() -> Self {
+        // Codabar style: 3 for items, 2 for patrons, 14 digits long
+        Self {
+            copy_prefix: "3".to_string(),
+            card_prefix: "2".to_string(),
+            length: 14,
+        }
+    }
+}
+
+impl BarcodeConfig {
+    fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            copy_prefix: env::var("BARCODE_COPY_PREFIX").unwrap_or(default.copy_prefix),
+            card_prefix: env::var("BARCODE_CARD_PREFIX").unwrap_or(default.card_prefix),
+            length: parse_var("BARCODE_LENGTH").unwrap_or(default.length),
+        }
+    }
+}
+
+/// Where uploaded media (book covers, avatars) is kept.
+#[derive(Debug, Clone)]
+pub enum MediaConfig {
+    /// Files under a directory on the local filesystem.
+    Local { root: PathBuf },
+    /// Objects in an S3 compatible bucket (AWS, MinIO, Garage...).
+    S3 {
+        endpoint: String,
+        bucket: String,
+        region: String,
+        access_key: String,
+        secret_key: String,
+        /// Base URL the bucket is publicly reachable at. When unset media is
+        /// proxied through `/api/media`.
+        public_url: Option<String>,
+    },
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        MediaConfig::Local {
+            root: PathBuf::from("media"),
+        }
+    }
+}
+
+impl MediaConfig {
+    fn from_env() -> Self {
+        match env::var("MEDIA_STORE").as_deref() {
+            Ok("s3") => MediaConfig::S3 {
+                endpoint: env::var("S3_ENDPOINT").unwrap_or("http://localhost:9000".to_string()),
+                bucket: env::var("S3_BUCKET").unwrap_or("maktaba".to_string()),
+                region: env::var("S3_REGION").unwrap_or("us-east-1".to_string()),
+                access_key: env::var("S3_ACCESS_KEY").unwrap_or_default(),
+                secret_key: env::var("S3_SECRET_KEY").unwrap_or_default(),
+                public_url: env::var("S3_PUBLIC_URL").ok(),
+            },
+            _ => MediaConfig::Local {
+                root: env::var("MEDIA_ROOT")
+                    .map(PathBuf::from)
+                    .unwrap_or(PathBuf::from("media")),
+            },
+        }
+    }
+}
+
+/// Loan rules applied at the circulation desk.
+#[derive(Debug, Clone)]
+pub struct CirculationConfig {
+    /// Length of a loan (and of a renewal) in days.
+    pub loan_days: i64,
+    /// Items a patron may have out at once.
+    pub max_loans: i64,
+    /// Times a loan may be renewed.
+    pub max_renewals: i64,
+    /// Unpaid fines above this amount block checkouts and renewals.
+    pub fine_limit: f64,
+    /// Billed for a lost item whose book has no replacement cost.
+    pub replacement_cost: f64,
+    /// Days a copy put aside for a hold waits to be collected.
+    pub hold_pickup_days: i64,
+}
+
+impl Default for CirculationConfig {
+    fn default() -> Self {
+        Self {
+            loan_days: 7,
+            max_loans: 5,
+            max_renewals: 2,
+            fine_limit: 0.0,
+            replacement_cost: 20.0,
+            hold_pickup_days: 7,
+        }
+    }
+}
+
+impl CirculationConfig {
+    fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            loan_days: parse_var("LOAN_DAYS").unwrap_or(default.loan_days),
+            max_loans: parse_var("MAX_LOANS").unwrap_or(default.max_loans),
+            max_renewals: parse_var("MAX_RENEWALS").unwrap_or(default.max_renewals),
+            fine_limit: parse_var("FINE_LIMIT").unwrap_or(default.fine_limit),
+            replacement_cost: parse_var("REPLACEMENT_COST").unwrap_or(default.replacement_cost),
+            hold_pickup_days: parse_var("HOLD_PICKUP_DAYS").unwrap_or(default.hold_pickup_days),
+        }
+    }
+}
+
+/// Lifetimes of the tokens handed out at login and how they are signed.
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    /// Access tokens are short lived and renewed with the refresh token.
+    pub access_token_minutes: i64,
+    /// A session ends when its refresh token goes unused this long.
+    pub refresh_token_days: i64,
+    /// How long a password reset link works.
+    pub password_reset_minutes: i64,
+    /// How long an email verification link works.
+    pub verification_hours: i64,
+    /// Seconds a member waits before another verification mail is sent.
+    pub verification_resend_seconds: i64,
+    /// Time to enter the one-time code after the password.
+    pub two_factor_minutes: i64,
+    /// Name authenticator apps list the accounts under.
+    pub two_factor_issuer: String,
+    /// Failed logins let through before each further one has to wait,
+    /// doubling every time.
+    pub login_free_attempts: i64,
+    /// Longest wait between failed logins, in seconds.
+    pub login_max_backoff_seconds: i64,
+    /// Failed logins after which the account is locked.
+    pub login_max_failures: i64,
+    /// How long a lockout lasts, failures older than that are forgotten.
+    pub lockout_minutes: i64,
+    pub jwt: JwtConfig,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            access_token_minutes: 15,
+            refresh_token_days: 30,
+            password_reset_minutes: 60,
+            verification_hours: 48,
+            verification_resend_seconds: 60,
+            two_factor_minutes: 5,
+            two_factor_issuer: "Maktaba".to_string(),
+            login_free_attempts: 3,
+            login_max_backoff_seconds: 300,
+            login_max_failures: 10,
+            lockout_minutes: 30,
+            jwt: JwtConfig::default(),
+        }
+    }
+}
+
+impl AuthConfig {
+    fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            access_token_minutes: parse_var("ACCESS_TOKEN_MINUTES")
+                .unwrap_or(default.access_token_minutes),
+            refresh_token_days: parse_var("REFRESH_TOKEN_DAYS")
+                .unwrap_or(default.refresh_token_days),
+            password_reset_minutes: parse_var("PASSWORD_RESET_MINUTES")
+                .unwrap_or(default.password_reset_minutes),
+            verification_hours: parse_var("VERIFICATION_HOURS")
+                .unwrap_or(default.verification_hours),
+            verification_resend_seconds: parse_var("VERIFICATION_RESEND_SECONDS")
+                .unwrap_or(default.verification_resend_seconds),
+            two_factor_minutes: parse_var("TWO_FACTOR_MINUTES")
+                .unwrap_or(default.two_factor_minutes),
+            two_factor_issuer: env::var("TWO_FACTOR_ISSUER").unwrap_or(default.two_factor_issuer),
+            login_free_attempts: parse_var("LOGIN_FREE_ATTEMPTS")
+                .unwrap_or(default.login_free_attempts),
+            login_max_backoff_seconds: parse_var("LOGIN_MAX_BACKOFF_SECONDS")
+                .unwrap_or(default.login_max_backoff_seconds),
+            login_max_failures: parse_var("LOGIN_MAX_FAILURES")
+                .unwrap_or(default.login_max_failures),
+            lockout_minutes: parse_var("LOCKOUT_MINUTES").unwrap_or(default.lockout_minutes),
+            jwt: JwtConfig::from_env(),
+        }
+    }
+}
+
+/// Development only, release builds refuse to start with it.
+pub const DEFAULT_JWT_SECRET: &str = "secret";
+
+/// How tokens are signed.
+#[derive(Debug, Clone)]
+pub enum JwtConfig {
+    /// HS256 with a shared secret.
+    Secret(String),
+    /// RS256 with an RSA key pair.
+    Rsa(KeyFiles),
+    /// EdDSA with an Ed25519 key pair.
+    EdDsa(KeyFiles),
+}
+
+/// Key pair files for asymmetric signing.
+///
+/// To rotate, add the new public key, switch `kid` and `private_key` to it
+/// and remove the old public key once tokens signed with it have expired.
+#[derive(Debug, Clone)]
+pub struct KeyFiles {
+    /// Id of the signing key, sent as the token's `kid` header
+    pub kid: String,
+    /// PEM encoded PKCS#8 private key of `kid`
+    pub private_key: PathBuf,
+    /// Directory of `<kid>.pem` public keys tokens are accepted from,
+    /// including the one of `kid`
+    pub public_keys: PathBuf,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        JwtConfig::Secret(DEFAULT_JWT_SECRET.to_string())
+    }
+}
+
+impl JwtConfig {
+    fn from_env() -> Self {
+        let key_files = || KeyFiles {
+            kid: env::var("JWT_KID").unwrap_or_default(),
+            private_key: env::var("JWT_PRIVATE_KEY")
+                .map(PathBuf::from)
+                .unwrap_or_default(),
+            public_keys: env::var("JWT_PUBLIC_KEYS")
+                .map(PathBuf::from)
+                .unwrap_or(PathBuf::from("keys")),
+        };
+        match env::var("JWT_ALGORITHM").as_deref() {
+            Ok("RS256") => JwtConfig::Rsa(key_files()),
+            Ok("EdDSA") => JwtConfig::EdDsa(key_files()),
+            _ => {
+                JwtConfig::Secret(env::var("JWT_SECRET").unwrap_or(DEFAULT_JWT_SECRET.to_string()))
+            }
+        }
+    }
+}
+
+/// Outgoing email.
+#[derive(Debug, Clone)]
+pub struct MailConfig {
+    /// Sender address, e.g. `Library <library@example.org>`
+    pub from: String,
+    pub transport: MailTransport,
+    /// Seconds between outbox runs.
+    pub outbox_interval: u64,
+    /// Deliveries tried before a message is given up on.
+    pub max_attempts: i64,
+}
+
+/// How mail leaves the server.
+#[derive(Debug, Clone)]
+pub enum MailTransport {
+    /// Written to the log, or as `.eml` files under `dir`. For development.
+    Log { dir: Option<PathBuf> },
+    /// An SMTP server, MailHog's `localhost:1025` by default.
+    Smtp {
+        host: String,
+        port: u16,
+        security: SmtpSecurity,
+        username: Option<String>,
+        password: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmtpSecurity {
+    None,
+    StartTls,
+    /// TLS from the start, usually port 465
+    Tls,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            from: "Maktaba <maktaba@localhost>".to_string(),
+            transport: MailTransport::Log { dir: None },
+            outbox_interval: 30,
+            max_attempts: 5,
+        }
+    }
+}
+
+impl MailConfig {
+    fn from_env() -> Self {
+        let default = Self::default();
+        let transport = match env::var("MAIL_TRANSPORT").as_deref() {
+            Ok("smtp") => MailTransport::Smtp {
+                host: env::var("SMTP_HOST").unwrap_or("localhost".to_string()),
+                port: parse_var("SMTP_PORT").unwrap_or(1025),
+                security: match env::var("SMTP_SECURITY").as_deref() {
+                    Ok("starttls") => SmtpSecurity::StartTls,
+                    Ok("tls") => SmtpSecurity::Tls,
+                    _ => SmtpSecurity::None,
+                },
+                username: env::var("SMTP_USERNAME").ok(),
+                password: env::var("SMTP_PASSWORD").ok(),
+            },
+            _ => MailTransport::Log {
+                dir: env::var("MAIL_DIR").map(PathBuf::from).ok(),
+            },
+        };
+        Self {
+            from: env::var("MAIL_FROM").unwrap_or(default.from),
+            transport,
+            // tokio panics on a zero interval
+            outbox_interval: parse_var("MAIL_OUTBOX_INTERVAL")
+                .filter(|&seconds| seconds > 0)
+                .unwrap_or(default.outbox_interval),
+            max_attempts: parse_var("MAIL_MAX_ATTEMPTS").unwrap_or(default.max_attempts),
+        }
+    }
+}
+
+/// Reminders about loans, sent by email on a schedule.
+#[derive(Debug, Clone)]
+pub struct NoticeConfig {
+    /// Seconds between runs.
+    pub interval: u64,
+    /// Days before the due date to remind members, e.g. `[3]`.
+    pub due_soon_days: Vec<i64>,
+    /// Days past the due date to send overdue notices, e.g. `[1, 7, 14]`.
+    pub overdue_days: Vec<i64>,
+    /// Days before a hold stops waiting for collection to remind its member.
+    pub hold_expiring_days: i64,
+}
+
+impl Default for NoticeConfig {
+    fn default() -> Self {
+        Self {
+            interval: 3600,
+            due_soon_days: vec![3],
+            overdue_days: vec![1, 7, 14],
+            hold_expiring_days: 2,
+        }
+    }
+}
+
+impl NoticeConfig {
+    fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            // tokio panics on a zero interval
+            interval: parse_var("NOTICE_INTERVAL")
+                .filter(|&seconds| seconds > 0)
+                .unwrap_or(default.interval),
+            due_soon_days: parse_list("NOTICE_DUE_SOON_DAYS").unwrap_or(default.due_soon_days),
+            overdue_days: parse_list("NOTICE_OVERDUE_DAYS").unwrap_or(default.overdue_days),
+            hold_expiring_days: parse_var("NOTICE_HOLD_EXPIRING_DAYS")
+                .unwrap_or(default.hold_expiring_days),
+        }
+    }
+}
+
+fn parse_var<T: std::str::FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok().and_then(|v| v.parse().ok())
+}
+
+/// A comma separated list, e.g. `1,7,14`.
+fn parse_list<T: std::str::FromStr>(key: &str) -> Option<Vec<T>> {
+    env::var(key)
+        .ok()?
+        .split(',')
+        .filter(|v| !v.trim().is_empty())
+        .map(|v| v.trim().parse().ok())
+        .collect()
+}
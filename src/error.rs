@@ -14,4 +14,12 @@ pub enum Error {
     Argon2(String),
     #[error(transparent)]
     Jwt(#[from] jsonwebtoken::errors::Error),
+    #[error(transparent)]
+    Keys(#[from] crate::jwt::Error),
+    #[error(transparent)]
+    Model(#[from] crate::model::error::Error),
+    #[error(transparent)]
+    Media(#[from] crate::media::Error),
+    #[error(transparent)]
+    Mail(#[from] crate::notify::mail::Error),
 }
@@ -0,0 +1,81 @@
+//! ISBN normalization, so the same book typed as `0-306-40615-2`,
+//! `0306406152` or `978-0-306-40615-7` is recognised.
+
+/// The ISBN-13 form of an ISBN-10 or ISBN-13, ignoring hyphens and spaces.
+///
+/// Returns `None` when `isbn` is neither or its check digit is wrong.
+pub fn normalize(isbn: &str) -> Option<String> {
+    let isbn: String = isbn
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    match isbn.len() {
+        10 => {
+            let (body, check) = isbn.split_at(9);
+            if !is_digits(body) || check != isbn10_check(body) {
+                return None;
+            }
+            let body = format!("978{body}");
+            let check = isbn13_check(&body);
+            Some(format!("{body}{check}"))
+        }
+        13 => {
+            let (body, check) = isbn.split_at(12);
+            if !is_digits(&isbn) || check != isbn13_check(body).to_string() {
+                return None;
+            }
+            Some(isbn)
+        }
+        _ => None,
+    }
+}
+
+/// Check character of the first nine digits of an ISBN-10, `X` standing for 10.
+fn isbn10_check(body: &str) -> String {
+    let sum: u32 = body
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .zip((2..=10).rev())
+        .map(|(d, weight)| d * weight)
+        .sum();
+    match (11 - sum % 11) % 11 {
+        10 => "X".to_string(),
+        check => check.to_string(),
+    }
+}
+
+/// Check digit of the first twelve digits of an ISBN-13.
+fn isbn13_check(body: &str) -> u32 {
+    let sum: u32 = body
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 0 { d } else { d * 3 })
+        .sum();
+    (10 - sum % 10) % 10
+}
+
+fn is_digits(s: &str) -> bool {
+    s.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn normalizing() {
+        let isbn = Some("9780306406157".to_string());
+        assert_eq!(normalize("0-306-40615-2"), isbn);
+        assert_eq!(normalize("0306406152"), isbn);
+        assert_eq!(normalize("978-0-306-40615-7"), isbn);
+        assert_eq!(normalize(" 978 0306406157 "), isbn);
+        assert_eq!(normalize("080442957x"), Some("9780804429573".to_string()));
+
+        assert_eq!(normalize("0306406153"), None);
+        assert_eq!(normalize("9780306406158"), None);
+        assert_eq!(normalize("1234"), None);
+    }
+}
@@ -0,0 +1,272 @@
+//! Printable spine and barcode labels laid out for Avery label stock.
+
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str};
+use serde::Deserialize;
+
+use crate::barcode;
+
+/// Points per inch
+const IN: f32 = 72.0;
+/// Points per millimetre
+const MM: f32 = 72.0 / 25.4;
+
+const PADDING: f32 = 4.0;
+const TITLE_SIZE: f32 = 8.0;
+const CALL_NUMBER_SIZE: f32 = 8.0;
+const CODE_SIZE: f32 = 6.0;
+/// Blank modules on each side of the bars
+const QUIET_ZONE: f32 = 10.0;
+
+const REGULAR: Name = Name(b"F1");
+const BOLD: Name = Name(b"F2");
+
+/// Supported label sheets.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+pub enum LabelTemplate {
+    /// US Letter, 30 labels of 2⅝" × 1"
+    #[default]
+    #[serde(rename = "5160")]
+    Avery5160,
+    /// US Letter, 10 labels of 4" × 2"
+    #[serde(rename = "5163")]
+    Avery5163,
+    /// A4, 21 labels of 63.5 × 38.1 mm
+    #[serde(rename = "L7160")]
+    AveryL7160,
+}
+
+/// Geometry of a label sheet, in points.
+struct Layout {
+    page: (f32, f32),
+    label: (f32, f32),
+    /// Left and top margin of the first label
+    margin: (f32, f32),
+    /// Distance between the origins of neighbouring labels
+    pitch: (f32, f32),
+    columns: usize,
+    rows: usize,
+}
+
+impl LabelTemplate {
+    fn layout(self) -> Layout {
+        match self {
+            LabelTemplate::Avery5160 => Layout {
+                page: (8.5 * IN, 11.0 * IN),
+                label: (2.625 * IN, 1.0 * IN),
+                margin: (0.1875 * IN, 0.5 * IN),
+                pitch: (2.75 * IN, 1.0 * IN),
+                columns: 3,
+                rows: 10,
+            },
+            LabelTemplate::Avery5163 => Layout {
+                page: (8.5 * IN, 11.0 * IN),
+                label: (4.0 * IN, 2.0 * IN),
+                margin: (0.15625 * IN, 0.5 * IN),
+                pitch: (4.1875 * IN, 2.0 * IN),
+                columns: 2,
+                rows: 5,
+            },
+            LabelTemplate::AveryL7160 => Layout {
+                page: (210.0 * MM, 297.0 * MM),
+                label: (63.5 * MM, 38.1 * MM),
+                margin: (7.2 * MM, 15.1 * MM),
+                pitch: (66.0 * MM, 38.1 * MM),
+                columns: 3,
+                rows: 7,
+            },
+        }
+    }
+}
+
+/// What gets printed on a single label.
+pub struct Label {
+    pub title: String,
+    pub call_number: Option<String>,
+    pub barcode: String,
+}
+
+/// Render `labels` as a PDF, leaving the first `skip` positions of the first
+/// sheet blank so partly used sheets can be fed again.
+pub fn render(
+    template: LabelTemplate,
+    labels: &[Label],
+    skip: usize,
+) -> barcoders::error::Result<Vec<u8>> {
+    let layout = template.layout();
+    let per_page = layout.columns * layout.rows;
+    let skip = skip % per_page;
+    let pages = (labels.len() + skip).div_ceil(per_page).max(1);
+
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let regular_id = Ref::new(3);
+    let bold_id = Ref::new(4);
+    // a page and its content stream for each sheet
+    let page_ids: Vec<(Ref, Ref)> = (0..pages as i32)
+        .map(|i| (Ref::new(5 + i * 2), Ref::new(6 + i * 2)))
+        .collect();
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id)
+        .kids(page_ids.iter().map(|(page, _)| *page))
+        .count(pages as i32);
+    pdf.type1_font(regular_id)
+        .base_font(Name(b"Helvetica"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.type1_font(bold_id)
+        .base_font(Name(b"Helvetica-Bold"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+
+    let mut contents: Vec<Content> = (0..pages).map(|_| Content::new()).collect();
+    for (label, position) in labels.iter().zip(skip..) {
+        let (page, slot) = (position / per_page, position % per_page);
+        let column = slot % layout.columns;
+        let row = slot / layout.columns;
+        let x = layout.margin.0 + column as f32 * layout.pitch.0;
+        // PDF origin is the bottom left corner
+        let y = layout.page.1 - layout.margin.1 - row as f32 * layout.pitch.1 - layout.label.1;
+
+        draw_label(&mut contents[page], label, x, y, layout.label)?;
+    }
+
+    for ((page_id, content_id), content) in page_ids.into_iter().zip(contents) {
+        let mut page = pdf.page(page_id);
+        page.media_box(Rect::new(0.0, 0.0, layout.page.0, layout.page.1))
+            .parent(page_tree_id)
+            .contents(content_id);
+        let mut resources = page.resources();
+        let mut fonts = resources.fonts();
+        fonts.pair(REGULAR, regular_id);
+        fonts.pair(BOLD, bold_id);
+        fonts.finish();
+        resources.finish();
+        page.finish();
+
+        pdf.stream(content_id, &content.finish());
+    }
+
+    Ok(pdf.finish())
+}
+
+fn draw_label(
+    content: &mut Content,
+    label: &Label,
+    x: f32,
+    y: f32,
+    (width, height): (f32, f32),
+) -> barcoders::error::Result<()> {
+    let modules = barcode::code128(&label.barcode)?;
+    let inner = width - PADDING * 2.0;
+    let mut top = y + height - PADDING;
+
+    top -= TITLE_SIZE;
+    text(
+        content,
+        BOLD,
+        TITLE_SIZE,
+        x + PADDING,
+        top,
+        &fit(&label.title, inner, TITLE_SIZE),
+    );
+    if let Some(call_number) = &label.call_number {
+        top -= CALL_NUMBER_SIZE + 1.0;
+        text(
+            content,
+            REGULAR,
+            CALL_NUMBER_SIZE,
+            x + PADDING,
+            top,
+            &fit(call_number, inner, CALL_NUMBER_SIZE),
+        );
+    }
+
+    // bars fill what is left between the text lines
+    let bottom = y + PADDING + CODE_SIZE + 1.0;
+    let bar_height = (top - 2.0 - bottom).max(0.0);
+    let module = inner / (modules.len() as f32 + QUIET_ZONE * 2.0);
+    let start = x + PADDING + module * QUIET_ZONE;
+    for (offset, bar) in barcode::bars(&modules) {
+        content.rect(
+            start + offset as f32 * module,
+            bottom,
+            bar as f32 * module,
+            bar_height,
+        );
+    }
+    content.fill_nonzero();
+
+    let code_width = label.barcode.len() as f32 * CODE_SIZE * 0.55;
+    text(
+        content,
+        REGULAR,
+        CODE_SIZE,
+        x + (width - code_width) / 2.0,
+        y + PADDING,
+        &label.barcode,
+    );
+
+    Ok(())
+}
+
+fn text(content: &mut Content, font: Name, size: f32, x: f32, y: f32, s: &str) {
+    content
+        .begin_text()
+        .set_font(font, size)
+        .next_line(x, y)
+        .show(Str(&win_ansi(s)))
+        .end_text();
+}
+
+/// Shorten `s` to roughly fit `width` points at the given font size.
+fn fit(s: &str, width: f32, size: f32) -> String {
+    // Helvetica averages a little over half an em per character
+    let max = (width / (size * 0.55)) as usize;
+    if s.chars().count() <= max {
+        return s.to_string();
+    }
+    let mut short: String = s.chars().take(max.saturating_sub(3)).collect();
+    short.push_str("...");
+    short
+}
+
+/// Encode for the standard fonts, characters outside Latin-1 become `?`.
+fn win_ansi(s: &str) -> Vec<u8> {
+    s.chars()
+        .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn label(n: i64) -> Label {
+        Label {
+            title: format!("A rather long book title that will not fit {n}"),
+            call_number: Some("Library 1".to_string()),
+            barcode: barcode::generate("3", n, 14),
+        }
+    }
+
+    #[test]
+    fn rendering_sheets() {
+        let labels: Vec<Label> = (1..=35).map(label).collect();
+        let pdf = render(LabelTemplate::Avery5160, &labels, 0).unwrap();
+
+        assert!(pdf.starts_with(b"%PDF"));
+        // 35 labels need a second sheet of 30
+        assert_eq!(
+            pdf.windows(b"/Type /Page\n".len())
+                .filter(|w| w == b"/Type /Page\n")
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn fitting_text() {
+        assert_eq!(fit("Short", 100.0, 8.0), "Short");
+        assert!(fit(&"x".repeat(100), 100.0, 8.0).ends_with("..."));
+    }
+}
@@ -1,7 +1,9 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use axum::{http::HeaderValue, middleware, Router};
+use config::Config;
 use listenfd::ListenFd;
+use model::{book::Book, user::User};
 use sqlx::SqlitePool;
 use state::AppStateInner;
 use tokio::net::TcpListener;
@@ -13,12 +15,22 @@ use self::{error::Result, middlewares::log::request_logger};
 
 mod assets;
 mod auth;
+mod barcode;
+mod callnumber;
+mod config;
 mod error;
 mod extractors;
+mod isbn;
+mod jwt;
+mod labels;
+mod media;
 mod middlewares;
 mod model;
+mod notify;
+mod offline;
 mod routes;
 mod state;
+mod totp;
 mod utils;
 
 #[tokio::main]
@@ -27,13 +39,33 @@ async fn main() -> Result<()> {
 
     let db_url = std::env::var("DATABASE_URL").unwrap_or("sqlite://:memory:".to_string());
     let pool = SqlitePool::connect(&db_url).await?;
-    let jwt_secret = std::env::var("JWT_SECRET").unwrap_or("secret".to_string());
+    let config = Config::from_env();
+    let keys = jwt::from_config(&config.auth.jwt)?;
+    let media = media::from_config(&config.media)?;
+    let mailer = notify::mail::from_config(&config.mail)?;
 
     sqlx::migrate!().run(&pool).await?;
 
+    let state = Arc::new(AppStateInner {
+        pool,
+        keys,
+        config,
+        media,
+    });
+
+    // rows created before barcodes existed
+    let copies = Book::assign_barcodes(&state).await?;
+    let users = User::assign_card_numbers(&state).await?;
+    if copies + users > 0 {
+        info!("Assigned {copies} copy barcodes and {users} card numbers");
+    }
+
+    notify::mail::spawn_outbox(state.clone(), mailer);
+    notify::notices::spawn_notices(state.clone());
+
     // build our application with a route
     let app = Router::new()
-        .merge(routes::routes(Arc::new(AppStateInner { pool, jwt_secret })))
+        .merge(routes::routes(state))
         // handle all other routes from the frontend
         .fallback(assets::static_handler)
         .layer(middleware::from_fn(request_logger))
@@ -59,12 +91,15 @@ async fn main() -> Result<()> {
         None => TcpListener::bind("0.0.0.0:3000").await?,
     };
     info!("Listening on port 3000");
-    axum::serve(listener, app)
-        .with_graceful_shutdown(async {
-            utils::shutdown_signal().await;
-            info!("Ctrl+C Received, Shutting down");
-        })
-        .await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async {
+        utils::shutdown_signal().await;
+        info!("Ctrl+C Received, Shutting down");
+    })
+    .await?;
     debug!("Bye");
     Ok(())
 }
@@ -0,0 +1,19 @@
+use std::io;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error("Invalid bucket configuration: {0}")]
+    Bucket(String),
+    #[error("'{0}' not found")]
+    NotFound(String),
+    #[error("Invalid media key '{0}'")]
+    InvalidKey(String),
+    #[error("Unsupported media type '{0}'")]
+    UnsupportedType(String),
+}
@@ -0,0 +1,136 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use tokio::fs;
+
+use super::{check_key, proxy_url, Error, Media, MediaStore, Result};
+
+/// Keeps media as plain files under `root`, served back through `/api/media`.
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        check_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl MediaStore for LocalStore {
+    async fn put(&self, key: &str, data: Bytes, _content_type: &str) -> Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(path, data).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Media> {
+        let path = self.path(key)?;
+        let data = fs::read(&path).await.map_err(|e| match e.kind() {
+            ErrorKind::NotFound => Error::NotFound(key.to_string()),
+            _ => e.into(),
+        })?;
+        let content_type = mime_guess::from_path(&path)
+            .first_or_octet_stream()
+            .to_string();
+
+        Ok(Media {
+            data: data.into(),
+            content_type,
+        })
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let path = self.path(key)?;
+        fs::remove_file(path).await.map_err(|e| match e.kind() {
+            ErrorKind::NotFound => Error::NotFound(key.to_string()),
+            _ => e.into(),
+        })
+    }
+
+    fn url(&self, key: &str) -> String {
+        proxy_url(key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use crate::media::{delete_url, store_image};
+
+    use super::*;
+
+    fn store() -> LocalStore {
+        LocalStore::new(std::env::temp_dir().join(format!("maktaba-{}", Uuid::new_v4())))
+    }
+
+    #[tokio::test]
+    async fn put_get_delete() -> Result<()> {
+        let store = store();
+
+        store
+            .put("covers/1.png", Bytes::from_static(b"cover"), "image/png")
+            .await?;
+        let media = store.get("covers/1.png").await?;
+        assert_eq!(&media.data[..], b"cover");
+        assert_eq!(media.content_type, "image/png");
+        assert_eq!(store.url("covers/1.png"), "/api/media/covers/1.png");
+
+        store.delete("covers/1.png").await?;
+        assert!(matches!(
+            store.get("covers/1.png").await,
+            Err(Error::NotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storing_images() -> Result<()> {
+        let store = store();
+
+        assert!(matches!(
+            store_image(&store, "avatars/1", "image/svg+xml", Bytes::new()).await,
+            Err(Error::UnsupportedType(_))
+        ));
+        let own = store_image(&store, "avatars/1", "image/png", Bytes::new()).await?;
+        assert!(own.ends_with(".png"));
+        let other = store_image(&store, "avatars/12", "image/jpeg", Bytes::new()).await?;
+
+        // only the caller's own objects go
+        delete_url(&store, &other, "avatars/1").await?;
+        assert!(store
+            .get(other.trim_start_matches("/api/media/"))
+            .await
+            .is_ok());
+        delete_url(&store, &own, "avatars/1").await?;
+        assert!(matches!(
+            store.get(own.trim_start_matches("/api/media/")).await,
+            Err(Error::NotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_path_traversal() {
+        let store = store();
+
+        for key in ["../secret", "/etc/passwd", "covers/../../x", "a//b", ""] {
+            assert!(matches!(store.get(key).await, Err(Error::InvalidKey(_))));
+        }
+    }
+}
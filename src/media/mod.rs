@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use uuid::Uuid;
+
+use crate::config::MediaConfig;
+
+pub use self::{
+    error::{Error, Result},
+    local::LocalStore,
+    s3::S3Store,
+};
+
+pub mod error;
+pub mod local;
+pub mod s3;
+
+/// A stored object and its mime type.
+pub struct Media {
+    pub data: Bytes,
+    pub content_type: String,
+}
+
+/// Storage backend for uploaded media.
+///
+/// Keys are relative, `/` separated paths such as `covers/12-<uuid>.png`.
+#[async_trait]
+pub trait MediaStore: Send + Sync {
+    /// Store `data` under `key`, replacing any existing object.
+    async fn put(&self, key: &str, data: Bytes, content_type: &str) -> Result<()>;
+
+    async fn get(&self, key: &str) -> Result<Media>;
+
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// URL clients should use to fetch `key`.
+    fn url(&self, key: &str) -> String;
+}
+
+/// Build the store selected by the configuration.
+pub fn from_config(config: &MediaConfig) -> Result<Arc<dyn MediaStore>> {
+    let store: Arc<dyn MediaStore> = match config {
+        MediaConfig::Local { root } => Arc::new(LocalStore::new(root)),
+        MediaConfig::S3 {
+            endpoint,
+            bucket,
+            region,
+            access_key,
+            secret_key,
+            public_url,
+        } => Arc::new(S3Store::new(
+            endpoint,
+            bucket,
+            region,
+            access_key,
+            secret_key,
+            public_url.clone(),
+        )?),
+    };
+    Ok(store)
+}
+
+/// Store an uploaded image under `prefix` and return its URL.
+///
+/// Only raster formats are taken, an SVG could carry script that runs when
+/// it's opened from the app's own origin.
+pub async fn store_image(
+    store: &dyn MediaStore,
+    prefix: &str,
+    content_type: &str,
+    data: Bytes,
+) -> Result<String> {
+    let ext = match content_type {
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        ct => return Err(Error::UnsupportedType(ct.to_string())),
+    };
+
+    let key = format!("{prefix}-{}.{ext}", Uuid::new_v4());
+    store.put(&key, data, content_type).await?;
+
+    Ok(store.url(&key))
+}
+
+/// Remove the object behind `url`, if it is one `store` handed out for
+/// `prefix` by [`store_image`]. Anything else is left alone, the URL may
+/// have come from elsewhere.
+pub async fn delete_url(store: &dyn MediaStore, url: &str, prefix: &str) -> Result<()> {
+    match url.strip_prefix(&store.url("")) {
+        Some(key) if key.starts_with(&format!("{prefix}-")) => store.delete(key).await,
+        _ => Ok(()),
+    }
+}
+
+/// Reject keys that could escape the store root.
+fn check_key(key: &str) -> Result<()> {
+    if key.is_empty()
+        || key.starts_with('/')
+        || key.contains('\\')
+        || key
+            .split('/')
+            .any(|s| s.is_empty() || s == "." || s == "..")
+    {
+        return Err(Error::InvalidKey(key.to_string()));
+    }
+    Ok(())
+}
+
+/// URL of `key` when served through the `/api/media` route.
+fn proxy_url(key: &str) -> String {
+    format!("/api/media/{key}")
+}
@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use reqwest::{header::CONTENT_TYPE, Client, StatusCode};
+use rusty_s3::{Bucket, Credentials, S3Action, UrlStyle};
+
+use super::{check_key, proxy_url, Error, Media, MediaStore, Result};
+
+/// How long the presigned request URLs stay valid.
+const SIGNATURE_TTL: Duration = Duration::from_secs(60);
+
+/// Keeps media in an S3 compatible bucket.
+///
+/// Uses path style requests so it works against MinIO and other
+/// self-hosted stand-ins as well as AWS.
+pub struct S3Store {
+    bucket: Bucket,
+    credentials: Credentials,
+    client: Client,
+    public_url: Option<String>,
+}
+
+impl S3Store {
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str,
+        public_url: Option<String>,
+    ) -> Result<Self> {
+        let endpoint = endpoint
+            .parse()
+            .map_err(|e: url::ParseError| Error::Bucket(e.to_string()))?;
+        let bucket = Bucket::new(
+            endpoint,
+            UrlStyle::Path,
+            bucket.to_string(),
+            region.to_string(),
+        )
+        .map_err(|e| Error::Bucket(e.to_string()))?;
+
+        Ok(Self {
+            bucket,
+            credentials: Credentials::new(access_key, secret_key),
+            client: Client::new(),
+            public_url,
+        })
+    }
+}
+
+#[async_trait]
+impl MediaStore for S3Store {
+    async fn put(&self, key: &str, data: Bytes, content_type: &str) -> Result<()> {
+        check_key(key)?;
+        let url = self
+            .bucket
+            .put_object(Some(&self.credentials), key)
+            .sign(SIGNATURE_TTL);
+        self.client
+            .put(url)
+            .header(CONTENT_TYPE, content_type)
+            .body(data)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Media> {
+        check_key(key)?;
+        let url = self
+            .bucket
+            .get_object(Some(&self.credentials), key)
+            .sign(SIGNATURE_TTL);
+        let res = self.client.get(url).send().await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Err(Error::NotFound(key.to_string()));
+        }
+        let res = res.error_for_status()?;
+        let content_type = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("application/octet-stream")
+            .to_string();
+
+        Ok(Media {
+            data: res.bytes().await?,
+            content_type,
+        })
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        check_key(key)?;
+        let url = self
+            .bucket
+            .delete_object(Some(&self.credentials), key)
+            .sign(SIGNATURE_TTL);
+        self.client.delete(url).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    fn url(&self, key: &str) -> String {
+        match &self.public_url {
+            Some(base) => format!("{}/{key}", base.trim_end_matches('/')),
+            None => proxy_url(key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        extract::{Path, State},
+        http::{HeaderMap, StatusCode},
+        response::IntoResponse,
+        routing::put,
+        Router,
+    };
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    type Objects = Arc<Mutex<HashMap<String, (Bytes, String)>>>;
+
+    /// Minimal in-process stand-in for a MinIO server: path style
+    /// `/{bucket}/{key}` PUT, GET and DELETE without signature checks.
+    async fn stand_in() -> String {
+        async fn put_object(
+            State(objects): State<Objects>,
+            Path((bucket, key)): Path<(String, String)>,
+            headers: HeaderMap,
+            body: Bytes,
+        ) -> StatusCode {
+            let content_type = headers
+                .get("content-type")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            objects
+                .lock()
+                .unwrap()
+                .insert(format!("{bucket}/{key}"), (body, content_type));
+            StatusCode::OK
+        }
+
+        async fn get_object(
+            State(objects): State<Objects>,
+            Path((bucket, key)): Path<(String, String)>,
+        ) -> axum::response::Response {
+            match objects.lock().unwrap().get(&format!("{bucket}/{key}")) {
+                Some((data, content_type)) => {
+                    ([("content-type", content_type.clone())], data.clone()).into_response()
+                }
+                None => StatusCode::NOT_FOUND.into_response(),
+            }
+        }
+
+        async fn delete_object(
+            State(objects): State<Objects>,
+            Path((bucket, key)): Path<(String, String)>,
+        ) -> StatusCode {
+            objects.lock().unwrap().remove(&format!("{bucket}/{key}"));
+            StatusCode::NO_CONTENT
+        }
+
+        let app = Router::new()
+            .route(
+                "/{bucket}/{*key}",
+                put(put_object).get(get_object).delete(delete_object),
+            )
+            .with_state(Objects::default());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn put_get_delete() -> Result<()> {
+        let endpoint = stand_in().await;
+        let store = S3Store::new(&endpoint, "maktaba", "us-east-1", "key", "secret", None)?;
+
+        store
+            .put("avatars/1.jpg", Bytes::from_static(b"avatar"), "image/jpeg")
+            .await?;
+        let media = store.get("avatars/1.jpg").await?;
+        assert_eq!(&media.data[..], b"avatar");
+        assert_eq!(media.content_type, "image/jpeg");
+
+        store.delete("avatars/1.jpg").await?;
+        assert!(matches!(
+            store.get("avatars/1.jpg").await,
+            Err(Error::NotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn public_url() -> Result<()> {
+        let store = S3Store::new(
+            "http://localhost:9000",
+            "maktaba",
+            "us-east-1",
+            "key",
+            "secret",
+            Some("https://cdn.example.com/".to_string()),
+        )?;
+
+        assert_eq!(
+            store.url("covers/1.png"),
+            "https://cdn.example.com/covers/1.png"
+        );
+        Ok(())
+    }
+}
@@ -1,11 +1,24 @@
-use axum::{body::Body, extract::Request, middleware::Next, response::Response};
+use axum::{body::Body, extract::Request, http::StatusCode, middleware::Next, response::Response};
+use serde_json::{json, Value};
+use tracing::debug;
 
-use crate::auth::Claims;
+use crate::{auth::Claims, extractors::json::Json, model::user::UserRole};
 
 pub mod log;
 pub mod role;
 
-// Middleware for filtering loggedin users
-pub async fn require_login(_: Claims, req: Request<Body>, next: Next) -> Result<Response, ()> {
+// Middleware for filtering loggedin users, kiosk tokens only reach kiosk routes
+pub async fn require_login(
+    claims: Claims,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    if let UserRole::Kiosk = claims.role {
+        debug!("Kiosk token outside kiosk routes");
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "Unauthorized" })),
+        ));
+    }
     Ok(next.run(req).await)
 }
@@ -1,8 +1,19 @@
-use axum::{body::Body, extract::Request, http::StatusCode, middleware::Next, response::Response};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
 use serde_json::{json, Value};
 use tracing::debug;
 
-use crate::{auth::Claims, extractors::json::Json, model::user::UserRole};
+use crate::{
+    auth::Claims,
+    extractors::json::Json,
+    model::{kiosk::Kiosk, user::UserRole, Engine},
+    state::AppState,
+};
 
 // Middleware for filtering admin users
 pub async fn require_admin_role(
@@ -39,3 +50,29 @@ pub async fn require_issuer_admin_role(
         ))
     }
 }
+
+// Middleware for filtering kiosk devices that haven't been deactivated
+pub async fn require_kiosk_role(
+    State(state): State<AppState<Engine>>,
+    claims: Claims,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    if let UserRole::Kiosk = claims.role {
+        match Kiosk::get(&state, claims.user_id).await {
+            Ok(kiosk) if !kiosk.active => debug!("Kiosk {} is inactive", claims.user_id),
+            // a reissued token replaces the one before
+            Ok(kiosk) if kiosk.token_id != claims.jti => {
+                debug!("Kiosk {} token was reissued", claims.user_id)
+            }
+            Ok(_) => return Ok(next.run(req).await),
+            Err(e) => debug!("{e}"),
+        }
+    } else {
+        debug!("Not a kiosk");
+    }
+    Err((
+        StatusCode::UNAUTHORIZED,
+        Json(json!({ "error": "Unauthorized" })),
+    ))
+}
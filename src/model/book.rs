@@ -1,14 +1,22 @@
-use chrono::NaiveDateTime;
-use modql::field::{Fields, HasSeaFields, SeaFieldValue};
-use sea_query::{Expr, Iden, Query, SqliteQueryBuilder};
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use modql::{
+    field::{Fields, HasSeaFields, SeaFieldValue},
+    SIden,
+};
+use sea_query::{Condition, Expr, Func, Iden, Query, SqliteQueryBuilder};
 use sea_query_binder::SqlxBinder;
 use serde::{Deserialize, Serialize};
 use sqlx::{query_as_with, query_with, FromRow, Type};
 use uuid::Uuid;
 
-use crate::state::AppState;
+use crate::{barcode, callnumber, state::AppState};
 
-use super::{Model, Result};
+use super::{
+    contributor::{self, Contributor},
+    Model, Result,
+};
 
 #[derive(Debug, Serialize, FromRow, Fields)]
 pub struct Book {
@@ -19,6 +27,14 @@ pub struct Book {
     pub category: Option<String>,
     pub year: Option<i32>,
     pub photo: Option<String>,
+    /// Billed when a copy is declared lost
+    pub replacement_cost: Option<f64>,
+    /// Dewey or LC, e.g. "823.914 ROW"
+    pub call_number: Option<String>,
+    #[serde(skip)]
+    pub call_number_sort: Option<String>,
+    /// Work the book is an edition of
+    pub work_id: Option<i64>,
     pub updated_at: Option<NaiveDateTime>,
     pub added_at: NaiveDateTime,
 }
@@ -31,12 +47,17 @@ pub struct BookForCreate {
     pub category: Option<String>,
     pub year: Option<i32>,
     pub photo: Option<String>,
+    pub replacement_cost: Option<f64>,
+    pub call_number: Option<String>,
+    /// Set from `call_number`
+    #[serde(skip)]
+    pub call_number_sort: Option<String>,
     #[field(skip)]
     #[sqlx(skip)]
     pub count: i32,
 }
 
-#[derive(Debug, Deserialize, FromRow, Fields)]
+#[derive(Debug, Default, Deserialize, FromRow, Fields)]
 pub struct BookForUpdate {
     pub title: Option<String>,
     pub author: Option<String>,
@@ -44,6 +65,11 @@ pub struct BookForUpdate {
     pub category: Option<String>,
     pub year: Option<i32>,
     pub photo: Option<String>,
+    pub replacement_cost: Option<f64>,
+    pub call_number: Option<String>,
+    /// Set from `call_number`
+    #[serde(skip)]
+    pub call_number_sort: Option<String>,
     #[field(skip)]
     #[sqlx(skip)]
     pub count: i32,
@@ -54,7 +80,16 @@ pub struct BookCopy {
     pub id: i64,
     pub book_id: i64,
     pub status: Option<BorrowStatus>,
+    /// Why the copy is in its status, e.g. "water damage"
+    pub status_reason: Option<String>,
     pub location: Option<String>,
+    pub barcode: Option<String>,
+    pub branch_id: Option<i64>,
+    pub shelf_location_id: Option<i64>,
+    /// Overrides the book's call number
+    pub call_number: Option<String>,
+    #[serde(skip)]
+    pub call_number_sort: Option<String>,
     pub updated_at: Option<NaiveDateTime>,
     pub added_at: NaiveDateTime,
 }
@@ -67,6 +102,28 @@ pub enum BorrowStatus {
     Available,
     Borrowed,
     Reserved,
+    /// Being moved between branches
+    #[serde(rename = "in_transit")]
+    #[sqlx(rename = "in_transit")]
+    InTransit,
+    /// Declared lost by its borrower
+    Lost,
+    Damaged,
+    #[serde(rename = "in_repair")]
+    #[sqlx(rename = "in_repair")]
+    InRepair,
+    /// Not found where it should be
+    Missing,
+    /// Taken out of the collection
+    Withdrawn,
+}
+
+impl BorrowStatus {
+    /// Whether the status is driven by circulation (loans, holds and
+    /// transfers) rather than set by staff.
+    pub fn is_circulating(&self) -> bool {
+        matches!(self, Self::Borrowed | Self::Reserved | Self::InTransit)
+    }
 }
 
 impl From<BorrowStatus> for sea_query::Value {
@@ -75,6 +132,12 @@ impl From<BorrowStatus> for sea_query::Value {
             BorrowStatus::Available => "available".into(),
             BorrowStatus::Borrowed => "borrowed".into(),
             BorrowStatus::Reserved => "reserved".into(),
+            BorrowStatus::InTransit => "in_transit".into(),
+            BorrowStatus::Lost => "lost".into(),
+            BorrowStatus::Damaged => "damaged".into(),
+            BorrowStatus::InRepair => "in_repair".into(),
+            BorrowStatus::Missing => "missing".into(),
+            BorrowStatus::Withdrawn => "withdrawn".into(),
         }
     }
 }
@@ -90,12 +153,98 @@ pub struct BookCopyForCreate {
     pub book_id: i64,
     pub status: Option<BorrowStatus>,
     pub location: Option<String>,
+    /// Pre-printed barcode, generated when not given
+    pub barcode: Option<String>,
+    pub branch_id: Option<i64>,
+    /// Also sets the branch to the shelf's
+    pub shelf_location_id: Option<i64>,
+    /// Overrides the book's call number
+    pub call_number: Option<String>,
+    /// Set from `call_number`
+    #[serde(skip)]
+    pub call_number_sort: Option<String>,
 }
 
 #[derive(Debug, Default, Deserialize, FromRow, Fields)]
 pub struct BookCopyForUpdate {
     pub status: Option<BorrowStatus>,
+    pub status_reason: Option<String>,
     pub location: Option<String>,
+    pub barcode: Option<String>,
+    pub branch_id: Option<i64>,
+    /// Also sets the branch to the shelf's
+    pub shelf_location_id: Option<i64>,
+    /// Overrides the book's call number
+    pub call_number: Option<String>,
+    /// Set from `call_number`
+    #[serde(skip)]
+    pub call_number_sort: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BookSort {
+    #[default]
+    Title,
+    /// Shelf order, books without a call number last
+    CallNumber,
+}
+
+/// Filters of the book search.
+#[derive(Debug, Default, Deserialize)]
+pub struct BookFilter {
+    /// Matched against title, author, ISBN and contributor names
+    pub q: Option<String>,
+    /// Only count copies held at this branch
+    pub branch: Option<i64>,
+    /// Only books with a copy on the shelf
+    #[serde(default)]
+    pub available: bool,
+    #[serde(default)]
+    pub sort: BookSort,
+    /// One result per work, for its first matching edition
+    #[serde(default)]
+    pub collapse: bool,
+}
+
+/// A book with how many of its copies there are and how many are on the shelf.
+#[derive(Debug, Serialize, FromRow)]
+pub struct BookAvailability {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub book: Book,
+    /// Copies lost, missing or withdrawn are not counted
+    pub copies: i64,
+    pub available: i64,
+    /// Matching editions of the work, when results are collapsed
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub editions: Option<i64>,
+}
+
+/// A copy together with the book details printed on its label.
+#[derive(Debug, FromRow)]
+pub struct CopyLabel {
+    pub book_id: i64,
+    pub copy_id: i64,
+    pub title: String,
+    /// The copy's own or else its book's
+    pub call_number: Option<String>,
+    pub location: Option<String>,
+    pub barcode: String,
+}
+
+/// A copy in a shelf list.
+#[derive(Debug, Serialize, FromRow)]
+pub struct ShelvedCopy {
+    pub book_id: i64,
+    pub copy_id: i64,
+    pub title: String,
+    pub author: String,
+    /// The copy's own or else its book's
+    pub call_number: Option<String>,
+    pub barcode: Option<String>,
+    pub status: Option<BorrowStatus>,
 }
 
 pub struct BookCategory {
@@ -107,7 +256,21 @@ pub struct BookCategory {
 enum BookIden {
     Id,
     BookId,
+    Title,
+    Location,
+    AddedAt,
     Status,
+    Barcode,
+    CopyId,
+    Rowid,
+    Author,
+    Isbn,
+    BranchId,
+    Copies,
+    Available,
+    CallNumber,
+    CallNumberSort,
+    ShelfLocationId,
 }
 
 impl Model for Book {
@@ -137,7 +300,7 @@ impl Book {
         let mut query = Query::select();
         query
             .from(BookCopy::table_ref())
-            .columns(Book::sea_idens())
+            .columns(BookCopy::sea_idens())
             .and_where(Expr::col(BookIden::Id).eq(copy_id))
             .and_where(Expr::col(BookIden::BookId).eq(book_id));
 
@@ -152,13 +315,16 @@ impl Book {
         Ok(entity)
     }
 
-    pub async fn create(state: &AppState<super::Engine>, book: BookForCreate) -> Result<i64> {
+    pub async fn create(state: &AppState<super::Engine>, mut book: BookForCreate) -> Result<i64> {
+        book.call_number_sort = book.call_number.as_deref().map(callnumber::sort_key);
         let count = book.count;
+        let author = book.author.clone();
         let id = super::create::<Self, _>(state, book).await?;
+        Contributor::link_authors(state, id, &author).await?;
 
         if count > 0 {
             for _ in (0..count) {
-                super::create::<BookCopy, _>(
+                Self::add_copy(
                     state,
                     BookCopyForCreate {
                         book_id: id,
@@ -172,25 +338,123 @@ impl Book {
         Ok(id)
     }
 
-    pub async fn add_copy(state: &AppState<super::Engine>, copy: BookCopyForCreate) -> Result<i64> {
-        super::create::<Self, _>(state, copy).await
+    /// Add a copy and return its id within the book.
+    pub async fn add_copy(
+        state: &AppState<super::Engine>,
+        mut copy: BookCopyForCreate,
+    ) -> Result<i64> {
+        let db = &state.pool;
+        copy.call_number_sort = copy.call_number.as_deref().map(callnumber::sort_key);
+
+        let fields = copy.not_none_sea_fields();
+        let (columns, sea_values) = fields.for_sea_insert();
+
+        let mut query = Query::insert();
+        query
+            .into_table(BookCopy::table_ref())
+            .columns(columns)
+            .values(sea_values)?
+            .returning_col(BookIden::Rowid);
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let (rowid,) = query_as_with::<_, (i64,), _>(&sql, values)
+            .fetch_one(db)
+            .await?;
+
+        // The copy id is only known once the `generate_copy_id` trigger ran
+        Self::assign_barcode(state, rowid).await
+    }
+
+    /// Generate a barcode for the copy at `rowid` unless it already has one,
+    /// returns the copy id.
+    async fn assign_barcode(state: &AppState<super::Engine>, rowid: i64) -> Result<i64> {
+        let db = &state.pool;
+        let config = &state.config.barcode;
+
+        let code = barcode::generate(&config.copy_prefix, rowid, config.length);
+
+        let mut query = Query::update();
+        query
+            .table(BookCopy::table_ref())
+            .value(
+                BookIden::Barcode,
+                Func::coalesce([Expr::col(BookIden::Barcode).into(), Expr::val(code).into()]),
+            )
+            .and_where(Expr::col(BookIden::Rowid).eq(rowid))
+            .returning_col(BookIden::Id);
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let (id,) = query_as_with::<_, (i64,), _>(&sql, values)
+            .fetch_one(db)
+            .await?;
+
+        Ok(id)
+    }
+
+    /// Give every copy without a barcode a generated one.
+    pub async fn assign_barcodes(state: &AppState<super::Engine>) -> Result<usize> {
+        let db = &state.pool;
+
+        let mut query = Query::select();
+        query
+            .column(BookIden::Rowid)
+            .from(BookCopy::table_ref())
+            .and_where(Expr::col(BookIden::Barcode).is_null());
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let rows = query_as_with::<_, (i64,), _>(&sql, values)
+            .fetch_all(db)
+            .await?;
+
+        for (rowid,) in &rows {
+            Self::assign_barcode(state, *rowid).await?;
+        }
+
+        Ok(rows.len())
+    }
+
+    pub async fn get_copy_by_barcode(
+        state: &AppState<super::Engine>,
+        code: &str,
+    ) -> Result<Option<BookCopy>> {
+        let db = &state.pool;
+
+        let mut query = Query::select();
+        query
+            .from(BookCopy::table_ref())
+            .columns(BookCopy::sea_idens())
+            .and_where(Expr::col(BookIden::Barcode).eq(code));
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let copy = query_as_with::<_, BookCopy, _>(&sql, values)
+            .fetch_optional(db)
+            .await?;
+
+        Ok(copy)
     }
 
     pub async fn update(
         state: &AppState<super::Engine>,
         id: i64,
-        book: BookForUpdate,
+        mut book: BookForUpdate,
     ) -> Result<()> {
-        super::update::<Self, _>(state, id, book).await
+        book.call_number_sort = book.call_number.as_deref().map(callnumber::sort_key);
+        let author = book.author.clone();
+        super::update::<Self, _>(state, id, book).await?;
+        if let Some(author) = author {
+            Contributor::link_authors(state, id, &author).await?;
+        }
+        Ok(())
     }
 
     pub async fn update_copy(
         state: &AppState<super::Engine>,
         copy_id: i64,
         book_id: i64,
-        book: BookCopyForUpdate,
+        mut book: BookCopyForUpdate,
     ) -> Result<()> {
         let db = &state.pool;
+        book.call_number_sort = book.call_number.as_deref().map(callnumber::sort_key);
 
         let fields = book.not_none_sea_fields();
         let fields = fields.for_sea_update();
@@ -233,31 +497,242 @@ impl Book {
         Ok(entities)
     }
 
+    /// Label details of the given copies and of every copy added on or after
+    /// `since`.
+    pub async fn list_labels(
+        state: &AppState<super::Engine>,
+        copies: &[(i64, i64)],
+        since: Option<NaiveDate>,
+    ) -> Result<Vec<CopyLabel>> {
+        let db = &state.pool;
+        let books = SIden(Book::TABLE);
+        let book_copies = SIden(BookCopy::TABLE);
+
+        let mut cond = Condition::any();
+        for (book_id, copy_id) in copies {
+            cond = cond.add(
+                Condition::all()
+                    .add(Expr::col((book_copies, BookIden::BookId)).eq(*book_id))
+                    .add(Expr::col((book_copies, BookIden::Id)).eq(*copy_id)),
+            );
+        }
+        if let Some(since) = since {
+            cond = cond.add(Expr::col((book_copies, BookIden::AddedAt)).gte(since));
+        }
+
+        let mut query = Query::select();
+        query
+            .column((book_copies, BookIden::BookId))
+            .expr_as(Expr::col((book_copies, BookIden::Id)), BookIden::CopyId)
+            .column((books, BookIden::Title))
+            .expr_as(
+                Func::coalesce([
+                    Expr::col((book_copies, BookIden::CallNumber)).into(),
+                    Expr::col((books, BookIden::CallNumber)).into(),
+                ]),
+                BookIden::CallNumber,
+            )
+            .column((book_copies, BookIden::Location))
+            .column((book_copies, BookIden::Barcode))
+            .from(BookCopy::table_ref())
+            .inner_join(
+                Book::table_ref(),
+                Expr::col((books, BookIden::Id)).equals((book_copies, BookIden::BookId)),
+            )
+            .cond_where(cond)
+            .and_where(Expr::col((book_copies, BookIden::Barcode)).is_not_null())
+            .order_by((book_copies, BookIden::BookId), sea_query::Order::Asc)
+            .order_by((book_copies, BookIden::Id), sea_query::Order::Asc);
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let labels = query_as_with::<_, CopyLabel, _>(&sql, values)
+            .fetch_all(db)
+            .await?;
+
+        Ok(labels)
+    }
+
     pub async fn list(state: &AppState<super::Engine>) -> Result<Vec<Book>> {
         super::list::<Self, _>(state).await
     }
 
+    /// Books matching `filter` with their copy counts.
+    pub async fn search(
+        state: &AppState<super::Engine>,
+        filter: &BookFilter,
+    ) -> Result<Vec<BookAvailability>> {
+        let db = &state.pool;
+        let books = SIden(Book::TABLE);
+        let book_copies = SIden(BookCopy::TABLE);
+
+        let mut join = Condition::all()
+            .add(Expr::col((book_copies, BookIden::BookId)).equals((books, BookIden::Id)));
+        if let Some(branch) = filter.branch {
+            join = join.add(Expr::col((book_copies, BookIden::BranchId)).eq(branch));
+        }
+
+        let held = Expr::case(
+            Expr::col((book_copies, BookIden::Status)).is_not_in([
+                BorrowStatus::Lost,
+                BorrowStatus::Missing,
+                BorrowStatus::Withdrawn,
+            ]),
+            1,
+        )
+        .finally(0);
+        let on_shelf = Expr::case(
+            Expr::col((book_copies, BookIden::Status)).eq(BorrowStatus::Available),
+            1,
+        )
+        .finally(0);
+
+        let mut query = Query::select();
+        query
+            .columns(Book::sea_column_refs_with_rel(books))
+            .expr_as(
+                Func::coalesce([Func::sum(held).into(), Expr::val(0).into()]),
+                BookIden::Copies,
+            )
+            .expr_as(
+                Func::coalesce([Func::sum(on_shelf).into(), Expr::val(0).into()]),
+                BookIden::Available,
+            )
+            .from(Book::table_ref())
+            .left_join(BookCopy::table_ref(), join)
+            .group_by_col((books, BookIden::Id));
+        if filter.sort == BookSort::CallNumber {
+            query
+                .order_by_expr(
+                    Expr::col((books, BookIden::CallNumberSort)).is_null(),
+                    sea_query::Order::Asc,
+                )
+                .order_by((books, BookIden::CallNumberSort), sea_query::Order::Asc);
+        }
+        query.order_by((books, BookIden::Title), sea_query::Order::Asc);
+
+        if let Some(q) = filter.q.as_deref().filter(|q| !q.is_empty()) {
+            let pattern = format!("%{q}%");
+            query.cond_where(
+                Condition::any()
+                    .add(Expr::col((books, BookIden::Title)).like(&pattern))
+                    .add(Expr::col((books, BookIden::Author)).like(&pattern))
+                    .add(Expr::col((books, BookIden::Isbn)).like(&pattern))
+                    .add(contributor::credited_like(&pattern)),
+            );
+        }
+        if filter.available {
+            query.and_having(Expr::col(BookIden::Available).gt(0));
+        }
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let books = query_as_with::<_, BookAvailability, _>(&sql, values)
+            .fetch_all(db)
+            .await?;
+
+        if filter.collapse {
+            return Ok(collapse_works(books));
+        }
+        Ok(books)
+    }
+
+    /// Copies on a shelf in the order they should sit on it.
+    pub async fn shelf_list(
+        state: &AppState<super::Engine>,
+        shelf_location_id: i64,
+    ) -> Result<Vec<ShelvedCopy>> {
+        let db = &state.pool;
+        let books = SIden(Book::TABLE);
+        let book_copies = SIden(BookCopy::TABLE);
+
+        let sort_key = Func::coalesce([
+            Expr::col((book_copies, BookIden::CallNumberSort)).into(),
+            Expr::col((books, BookIden::CallNumberSort)).into(),
+        ]);
+
+        let mut query = Query::select();
+        query
+            .column((book_copies, BookIden::BookId))
+            .expr_as(Expr::col((book_copies, BookIden::Id)), BookIden::CopyId)
+            .columns([(books, BookIden::Title), (books, BookIden::Author)])
+            .expr_as(
+                Func::coalesce([
+                    Expr::col((book_copies, BookIden::CallNumber)).into(),
+                    Expr::col((books, BookIden::CallNumber)).into(),
+                ]),
+                BookIden::CallNumber,
+            )
+            .columns([
+                (book_copies, BookIden::Barcode),
+                (book_copies, BookIden::Status),
+            ])
+            .from(BookCopy::table_ref())
+            .inner_join(
+                Book::table_ref(),
+                Expr::col((books, BookIden::Id)).equals((book_copies, BookIden::BookId)),
+            )
+            .and_where(Expr::col((book_copies, BookIden::ShelfLocationId)).eq(shelf_location_id))
+            .order_by_expr(
+                Expr::expr(sort_key.clone()).is_null(),
+                sea_query::Order::Asc,
+            )
+            .order_by_expr(sort_key.into(), sea_query::Order::Asc)
+            .order_by((books, BookIden::Title), sea_query::Order::Asc)
+            .order_by((book_copies, BookIden::Id), sea_query::Order::Asc);
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let copies = query_as_with::<_, ShelvedCopy, _>(&sql, values)
+            .fetch_all(db)
+            .await?;
+
+        Ok(copies)
+    }
+
     pub async fn delete(state: &AppState<super::Engine>, id: i64) -> Result<()> {
         super::delete::<Self>(state, id).await
     }
 }
 
+/// Fold the editions of each work into its first result, adding up copies.
+fn collapse_works(books: Vec<BookAvailability>) -> Vec<BookAvailability> {
+    let mut collapsed: Vec<BookAvailability> = Vec::with_capacity(books.len());
+    let mut works = HashMap::new();
+    for mut book in books {
+        let Some(work_id) = book.book.work_id else {
+            book.editions = Some(1);
+            collapsed.push(book);
+            continue;
+        };
+        match works.get(&work_id) {
+            Some(&i) => {
+                let first: &mut BookAvailability = &mut collapsed[i];
+                first.copies += book.copies;
+                first.available += book.available;
+                first.editions = first.editions.map(|n| n + 1);
+            }
+            None => {
+                works.insert(work_id, collapsed.len());
+                book.editions = Some(1);
+                collapsed.push(book);
+            }
+        }
+    }
+    collapsed
+}
+
 #[cfg(test)]
 mod test {
-    use std::sync::Arc;
-
     use sqlx::SqlitePool;
 
-    use crate::state::AppStateInner;
+    use crate::{
+        model::branch::{Branch, BranchForCreate, ShelfLocationForCreate},
+        state::test_state,
+    };
 
     use super::*;
 
     #[sqlx::test(fixtures("books"))]
     fn getting_book_by_id(pool: SqlitePool) -> Result<()> {
-        let state = Arc::new(AppStateInner {
-            pool,
-            jwt_secret: "secret".to_string(),
-        });
+        let state = test_state(pool);
 
         let book = Book::get(&state, 1).await?;
 
@@ -265,4 +740,129 @@ mod test {
 
         Ok(())
     }
+
+    #[sqlx::test(fixtures("books"))]
+    fn adding_copy_with_barcode(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        let id = Book::add_copy(
+            &state,
+            BookCopyForCreate {
+                book_id: 3,
+                ..Default::default()
+            },
+        )
+        .await?;
+        assert_eq!(id, 2);
+
+        let copy = Book::get_copy(&state, id, 3).await?;
+        let code = copy.barcode.unwrap();
+        assert!(barcode::is_valid(&code));
+
+        let found = Book::get_copy_by_barcode(&state, &code).await?.unwrap();
+        assert_eq!((found.book_id, found.id), (3, 2));
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("books"))]
+    fn assigning_missing_barcodes(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        assert_eq!(Book::assign_barcodes(&state).await?, 10);
+        assert_eq!(Book::assign_barcodes(&state).await?, 0);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("books"))]
+    fn listing_labels(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        Book::assign_barcodes(&state).await?;
+
+        let labels = Book::list_labels(&state, &[(1, 1), (2, 1)], None).await?;
+        assert_eq!(labels.len(), 2);
+        assert_eq!(&labels[0].title, "Book 1");
+
+        let since = chrono::Utc::now().date_naive();
+        let labels = Book::list_labels(&state, &[], Some(since)).await?;
+        assert_eq!(labels.len(), 10);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("books"))]
+    fn searching_books(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        let books = Book::search(&state, &BookFilter::default()).await?;
+        assert_eq!(books.len(), 3);
+        assert_eq!((books[0].copies, books[0].available), (5, 5));
+
+        let filter = BookFilter {
+            q: Some("author 2".to_string()),
+            ..Default::default()
+        };
+        let books = Book::search(&state, &filter).await?;
+        assert_eq!(books.len(), 1);
+        assert_eq!(&books[0].book.title, "Book 2");
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("books"))]
+    fn sorting_by_call_number(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        for (id, call_number) in [(1, "823.9 ADA"), (2, "823.12 BRO")] {
+            let book = BookForUpdate {
+                call_number: Some(call_number.to_string()),
+                ..Default::default()
+            };
+            Book::update(&state, id, book).await?;
+        }
+
+        let filter = BookFilter {
+            sort: BookSort::CallNumber,
+            ..Default::default()
+        };
+        let books = Book::search(&state, &filter).await?;
+        let ids: Vec<_> = books.iter().map(|b| b.book.id).collect();
+        assert_eq!(ids, [2, 1, 3]);
+
+        let branch = Branch::create(
+            &state,
+            BranchForCreate {
+                name: "Main".to_string(),
+                code: None,
+                address: None,
+            },
+        )
+        .await?;
+        let shelf = Branch::add_shelf(
+            &state,
+            ShelfLocationForCreate {
+                branch_id: branch,
+                floor: None,
+                name: "A".to_string(),
+            },
+        )
+        .await?;
+        for (book_id, copy_id, call_number) in [(1, 1, None), (2, 1, None), (1, 2, Some("5 SMI"))] {
+            let copy = BookCopyForUpdate {
+                shelf_location_id: Some(shelf),
+                call_number: call_number.map(str::to_string),
+                ..Default::default()
+            };
+            Book::update_copy(&state, copy_id, book_id, copy).await?;
+        }
+
+        let copies = Book::shelf_list(&state, shelf).await?;
+        let order: Vec<_> = copies.iter().map(|c| c.call_number.as_deref()).collect();
+        assert_eq!(
+            order,
+            [Some("5 SMI"), Some("823.12 BRO"), Some("823.9 ADA")]
+        );
+
+        Ok(())
+    }
 }
@@ -1,6 +1,6 @@
 use chrono::{NaiveDate, NaiveDateTime};
 use modql::field::{Fields, HasSeaFields};
-use sea_query::{Expr, Iden, Query, SqliteQueryBuilder};
+use sea_query::{Expr, Iden, Order, Query, SqliteQueryBuilder};
 use sea_query_binder::SqlxBinder;
 use serde::{Deserialize, Serialize};
 use sqlx::{query_as_with, FromRow, Type};
@@ -19,6 +19,7 @@ pub struct Borrowing {
     pub due_date: NaiveDate,
     pub return_date: Option<NaiveDate>,
     pub status: BorrowingStatus,
+    pub renewals: i64,
     pub updated_at: Option<NaiveDateTime>,
 }
 
@@ -30,6 +31,8 @@ pub enum BorrowingStatus {
     Borrowed,
     Returned,
     Late,
+    /// The borrower lost the item and was billed for it
+    Lost,
 }
 
 impl From<BorrowingStatus> for sea_query::Value {
@@ -38,6 +41,7 @@ impl From<BorrowingStatus> for sea_query::Value {
             BorrowingStatus::Borrowed => "borrowed".into(),
             BorrowingStatus::Returned => "returned".into(),
             BorrowingStatus::Late => "late".into(),
+            BorrowingStatus::Lost => "lost".into(),
         }
     }
 }
@@ -69,6 +73,8 @@ enum BorrowingIden {
     CopyId,
     BookId,
     Status,
+    DueDate,
+    ReturnDate,
 }
 
 impl Model for Borrowing {
@@ -108,6 +114,30 @@ impl Borrowing {
         super::list_where::<Self, _, _, _>(state, BorrowingIden::Status, status).await
     }
 
+    /// Loans still out that are due on or before `until`, overdue ones included.
+    pub async fn list_due(
+        state: &AppState<super::Engine>,
+        until: NaiveDate,
+    ) -> Result<Vec<Borrowing>> {
+        let mut query = Query::select();
+        query
+            .from(Self::table_ref())
+            .columns(Self::sea_idens())
+            .and_where(
+                Expr::col(BorrowingIden::Status)
+                    .is_in([BorrowingStatus::Borrowed, BorrowingStatus::Late]),
+            )
+            .and_where(Expr::col(BorrowingIden::ReturnDate).is_null())
+            .and_where(Expr::col(BorrowingIden::DueDate).lte(until))
+            .order_by(BorrowingIden::DueDate, Order::Asc);
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let entities = query_as_with::<_, Self, _>(&sql, values)
+            .fetch_all(&state.pool)
+            .await?;
+        Ok(entities)
+    }
+
     pub async fn list_by_book_copy(
         state: &AppState<super::Engine>,
         book_id: i64,
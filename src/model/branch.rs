@@ -0,0 +1,210 @@
+use chrono::NaiveDateTime;
+use modql::field::Fields;
+use sea_query::Iden;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::state::AppState;
+
+use super::{Model, Result};
+
+#[derive(Debug, Serialize, FromRow, Fields)]
+pub struct Branch {
+    pub id: i64,
+    pub name: String,
+    pub code: Option<String>,
+    pub address: Option<String>,
+    pub updated_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Fields)]
+pub struct BranchForCreate {
+    pub name: String,
+    pub code: Option<String>,
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Fields)]
+pub struct BranchForUpdate {
+    pub name: Option<String>,
+    pub code: Option<String>,
+    pub address: Option<String>,
+}
+
+/// A shelf, bay or area within a branch.
+#[derive(Debug, Serialize, FromRow, Fields)]
+pub struct ShelfLocation {
+    pub id: i64,
+    pub branch_id: i64,
+    pub floor: Option<String>,
+    pub name: String,
+    pub updated_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Fields)]
+pub struct ShelfLocationForCreate {
+    #[serde(skip_deserializing)]
+    pub branch_id: i64,
+    pub floor: Option<String>,
+    pub name: String,
+}
+
+#[derive(Debug, Default, Deserialize, Fields)]
+pub struct ShelfLocationForUpdate {
+    pub floor: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Iden)]
+enum BranchIden {
+    BranchId,
+}
+
+impl Model for Branch {
+    const TABLE: &'static str = "Branches";
+}
+
+impl Model for ShelfLocation {
+    const TABLE: &'static str = "ShelfLocations";
+}
+
+impl Branch {
+    pub async fn get(state: &AppState<super::Engine>, id: i64) -> Result<Branch> {
+        super::get::<Self, _>(state, id).await
+    }
+
+    pub async fn create(state: &AppState<super::Engine>, branch: BranchForCreate) -> Result<i64> {
+        super::create::<Self, _>(state, branch).await
+    }
+
+    pub async fn update(
+        state: &AppState<super::Engine>,
+        id: i64,
+        branch: BranchForUpdate,
+    ) -> Result<()> {
+        super::update::<Self, _>(state, id, branch).await
+    }
+
+    pub async fn list(state: &AppState<super::Engine>) -> Result<Vec<Branch>> {
+        super::list::<Self, _>(state).await
+    }
+
+    pub async fn delete(state: &AppState<super::Engine>, id: i64) -> Result<()> {
+        super::delete::<Self>(state, id).await
+    }
+
+    pub async fn get_shelf(state: &AppState<super::Engine>, id: i64) -> Result<ShelfLocation> {
+        super::get::<ShelfLocation, _>(state, id).await
+    }
+
+    pub async fn add_shelf(
+        state: &AppState<super::Engine>,
+        shelf: ShelfLocationForCreate,
+    ) -> Result<i64> {
+        super::create::<ShelfLocation, _>(state, shelf).await
+    }
+
+    pub async fn update_shelf(
+        state: &AppState<super::Engine>,
+        id: i64,
+        shelf: ShelfLocationForUpdate,
+    ) -> Result<()> {
+        super::update::<ShelfLocation, _>(state, id, shelf).await
+    }
+
+    pub async fn delete_shelf(state: &AppState<super::Engine>, id: i64) -> Result<()> {
+        super::delete::<ShelfLocation>(state, id).await
+    }
+
+    pub async fn list_shelves(
+        state: &AppState<super::Engine>,
+        branch_id: i64,
+    ) -> Result<Vec<ShelfLocation>> {
+        super::list_where::<ShelfLocation, _, _, _>(state, BranchIden::BranchId, branch_id).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::SqlitePool;
+
+    use crate::{
+        model::book::{Book, BookCopyForCreate, BookCopyForUpdate, BookFilter},
+        state::test_state,
+    };
+
+    use super::*;
+
+    #[sqlx::test(fixtures("books"))]
+    fn shelving_copies(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        let main = Branch::create(
+            &state,
+            BranchForCreate {
+                name: "Main".to_string(),
+                code: Some("MAIN".to_string()),
+                address: None,
+            },
+        )
+        .await?;
+        let east = Branch::create(
+            &state,
+            BranchForCreate {
+                name: "East".to_string(),
+                code: None,
+                address: None,
+            },
+        )
+        .await?;
+        let shelf = Branch::add_shelf(
+            &state,
+            ShelfLocationForCreate {
+                branch_id: east,
+                floor: Some("1".to_string()),
+                name: "A".to_string(),
+            },
+        )
+        .await?;
+        assert_eq!(Branch::list_shelves(&state, east).await?.len(), 1);
+
+        // a shelf puts the copy in the shelf's branch
+        let copy_id = Book::add_copy(
+            &state,
+            BookCopyForCreate {
+                book_id: 3,
+                branch_id: Some(main),
+                shelf_location_id: Some(shelf),
+                ..Default::default()
+            },
+        )
+        .await?;
+        let copy = Book::get_copy(&state, copy_id, 3).await?;
+        assert_eq!(copy.branch_id, Some(east));
+
+        Book::update_copy(
+            &state,
+            1,
+            1,
+            BookCopyForUpdate {
+                branch_id: Some(main),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        let filter = BookFilter {
+            branch: Some(east),
+            available: true,
+            ..Default::default()
+        };
+        let books = Book::search(&state, &filter).await?;
+        assert_eq!(books.len(), 1);
+        assert_eq!(books[0].book.id, 3);
+        assert_eq!(books[0].available, 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1300 @@
+//! Checkout, check-in and renewal of book copies.
+//!
+//! Each operation runs in its own transaction and reports why it could not
+//! go ahead instead of failing, so a desk can process a stack of items and
+//! get one result per item.
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use modql::{field::HasSeaFields, SIden};
+use sea_query::{Expr, Func, Iden, Order, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
+use serde::{Deserialize, Serialize};
+use sqlx::{query_as_with, query_with, FromRow, SqliteConnection, Type};
+
+use crate::state::AppState;
+
+use super::{
+    book::{Book, BookCopy, BorrowStatus},
+    borrowing::{Borrowing, BorrowingStatus},
+    error::Error,
+    fine::{Fine, FineReason},
+    notification::{notify, Event},
+    reservation::{Reservation, ReservationStatus},
+    user::{User, UserRole},
+    Model, Result,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Action {
+    Checkout,
+    Checkin,
+    #[serde(alias = "renew")]
+    Renewal,
+}
+
+impl From<Action> for sea_query::Value {
+    fn from(val: Action) -> Self {
+        match val {
+            Action::Checkout => "checkout".into(),
+            Action::Checkin => "checkin".into(),
+            Action::Renewal => "renewal".into(),
+        }
+    }
+}
+
+impl sea_query::Nullable for Action {
+    fn null() -> sea_query::Value {
+        sea_query::Value::String(None)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockReason {
+    /// The copy is not on the shelf (lost, reserved without a hold...)
+    NotAvailable,
+    NotOnLoan,
+    OnLoanToAnother,
+    FinesOwed,
+    LoanLimit,
+    RenewalLimit,
+    /// The patron hasn't confirmed their email address yet
+    Unverified,
+}
+
+/// A reservation waiting for a copy.
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub struct Hold {
+    pub reservation_id: i64,
+    pub user_id: i64,
+    /// Where the copy should be sent for collection
+    pub pickup_branch_id: Option<i64>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Outcome {
+    Success {
+        action: Action,
+        borrowing_id: i64,
+        /// New due date of checkouts and renewals
+        due_date: Option<NaiveDate>,
+        /// Hold the copy should be put aside for after a check-in
+        hold: Option<Hold>,
+    },
+    Blocked {
+        action: Action,
+        reason: BlockReason,
+    },
+    /// The copy is held for another patron
+    HoldWaiting {
+        action: Action,
+        hold: Hold,
+    },
+    /// A lost or missing copy turned up and is back in circulation
+    Found {
+        action: Action,
+        /// The loan it was lost on
+        borrowing_id: Option<i64>,
+        /// Replacement fee already paid and owed back to the borrower
+        refund: Option<f64>,
+        hold: Option<Hold>,
+    },
+    NotFound,
+    /// No patron holds the card given with the item
+    PatronNotFound,
+}
+
+/// Fee billed for a lost item.
+#[derive(Debug, Serialize)]
+pub struct Replacement {
+    pub borrowing_id: i64,
+    pub fine_id: i64,
+    pub amount: f64,
+}
+
+/// Result of a single scanned barcode.
+#[derive(Debug, Serialize)]
+pub struct ItemResult {
+    pub barcode: String,
+    pub book_id: Option<i64>,
+    pub copy_id: Option<i64>,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+#[derive(Iden)]
+enum CirculationIden {
+    Id,
+    UserId,
+    BookId,
+    CopyId,
+    Status,
+    BorrowDate,
+    DueDate,
+    ReturnDate,
+    Renewals,
+    TransactionId,
+    FineAmount,
+    Paid,
+    ReservationId,
+    PickupBranchId,
+    StatusReason,
+    Reason,
+    ReplacementCost,
+    WorkId,
+    AnyEdition,
+    ReadyDate,
+    Role,
+    EmailVerifiedAt,
+}
+
+pub struct Circulation;
+
+impl Circulation {
+    /// Process scanned copy barcodes at the desk.
+    ///
+    /// Without a patron every item is checked in. With a patron, items they
+    /// already have are renewed and everything else is checked out to them.
+    pub async fn scan(
+        state: &AppState<super::Engine>,
+        user_id: Option<i64>,
+        barcodes: &[String],
+        today: NaiveDate,
+    ) -> Result<Vec<ItemResult>> {
+        let mut results = vec![];
+        for barcode in barcodes {
+            let Some(copy) = Book::get_copy_by_barcode(state, barcode).await? else {
+                results.push(ItemResult {
+                    barcode: barcode.clone(),
+                    book_id: None,
+                    copy_id: None,
+                    outcome: Outcome::NotFound,
+                });
+                continue;
+            };
+
+            let outcome = match user_id {
+                None => Self::checkin(state, copy.book_id, copy.id, today).await?,
+                Some(user_id) => {
+                    // given back before renewing or checking out, which need
+                    // a connection of their own
+                    let loan = {
+                        let mut conn = state.pool.acquire().await?;
+                        active_loan(&mut conn, copy.book_id, copy.id).await?
+                    };
+                    match loan {
+                        Some(loan) if loan.user_id == user_id => {
+                            Self::renew(state, user_id, copy.book_id, copy.id, today).await?
+                        }
+                        _ => Self::checkout(state, user_id, copy.book_id, copy.id, today).await?,
+                    }
+                }
+            };
+
+            results.push(ItemResult {
+                barcode: barcode.clone(),
+                book_id: Some(copy.book_id),
+                copy_id: Some(copy.id),
+                outcome,
+            });
+        }
+
+        Ok(results)
+    }
+
+    pub async fn checkout(
+        state: &AppState<super::Engine>,
+        user_id: i64,
+        book_id: i64,
+        copy_id: i64,
+        today: NaiveDate,
+    ) -> Result<Outcome> {
+        let config = &state.config.circulation;
+        let action = Action::Checkout;
+        let mut tx = state.pool.begin().await?;
+
+        let Some(copy) = get_copy(&mut tx, book_id, copy_id).await? else {
+            return Ok(Outcome::NotFound);
+        };
+        if let Some(loan) = active_loan(&mut tx, book_id, copy_id).await? {
+            let reason = match loan.user_id == user_id {
+                true => BlockReason::NotAvailable,
+                false => BlockReason::OnLoanToAnother,
+            };
+            return Ok(Outcome::Blocked { action, reason });
+        }
+        let hold = waiting_hold(&mut tx, book_id, copy_id).await?;
+        match (&hold, copy.status) {
+            (Some(hold), _) if hold.user_id != user_id => {
+                return Ok(Outcome::HoldWaiting {
+                    action,
+                    hold: hold.clone(),
+                })
+            }
+            (Some(_), Some(BorrowStatus::Available | BorrowStatus::Reserved)) => {}
+            (None, Some(BorrowStatus::Available)) => {}
+            _ => {
+                return Ok(Outcome::Blocked {
+                    action,
+                    reason: BlockReason::NotAvailable,
+                })
+            }
+        }
+        if let Some(reason) = patron_block(&mut tx, state, user_id, action).await? {
+            return Ok(Outcome::Blocked { action, reason });
+        }
+
+        let due_date = today + Duration::days(config.loan_days);
+        let mut query = Query::insert();
+        query
+            .into_table(Borrowing::table_ref())
+            .columns([
+                CirculationIden::UserId,
+                CirculationIden::BookId,
+                CirculationIden::CopyId,
+                CirculationIden::BorrowDate,
+                CirculationIden::DueDate,
+            ])
+            .values([
+                user_id.into(),
+                book_id.into(),
+                copy_id.into(),
+                today.into(),
+                due_date.into(),
+            ])?
+            .returning_col(CirculationIden::Id);
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let (borrowing_id,) = query_as_with::<_, (i64,), _>(&sql, values)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        set_copy_status(&mut tx, book_id, copy_id, BorrowStatus::Borrowed).await?;
+        if let Some(hold) = hold {
+            set_reservation_status(&mut tx, hold.reservation_id, ReservationStatus::Fulfilled)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(Outcome::Success {
+            action,
+            borrowing_id,
+            due_date: Some(due_date),
+            hold: None,
+        })
+    }
+
+    pub async fn checkin(
+        state: &AppState<super::Engine>,
+        book_id: i64,
+        copy_id: i64,
+        today: NaiveDate,
+    ) -> Result<Outcome> {
+        let action = Action::Checkin;
+        let mut tx = state.pool.begin().await?;
+
+        let Some(loan) = active_loan(&mut tx, book_id, copy_id).await? else {
+            let copy = get_copy(&mut tx, book_id, copy_id).await?;
+            if let Some(BorrowStatus::Lost | BorrowStatus::Missing) = copy.and_then(|c| c.status) {
+                let outcome = found(&mut tx, state, book_id, copy_id, today).await?;
+                tx.commit().await?;
+                return Ok(outcome);
+            }
+            return Ok(Outcome::Blocked {
+                action,
+                reason: BlockReason::NotOnLoan,
+            });
+        };
+
+        // the `update_borrowing_return_and_status` trigger marks late returns
+        let mut query = Query::update();
+        query
+            .table(Borrowing::table_ref())
+            .values([
+                (CirculationIden::Status, BorrowingStatus::Returned.into()),
+                (CirculationIden::ReturnDate, today.into()),
+            ])
+            .and_where(Expr::col(CirculationIden::Id).eq(loan.id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        let hold = trap_hold(&mut tx, book_id, copy_id).await?;
+        let status = match &hold {
+            Some(hold) => {
+                let copy = get_copy(&mut tx, book_id, copy_id).await?;
+                let branch_id = copy.and_then(|copy| copy.branch_id);
+                ready_hold(&mut tx, state, hold, book_id, branch_id, today).await?;
+                BorrowStatus::Reserved
+            }
+            None => BorrowStatus::Available,
+        };
+        set_copy_status(&mut tx, book_id, copy_id, status).await?;
+
+        tx.commit().await?;
+
+        Ok(Outcome::Success {
+            action,
+            borrowing_id: loan.id,
+            due_date: None,
+            hold,
+        })
+    }
+
+    pub async fn renew(
+        state: &AppState<super::Engine>,
+        user_id: i64,
+        book_id: i64,
+        copy_id: i64,
+        today: NaiveDate,
+    ) -> Result<Outcome> {
+        let config = &state.config.circulation;
+        let action = Action::Renewal;
+        let mut tx = state.pool.begin().await?;
+
+        let loan = match active_loan(&mut tx, book_id, copy_id).await? {
+            Some(loan) if loan.user_id == user_id => loan,
+            _ => {
+                return Ok(Outcome::Blocked {
+                    action,
+                    reason: BlockReason::NotOnLoan,
+                })
+            }
+        };
+        if loan.renewals >= config.max_renewals {
+            return Ok(Outcome::Blocked {
+                action,
+                reason: BlockReason::RenewalLimit,
+            });
+        }
+        if let Some(hold) = waiting_hold(&mut tx, book_id, copy_id).await? {
+            return Ok(Outcome::HoldWaiting { action, hold });
+        }
+        if let Some(reason) = patron_block(&mut tx, state, user_id, action).await? {
+            return Ok(Outcome::Blocked { action, reason });
+        }
+
+        let due_date = loan.due_date.max(today + Duration::days(config.loan_days));
+        let mut query = Query::update();
+        query
+            .table(Borrowing::table_ref())
+            .values([
+                (CirculationIden::DueDate, due_date.into()),
+                (CirculationIden::Renewals, (loan.renewals + 1).into()),
+            ])
+            .and_where(Expr::col(CirculationIden::Id).eq(loan.id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        tx.commit().await?;
+
+        Ok(Outcome::Success {
+            action,
+            borrowing_id: loan.id,
+            due_date: Some(due_date),
+            hold: None,
+        })
+    }
+
+    /// Mark the item of a loan as lost and bill its borrower for it.
+    ///
+    /// The fee is the book's replacement cost, or the configured one when it
+    /// has none. Checking the copy in later reverses it.
+    pub async fn declare_lost(
+        state: &AppState<super::Engine>,
+        borrowing_id: i64,
+    ) -> Result<Replacement> {
+        let config = &state.config.circulation;
+        let mut tx = state.pool.begin().await?;
+
+        let mut query = Query::select();
+        query
+            .from(Borrowing::table_ref())
+            .columns(Borrowing::sea_idens())
+            .and_where(Expr::col(CirculationIden::Id).eq(borrowing_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let loan = query_as_with::<_, Borrowing, _>(&sql, values)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(Error::EntityNotFound {
+                entity: Borrowing::TABLE,
+                id: borrowing_id,
+            })?;
+        // late returns are marked late too, only those still out count
+        let on_loan = matches!(
+            loan.status,
+            BorrowingStatus::Borrowed | BorrowingStatus::Late
+        ) && loan.return_date.is_none();
+        if !on_loan {
+            return Err(Error::Conflict("Only items on loan can be declared lost"));
+        }
+
+        let mut query = Query::select();
+        query
+            .column(CirculationIden::ReplacementCost)
+            .from(Book::table_ref())
+            .and_where(Expr::col(CirculationIden::Id).eq(loan.book_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let (cost,) = query_as_with::<_, (Option<f64>,), _>(&sql, values)
+            .fetch_one(&mut *tx)
+            .await?;
+        let amount = cost.unwrap_or(config.replacement_cost);
+
+        let mut query = Query::update();
+        query
+            .table(Borrowing::table_ref())
+            .value(CirculationIden::Status, BorrowingStatus::Lost)
+            .and_where(Expr::col(CirculationIden::Id).eq(loan.id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        set_copy_status(&mut tx, loan.book_id, loan.copy_id, BorrowStatus::Lost).await?;
+
+        let mut query = Query::insert();
+        query
+            .into_table(Fine::table_ref())
+            .columns([
+                CirculationIden::TransactionId,
+                CirculationIden::FineAmount,
+                CirculationIden::Reason,
+            ])
+            .values([
+                loan.id.into(),
+                amount.into(),
+                FineReason::Replacement.into(),
+            ])?
+            .returning_col(CirculationIden::Id);
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let (fine_id,) = query_as_with::<_, (i64,), _>(&sql, values)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let event = Event::FineIssued {
+            fine_id,
+            book_id: loan.book_id,
+            amount,
+        };
+        notify(&mut tx, state, loan.user_id, event, true).await?;
+
+        tx.commit().await?;
+
+        Ok(Replacement {
+            borrowing_id: loan.id,
+            fine_id,
+            amount,
+        })
+    }
+
+    /// Take a copy off (or put it back on) the shelf, e.g. damaged, in repair
+    /// or withdrawn.
+    ///
+    /// Statuses driven by loans, holds and transfers can't be set this way,
+    /// nor can copies currently in one of them be changed. Lost copies go
+    /// back on the shelf by being checked in, which reverses the fee.
+    pub async fn change_status(
+        state: &AppState<super::Engine>,
+        book_id: i64,
+        copy_id: i64,
+        status: BorrowStatus,
+        reason: Option<String>,
+    ) -> Result<()> {
+        if status.is_circulating() {
+            return Err(Error::Conflict("Status is set by circulation"));
+        }
+        let mut tx = state.pool.begin().await?;
+
+        let copy = get_copy(&mut tx, book_id, copy_id)
+            .await?
+            .ok_or(Error::EntityNotFound {
+                entity: BookCopy::TABLE,
+                id: copy_id,
+            })?;
+        match copy.status {
+            Some(current) if current.is_circulating() => {
+                return Err(Error::Conflict("Copy is on loan, on hold or in transit"))
+            }
+            Some(BorrowStatus::Lost) if matches!(status, BorrowStatus::Available) => {
+                return Err(Error::Conflict(
+                    "Lost copies are put back by checking them in",
+                ))
+            }
+            _ => {}
+        }
+
+        let mut query = Query::update();
+        query
+            .table(BookCopy::table_ref())
+            .values([
+                (CirculationIden::Status, status.into()),
+                (CirculationIden::StatusReason, reason.into()),
+            ])
+            .and_where(Expr::col(CirculationIden::BookId).eq(book_id))
+            .and_where(Expr::col(CirculationIden::Id).eq(copy_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// Put a lost or missing copy back in circulation, closing the loan it was
+/// lost on and reversing the replacement fee.
+async fn found(
+    conn: &mut SqliteConnection,
+    state: &AppState<super::Engine>,
+    book_id: i64,
+    copy_id: i64,
+    today: NaiveDate,
+) -> Result<Outcome> {
+    let mut query = Query::select();
+    query
+        .from(Borrowing::table_ref())
+        .columns(Borrowing::sea_idens())
+        .and_where(Expr::col(CirculationIden::BookId).eq(book_id))
+        .and_where(Expr::col(CirculationIden::CopyId).eq(copy_id))
+        .and_where(Expr::col(CirculationIden::Status).eq(BorrowingStatus::Lost))
+        .order_by(CirculationIden::Id, Order::Desc)
+        .limit(1);
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let loan = query_as_with::<_, Borrowing, _>(&sql, values)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    let mut refund = None;
+    if let Some(loan) = &loan {
+        let mut query = Query::update();
+        query
+            .table(Borrowing::table_ref())
+            .values([
+                (CirculationIden::Status, BorrowingStatus::Returned.into()),
+                (CirculationIden::ReturnDate, today.into()),
+            ])
+            .and_where(Expr::col(CirculationIden::Id).eq(loan.id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *conn).await?;
+
+        // unpaid fees are dropped, paid ones are owed back
+        let mut query = Query::delete();
+        query
+            .from_table(Fine::table_ref())
+            .and_where(Expr::col(CirculationIden::TransactionId).eq(loan.id))
+            .and_where(Expr::col(CirculationIden::Reason).eq(FineReason::Replacement))
+            .and_where(Expr::col(CirculationIden::Paid).eq(false));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *conn).await?;
+
+        let mut query = Query::select();
+        query
+            .expr(Func::sum(Expr::col(CirculationIden::FineAmount)))
+            .from(Fine::table_ref())
+            .and_where(Expr::col(CirculationIden::TransactionId).eq(loan.id))
+            .and_where(Expr::col(CirculationIden::Reason).eq(FineReason::Replacement));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        (refund,) = query_as_with::<_, (Option<f64>,), _>(&sql, values)
+            .fetch_one(&mut *conn)
+            .await?;
+    }
+
+    let hold = trap_hold(conn, book_id, copy_id).await?;
+    let status = match &hold {
+        Some(hold) => {
+            let copy = get_copy(conn, book_id, copy_id).await?;
+            let branch_id = copy.and_then(|copy| copy.branch_id);
+            ready_hold(conn, state, hold, book_id, branch_id, today).await?;
+            BorrowStatus::Reserved
+        }
+        None => BorrowStatus::Available,
+    };
+    set_copy_status(conn, book_id, copy_id, status).await?;
+
+    Ok(Outcome::Found {
+        action: Action::Checkin,
+        borrowing_id: loan.map(|l| l.id),
+        refund,
+        hold,
+    })
+}
+
+pub(super) async fn get_copy(
+    conn: &mut SqliteConnection,
+    book_id: i64,
+    copy_id: i64,
+) -> Result<Option<BookCopy>> {
+    let mut query = Query::select();
+    query
+        .from(BookCopy::table_ref())
+        .columns(BookCopy::sea_idens())
+        .and_where(Expr::col(CirculationIden::BookId).eq(book_id))
+        .and_where(Expr::col(CirculationIden::Id).eq(copy_id));
+
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let copy = query_as_with::<_, BookCopy, _>(&sql, values)
+        .fetch_optional(conn)
+        .await?;
+    Ok(copy)
+}
+
+/// The loan currently out on a copy.
+async fn active_loan(
+    conn: &mut SqliteConnection,
+    book_id: i64,
+    copy_id: i64,
+) -> Result<Option<Borrowing>> {
+    let mut query = Query::select();
+    query
+        .from(Borrowing::table_ref())
+        .columns(Borrowing::sea_idens())
+        .and_where(Expr::col(CirculationIden::BookId).eq(book_id))
+        .and_where(Expr::col(CirculationIden::CopyId).eq(copy_id))
+        .and_where(
+            Expr::col(CirculationIden::Status)
+                .is_in([BorrowingStatus::Borrowed, BorrowingStatus::Late]),
+        )
+        .and_where(Expr::col(CirculationIden::ReturnDate).is_null());
+
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let loan = query_as_with::<_, Borrowing, _>(&sql, values)
+        .fetch_optional(conn)
+        .await?;
+    Ok(loan)
+}
+
+/// The oldest open reservation on a copy.
+pub(super) async fn waiting_hold(
+    conn: &mut SqliteConnection,
+    book_id: i64,
+    copy_id: i64,
+) -> Result<Option<Hold>> {
+    let mut query = Query::select();
+    query
+        .expr_as(
+            Expr::col(CirculationIden::Id),
+            CirculationIden::ReservationId,
+        )
+        .columns([CirculationIden::UserId, CirculationIden::PickupBranchId])
+        .from(Reservation::table_ref())
+        .and_where(Expr::col(CirculationIden::BookId).eq(book_id))
+        .and_where(Expr::col(CirculationIden::CopyId).eq(copy_id))
+        .and_where(
+            Expr::col(CirculationIden::Status)
+                .is_in([ReservationStatus::Pending, ReservationStatus::Active]),
+        )
+        .order_by(CirculationIden::Id, Order::Asc)
+        .limit(1);
+
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let hold = query_as_with::<_, Hold, _>(&sql, values)
+        .fetch_optional(conn)
+        .await?;
+    Ok(hold)
+}
+
+/// The hold a copy coming back should be put aside for.
+///
+/// Without a hold on the copy itself, the oldest any edition hold in its
+/// book's work is moved onto it, unless that hold already has a copy put
+/// aside.
+async fn trap_hold(
+    conn: &mut SqliteConnection,
+    book_id: i64,
+    copy_id: i64,
+) -> Result<Option<Hold>> {
+    if let Some(hold) = waiting_hold(conn, book_id, copy_id).await? {
+        return Ok(Some(hold));
+    }
+
+    let reservations = SIden(Reservation::TABLE);
+    let book_copies = SIden(BookCopy::TABLE);
+    let work = Query::select()
+        .column(CirculationIden::WorkId)
+        .from(Book::table_ref())
+        .and_where(Expr::col(CirculationIden::Id).eq(book_id))
+        .to_owned();
+    let editions = Query::select()
+        .column(CirculationIden::Id)
+        .from(Book::table_ref())
+        .and_where(Expr::col(CirculationIden::WorkId).in_subquery(work))
+        .to_owned();
+
+    let mut query = Query::select();
+    query
+        .expr_as(
+            Expr::col((reservations, CirculationIden::Id)),
+            CirculationIden::ReservationId,
+        )
+        .columns([
+            (reservations, CirculationIden::UserId),
+            (reservations, CirculationIden::PickupBranchId),
+        ])
+        .from(Reservation::table_ref())
+        .inner_join(
+            BookCopy::table_ref(),
+            Expr::col((book_copies, CirculationIden::BookId))
+                .equals((reservations, CirculationIden::BookId))
+                .and(
+                    Expr::col((book_copies, CirculationIden::Id))
+                        .equals((reservations, CirculationIden::CopyId)),
+                ),
+        )
+        .and_where(Expr::col((reservations, CirculationIden::AnyEdition)).eq(true))
+        .and_where(Expr::col((reservations, CirculationIden::BookId)).in_subquery(editions))
+        .and_where(
+            Expr::col((reservations, CirculationIden::Status))
+                .is_in([ReservationStatus::Pending, ReservationStatus::Active]),
+        )
+        .and_where(Expr::col((book_copies, CirculationIden::Status)).ne(BorrowStatus::Reserved))
+        .order_by((reservations, CirculationIden::Id), Order::Asc)
+        .limit(1);
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let Some(hold) = query_as_with::<_, Hold, _>(&sql, values)
+        .fetch_optional(&mut *conn)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let mut query = Query::update();
+    query
+        .table(Reservation::table_ref())
+        .values([
+            (CirculationIden::BookId, book_id.into()),
+            (CirculationIden::CopyId, copy_id.into()),
+        ])
+        .and_where(Expr::col(CirculationIden::Id).eq(hold.reservation_id));
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    query_with(&sql, values).execute(&mut *conn).await?;
+
+    Ok(Some(hold))
+}
+
+/// Make `hold` ready for collection and tell its patron, when the copy put
+/// aside for it is at their pickup branch. Holds without a pickup branch can
+/// be collected anywhere.
+pub(super) async fn ready_hold(
+    conn: &mut SqliteConnection,
+    state: &AppState<super::Engine>,
+    hold: &Hold,
+    book_id: i64,
+    branch_id: Option<i64>,
+    today: NaiveDate,
+) -> Result<()> {
+    if hold
+        .pickup_branch_id
+        .is_some_and(|pickup| Some(pickup) != branch_id)
+    {
+        return Ok(());
+    }
+
+    let mut query = Query::update();
+    query
+        .table(Reservation::table_ref())
+        .values([
+            (CirculationIden::Status, ReservationStatus::Active.into()),
+            (CirculationIden::ReadyDate, today.into()),
+        ])
+        .and_where(Expr::col(CirculationIden::Id).eq(hold.reservation_id));
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    query_with(&sql, values).execute(&mut *conn).await?;
+
+    let event = Event::HoldReady {
+        reservation_id: hold.reservation_id,
+        book_id,
+        until: today + Duration::days(state.config.circulation.hold_pickup_days),
+    };
+    notify(conn, state, hold.user_id, event, true).await?;
+    Ok(())
+}
+
+/// Why a patron may not take out (or renew) items, if they may not.
+async fn patron_block(
+    conn: &mut SqliteConnection,
+    state: &AppState<super::Engine>,
+    user_id: i64,
+    action: Action,
+) -> Result<Option<BlockReason>> {
+    let config = &state.config.circulation;
+    let fines = SIden(Fine::TABLE);
+    let borrowing = SIden(Borrowing::TABLE);
+
+    // admins vouch for themselves
+    let mut query = Query::select();
+    query
+        .columns([CirculationIden::Role, CirculationIden::EmailVerifiedAt])
+        .from(User::table_ref())
+        .and_where(Expr::col(CirculationIden::Id).eq(user_id));
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let patron = query_as_with::<_, (UserRole, Option<NaiveDateTime>), _>(&sql, values)
+        .fetch_optional(&mut *conn)
+        .await?;
+    if let Some((role, None)) = patron {
+        if role != UserRole::Admin {
+            return Ok(Some(BlockReason::Unverified));
+        }
+    }
+
+    let mut query = Query::select();
+    query
+        .expr(Func::coalesce([
+            Func::sum(Expr::col((fines, CirculationIden::FineAmount))).into(),
+            Expr::val(0.0).into(),
+        ]))
+        .from(Fine::table_ref())
+        .inner_join(
+            Borrowing::table_ref(),
+            Expr::col((borrowing, CirculationIden::Id))
+                .equals((fines, CirculationIden::TransactionId)),
+        )
+        .and_where(Expr::col((borrowing, CirculationIden::UserId)).eq(user_id))
+        .and_where(Expr::col((fines, CirculationIden::Paid)).eq(false));
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let (owed,) = query_as_with::<_, (f64,), _>(&sql, values)
+        .fetch_one(&mut *conn)
+        .await?;
+    if owed > config.fine_limit {
+        return Ok(Some(BlockReason::FinesOwed));
+    }
+
+    if action == Action::Checkout {
+        let mut query = Query::select();
+        query
+            .expr(Expr::col(CirculationIden::Id).count())
+            .from(Borrowing::table_ref())
+            .and_where(Expr::col(CirculationIden::UserId).eq(user_id))
+            .and_where(
+                Expr::col(CirculationIden::Status)
+                    .is_in([BorrowingStatus::Borrowed, BorrowingStatus::Late]),
+            )
+            .and_where(Expr::col(CirculationIden::ReturnDate).is_null());
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let (loans,) = query_as_with::<_, (i64,), _>(&sql, values)
+            .fetch_one(&mut *conn)
+            .await?;
+        if loans >= config.max_loans {
+            return Ok(Some(BlockReason::LoanLimit));
+        }
+    }
+
+    Ok(None)
+}
+
+pub(super) async fn set_copy_status(
+    conn: &mut SqliteConnection,
+    book_id: i64,
+    copy_id: i64,
+    status: BorrowStatus,
+) -> Result<()> {
+    // reasons only apply to statuses set by staff
+    let mut query = Query::update();
+    query
+        .table(BookCopy::table_ref())
+        .values([
+            (CirculationIden::Status, status.into()),
+            (CirculationIden::StatusReason, Option::<String>::None.into()),
+        ])
+        .and_where(Expr::col(CirculationIden::BookId).eq(book_id))
+        .and_where(Expr::col(CirculationIden::Id).eq(copy_id));
+
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    query_with(&sql, values).execute(conn).await?;
+    Ok(())
+}
+
+pub(super) async fn set_reservation_status(
+    conn: &mut SqliteConnection,
+    id: i64,
+    status: ReservationStatus,
+) -> Result<()> {
+    let mut query = Query::update();
+    query
+        .table(Reservation::table_ref())
+        .value(CirculationIden::Status, status)
+        .and_where(Expr::col(CirculationIden::Id).eq(id));
+
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    query_with(&sql, values).execute(conn).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use sqlx::{
+        sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+        SqlitePool,
+    };
+
+    use crate::{
+        model::{
+            book::{BookFilter, BookForUpdate},
+            reservation::ReservationForCreate,
+            user::UserForUpdate,
+            work::Work,
+        },
+        state::test_state,
+    };
+
+    use super::*;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, 1).unwrap()
+    }
+
+    #[sqlx::test(fixtures("users", "books"))]
+    fn checkout_renew_checkin(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        let outcome = Circulation::checkout(&state, 1, 1, 1, today()).await?;
+        assert!(matches!(
+            outcome,
+            Outcome::Success {
+                action: Action::Checkout,
+                ..
+            }
+        ));
+        let copy = Book::get_copy(&state, 1, 1).await?;
+        assert!(matches!(copy.status, Some(BorrowStatus::Borrowed)));
+
+        // someone else can't take it
+        let outcome = Circulation::checkout(&state, 2, 1, 1, today()).await?;
+        assert_eq!(
+            outcome,
+            Outcome::Blocked {
+                action: Action::Checkout,
+                reason: BlockReason::OnLoanToAnother
+            }
+        );
+
+        let outcome = Circulation::renew(&state, 1, 1, 1, today()).await?;
+        assert!(matches!(
+            outcome,
+            Outcome::Success {
+                action: Action::Renewal,
+                ..
+            }
+        ));
+
+        let outcome = Circulation::checkin(&state, 1, 1, today()).await?;
+        assert!(matches!(
+            outcome,
+            Outcome::Success {
+                action: Action::Checkin,
+                hold: None,
+                ..
+            }
+        ));
+        let copy = Book::get_copy(&state, 1, 1).await?;
+        assert!(matches!(copy.status, Some(BorrowStatus::Available)));
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("users", "books"))]
+    fn unverified_members_cannot_borrow(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        let unverify = |email: &str| UserForUpdate {
+            email: Some(email.to_string()),
+            ..Default::default()
+        };
+        User::update(&state, 1, unverify("jdoe@localhost")).await?;
+        User::update(&state, 3, unverify("root@localhost")).await?;
+
+        let outcome = Circulation::checkout(&state, 1, 1, 1, today()).await?;
+        assert_eq!(
+            outcome,
+            Outcome::Blocked {
+                action: Action::Checkout,
+                reason: BlockReason::Unverified
+            }
+        );
+        // admins aren't held up by it
+        let outcome = Circulation::checkout(&state, 3, 1, 1, today()).await?;
+        assert!(matches!(outcome, Outcome::Success { .. }));
+
+        User::verify_email(&state, 1, "jdoe@localhost").await?;
+        let outcome = Circulation::checkout(&state, 1, 1, 2, today()).await?;
+        assert!(matches!(outcome, Outcome::Success { .. }));
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("users", "books"))]
+    fn holds_are_kept_for_their_patron(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        Circulation::checkout(&state, 1, 1, 1, today()).await?;
+        let reservation_id = Reservation::create(
+            &state,
+            ReservationForCreate {
+                copy_id: 1,
+                book_id: 1,
+                user_id: 2,
+                reservation_date: None,
+                pickup_branch_id: None,
+                any_edition: false,
+            },
+        )
+        .await?;
+        let hold = Hold {
+            reservation_id,
+            user_id: 2,
+            pickup_branch_id: None,
+        };
+
+        // no renewals while someone is waiting
+        let outcome = Circulation::renew(&state, 1, 1, 1, today()).await?;
+        assert_eq!(
+            outcome,
+            Outcome::HoldWaiting {
+                action: Action::Renewal,
+                hold: hold.clone()
+            }
+        );
+
+        let outcome = Circulation::checkin(&state, 1, 1, today()).await?;
+        assert!(matches!(outcome, Outcome::Success { hold: Some(_), .. }));
+
+        let outcome = Circulation::checkout(&state, 3, 1, 1, today()).await?;
+        assert_eq!(
+            outcome,
+            Outcome::HoldWaiting {
+                action: Action::Checkout,
+                hold
+            }
+        );
+
+        let outcome = Circulation::checkout(&state, 2, 1, 1, today()).await?;
+        assert!(matches!(outcome, Outcome::Success { .. }));
+        let reservation = Reservation::get(&state, reservation_id).await?;
+        assert!(matches!(reservation.status, ReservationStatus::Fulfilled));
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("users", "books"))]
+    fn holds_on_any_edition(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        Work::merge(&state, &[1, 3]).await?;
+
+        let reservation_id = Reservation::create(
+            &state,
+            ReservationForCreate {
+                copy_id: 1,
+                book_id: 1,
+                user_id: 2,
+                reservation_date: None,
+                pickup_branch_id: None,
+                any_edition: true,
+            },
+        )
+        .await?;
+
+        // book 3's only copy is another edition of the work
+        Circulation::checkout(&state, 1, 3, 1, today()).await?;
+        let outcome = Circulation::checkin(&state, 3, 1, today()).await?;
+        assert!(matches!(outcome, Outcome::Success { hold: Some(_), .. }));
+        let copy = Book::get_copy(&state, 1, 3).await?;
+        assert!(matches!(copy.status, Some(BorrowStatus::Reserved)));
+
+        // book 2 is not in the work, and the hold has its copy already
+        for (book_id, copy_id) in [(2, 1), (1, 2)] {
+            Circulation::checkout(&state, 1, book_id, copy_id, today()).await?;
+            let outcome = Circulation::checkin(&state, book_id, copy_id, today()).await?;
+            assert!(matches!(outcome, Outcome::Success { hold: None, .. }));
+        }
+
+        let outcome = Circulation::checkout(&state, 2, 3, 1, today()).await?;
+        assert!(matches!(outcome, Outcome::Success { .. }));
+        let reservation = Reservation::get(&state, reservation_id).await?;
+        assert!(matches!(reservation.status, ReservationStatus::Fulfilled));
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("users", "books"))]
+    fn scanning_at_the_desk(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        Book::assign_barcodes(&state).await?;
+        let barcode = Book::get_copy(&state, 1, 1).await?.barcode.unwrap();
+        let barcodes = [barcode.clone(), "unknown".to_string()];
+
+        let results = Circulation::scan(&state, Some(1), &barcodes, today()).await?;
+        assert!(matches!(
+            results[0].outcome,
+            Outcome::Success {
+                action: Action::Checkout,
+                ..
+            }
+        ));
+        assert_eq!(results[1].outcome, Outcome::NotFound);
+
+        let results = Circulation::scan(&state, Some(1), &barcodes[..1], today()).await?;
+        assert!(matches!(
+            results[0].outcome,
+            Outcome::Success {
+                action: Action::Renewal,
+                ..
+            }
+        ));
+
+        let results = Circulation::scan(&state, None, &barcodes[..1], today()).await?;
+        assert!(matches!(
+            results[0].outcome,
+            Outcome::Success {
+                action: Action::Checkin,
+                ..
+            }
+        ));
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("users", "books"))]
+    fn scanning_on_one_connection(
+        pool_opts: SqlitePoolOptions,
+        connect_opts: SqliteConnectOptions,
+    ) -> Result<()> {
+        let pool = pool_opts
+            .max_connections(1)
+            .connect_with(connect_opts)
+            .await?;
+        let state = test_state(pool);
+        Book::assign_barcodes(&state).await?;
+        let barcodes = [Book::get_copy(&state, 1, 1).await?.barcode.unwrap()];
+
+        // a connection held across the item would wait on itself forever
+        for _ in 0..2 {
+            let scan = Circulation::scan(&state, Some(1), &barcodes, today());
+            let results = tokio::time::timeout(Duration::from_secs(5), scan)
+                .await
+                .expect("scan is stuck")?;
+            assert!(matches!(results[0].outcome, Outcome::Success { .. }));
+        }
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("users", "books"))]
+    fn losing_and_finding_a_copy(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        Book::update(
+            &state,
+            1,
+            BookForUpdate {
+                replacement_cost: Some(30.0),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        let Outcome::Success { borrowing_id, .. } =
+            Circulation::checkout(&state, 1, 1, 1, today()).await?
+        else {
+            panic!("checkout failed");
+        };
+        let replacement = Circulation::declare_lost(&state, borrowing_id).await?;
+        assert_eq!(replacement.amount, 30.0);
+        assert!(matches!(
+            Circulation::declare_lost(&state, borrowing_id).await,
+            Err(Error::Conflict(_))
+        ));
+        let copy = Book::get_copy(&state, 1, 1).await?;
+        assert!(matches!(copy.status, Some(BorrowStatus::Lost)));
+
+        // the fee is owed until the copy turns up
+        let outcome = Circulation::checkout(&state, 1, 1, 2, today()).await?;
+        assert_eq!(
+            outcome,
+            Outcome::Blocked {
+                action: Action::Checkout,
+                reason: BlockReason::FinesOwed
+            }
+        );
+        let books = Book::search(&state, &BookFilter::default()).await?;
+        assert_eq!((books[0].copies, books[0].available), (4, 4));
+
+        let outcome = Circulation::checkin(&state, 1, 1, today()).await?;
+        assert_eq!(
+            outcome,
+            Outcome::Found {
+                action: Action::Checkin,
+                borrowing_id: Some(borrowing_id),
+                refund: None,
+                hold: None
+            }
+        );
+        assert!(Fine::get(&state, replacement.fine_id).await.is_err());
+        let loan = Borrowing::get(&state, borrowing_id).await?;
+        assert!(matches!(loan.status, BorrowingStatus::Returned));
+        let outcome = Circulation::checkout(&state, 1, 1, 2, today()).await?;
+        assert!(matches!(outcome, Outcome::Success { .. }));
+
+        // staff can't take a copy on loan off the shelf
+        assert!(matches!(
+            Circulation::change_status(&state, 1, 2, BorrowStatus::Damaged, None).await,
+            Err(Error::Conflict(_))
+        ));
+        Circulation::change_status(
+            &state,
+            1,
+            3,
+            BorrowStatus::Damaged,
+            Some("water damage".to_string()),
+        )
+        .await?;
+        let copy = Book::get_copy(&state, 3, 1).await?;
+        assert_eq!(copy.status_reason.as_deref(), Some("water damage"));
+        let outcome = Circulation::checkout(&state, 2, 1, 3, today()).await?;
+        assert_eq!(
+            outcome,
+            Outcome::Blocked {
+                action: Action::Checkout,
+                reason: BlockReason::NotAvailable
+            }
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("users", "books"))]
+    fn handling_late_loans(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        let mut loans = vec![];
+        for copy_id in 1..=3 {
+            let Outcome::Success { borrowing_id, .. } =
+                Circulation::checkout(&state, 1, 1, copy_id, today()).await?
+            else {
+                panic!("checkout failed");
+            };
+            loans.push(borrowing_id);
+        }
+        sqlx::query("UPDATE Borrowing SET status = 'late'")
+            .execute(&state.pool)
+            .await?;
+
+        // overdue loans are still out, they can be returned or lost
+        let outcome = Circulation::checkin(&state, 1, 1, today()).await?;
+        assert!(matches!(outcome, Outcome::Success { .. }));
+        Circulation::declare_lost(&state, loans[1]).await?;
+
+        // a late return is marked late as well, but it's back
+        sqlx::query("UPDATE Borrowing SET status = 'late' WHERE id = ?")
+            .bind(loans[0])
+            .execute(&state.pool)
+            .await?;
+        assert!(matches!(
+            Circulation::declare_lost(&state, loans[0]).await,
+            Err(Error::Conflict(_))
+        ));
+        assert!(matches!(
+            Circulation::checkin(&state, 1, 1, today()).await?,
+            Outcome::Blocked {
+                reason: BlockReason::NotOnLoan,
+                ..
+            }
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,467 @@
+use chrono::NaiveDateTime;
+use modql::{
+    field::{Fields, HasSeaFields},
+    SIden,
+};
+use sea_query::{Expr, Iden, OnConflict, Order, Query, SimpleExpr, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
+use serde::{Deserialize, Serialize};
+use sqlx::{query_as_with, query_with, FromRow, SqliteConnection, Type};
+
+use crate::state::AppState;
+
+use super::{book::Book, Model, Result};
+
+/// Separators between names in an author string, as in "A & B".
+const NAME_SEPARATORS: [&str; 3] = [";", " & ", " and "];
+
+#[derive(Debug, Serialize, FromRow, Fields)]
+pub struct Contributor {
+    pub id: i64,
+    pub name: String,
+    pub updated_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Fields)]
+pub struct ContributorForUpdate {
+    pub name: String,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum ContributorRole {
+    #[default]
+    Author,
+    Editor,
+    Translator,
+    Illustrator,
+}
+
+impl From<ContributorRole> for sea_query::Value {
+    fn from(val: ContributorRole) -> Self {
+        match val {
+            ContributorRole::Author => "author".into(),
+            ContributorRole::Editor => "editor".into(),
+            ContributorRole::Translator => "translator".into(),
+            ContributorRole::Illustrator => "illustrator".into(),
+        }
+    }
+}
+
+impl sea_query::Nullable for ContributorRole {
+    fn null() -> sea_query::Value {
+        sea_query::Value::String(None)
+    }
+}
+
+/// A contributor as credited on a book.
+#[derive(Debug, Serialize, FromRow)]
+pub struct Credit {
+    pub contributor_id: i64,
+    pub name: String,
+    pub role: ContributorRole,
+    pub position: i64,
+}
+
+/// A credit to give on a book, contributors are matched by name (ignoring
+/// case) and added when new.
+#[derive(Debug, Deserialize)]
+pub struct CreditForSet {
+    pub name: String,
+    #[serde(default)]
+    pub role: ContributorRole,
+}
+
+/// A book and what the contributor did on it.
+#[derive(Debug, Serialize, FromRow)]
+pub struct ContributedBook {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub book: Book,
+    pub role: ContributorRole,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ContributorFilter {
+    /// Part of the name
+    pub q: Option<String>,
+}
+
+pub(super) struct BookContributor;
+
+#[derive(Iden)]
+enum ContributorIden {
+    Id,
+    Name,
+    BookId,
+    ContributorId,
+    Role,
+    Position,
+    Author,
+}
+
+impl Model for Contributor {
+    const TABLE: &'static str = "Contributors";
+}
+
+impl Model for BookContributor {
+    const TABLE: &'static str = "BookContributors";
+}
+
+impl Contributor {
+    pub async fn get(state: &AppState<super::Engine>, id: i64) -> Result<Contributor> {
+        super::get::<Self, _>(state, id).await
+    }
+
+    pub async fn update(
+        state: &AppState<super::Engine>,
+        id: i64,
+        contributor: ContributorForUpdate,
+    ) -> Result<()> {
+        super::update::<Self, _>(state, id, contributor).await
+    }
+
+    pub async fn list(
+        state: &AppState<super::Engine>,
+        filter: &ContributorFilter,
+    ) -> Result<Vec<Contributor>> {
+        let db = &state.pool;
+
+        let mut query = Query::select();
+        query
+            .from(Self::table_ref())
+            .columns(Self::sea_idens())
+            .order_by(ContributorIden::Name, Order::Asc);
+        if let Some(q) = filter.q.as_deref().filter(|q| !q.is_empty()) {
+            query.and_where(Expr::col(ContributorIden::Name).like(format!("%{q}%")));
+        }
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let contributors = query_as_with::<_, Self, _>(&sql, values)
+            .fetch_all(db)
+            .await?;
+
+        Ok(contributors)
+    }
+
+    /// Every book the contributor is credited on.
+    pub async fn books(state: &AppState<super::Engine>, id: i64) -> Result<Vec<ContributedBook>> {
+        let db = &state.pool;
+        let books = SIden(Book::TABLE);
+        let credits = SIden(BookContributor::TABLE);
+
+        let mut query = Query::select();
+        query
+            .columns(Book::sea_column_refs_with_rel(books))
+            .column((credits, ContributorIden::Role))
+            .from(Book::table_ref())
+            .inner_join(
+                BookContributor::table_ref(),
+                Expr::col((credits, ContributorIden::BookId)).equals((books, ContributorIden::Id)),
+            )
+            .and_where(Expr::col((credits, ContributorIden::ContributorId)).eq(id))
+            .order_by((books, ContributorIden::Id), Order::Asc);
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let books = query_as_with::<_, ContributedBook, _>(&sql, values)
+            .fetch_all(db)
+            .await?;
+
+        Ok(books)
+    }
+
+    /// Contributors credited on a book, in credit order.
+    pub async fn for_book(state: &AppState<super::Engine>, book_id: i64) -> Result<Vec<Credit>> {
+        let db = &state.pool;
+        let contributors = SIden(Self::TABLE);
+        let credits = SIden(BookContributor::TABLE);
+
+        let mut query = Query::select();
+        query
+            .column((credits, ContributorIden::ContributorId))
+            .column((contributors, ContributorIden::Name))
+            .columns([
+                (credits, ContributorIden::Role),
+                (credits, ContributorIden::Position),
+            ])
+            .from(BookContributor::table_ref())
+            .inner_join(
+                Self::table_ref(),
+                Expr::col((contributors, ContributorIden::Id))
+                    .equals((credits, ContributorIden::ContributorId)),
+            )
+            .and_where(Expr::col((credits, ContributorIden::BookId)).eq(book_id))
+            .order_by((credits, ContributorIden::Position), Order::Asc);
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let credits = query_as_with::<_, Credit, _>(&sql, values)
+            .fetch_all(db)
+            .await?;
+
+        Ok(credits)
+    }
+
+    /// Replace the credits of a book.
+    ///
+    /// The book's author string is rewritten from the authors so it keeps
+    /// matching, unless none are given.
+    pub async fn set_for_book(
+        state: &AppState<super::Engine>,
+        book_id: i64,
+        credits: &[CreditForSet],
+    ) -> Result<()> {
+        let mut tx = state.pool.begin().await?;
+
+        let mut query = Query::delete();
+        query
+            .from_table(BookContributor::table_ref())
+            .and_where(Expr::col(ContributorIden::BookId).eq(book_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        for (i, credit) in credits.iter().enumerate() {
+            credit_book(&mut tx, book_id, &credit.name, credit.role, i as i64 + 1).await?;
+        }
+
+        let authors: Vec<&str> = credits
+            .iter()
+            .filter(|c| c.role == ContributorRole::Author)
+            .map(|c| c.name.trim())
+            .collect();
+        if !authors.is_empty() {
+            let mut query = Query::update();
+            query
+                .table(Book::table_ref())
+                .value(ContributorIden::Author, authors.join("; "))
+                .and_where(Expr::col(ContributorIden::Id).eq(book_id));
+            let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+            query_with(&sql, values).execute(&mut *tx).await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Credit the names in a book's author string as its authors, replacing
+    /// the previous ones. Other roles are kept.
+    pub async fn link_authors(
+        state: &AppState<super::Engine>,
+        book_id: i64,
+        author: &str,
+    ) -> Result<()> {
+        let mut tx = state.pool.begin().await?;
+
+        let mut query = Query::delete();
+        query
+            .from_table(BookContributor::table_ref())
+            .and_where(Expr::col(ContributorIden::BookId).eq(book_id))
+            .and_where(Expr::col(ContributorIden::Role).eq(ContributorRole::Author));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        for (i, name) in split_names(author).iter().enumerate() {
+            credit_book(
+                &mut tx,
+                book_id,
+                name,
+                ContributorRole::Author,
+                i as i64 + 1,
+            )
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// Names in an author string such as "Terry Pratchett & Neil Gaiman".
+pub fn split_names(author: &str) -> Vec<String> {
+    let mut names = vec![author.to_string()];
+    for separator in NAME_SEPARATORS {
+        names = names
+            .iter()
+            .flat_map(|name| name.split(separator))
+            .map(str::to_string)
+            .collect();
+    }
+    names
+        .into_iter()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Condition on `Books` rows matching books with a contributor whose name is
+/// like `pattern`.
+pub(super) fn credited_like(pattern: &str) -> SimpleExpr {
+    let books = SIden(Book::TABLE);
+    let contributors = SIden(Contributor::TABLE);
+    let credits = SIden(BookContributor::TABLE);
+
+    let mut query = Query::select();
+    query
+        .expr(Expr::val(1))
+        .from(BookContributor::table_ref())
+        .inner_join(
+            Contributor::table_ref(),
+            Expr::col((contributors, ContributorIden::Id))
+                .equals((credits, ContributorIden::ContributorId)),
+        )
+        .and_where(
+            Expr::col((credits, ContributorIden::BookId)).equals((books, ContributorIden::Id)),
+        )
+        .and_where(Expr::col((contributors, ContributorIden::Name)).like(pattern));
+    Expr::exists(query)
+}
+
+async fn credit_book(
+    conn: &mut SqliteConnection,
+    book_id: i64,
+    name: &str,
+    role: ContributorRole,
+    position: i64,
+) -> Result<()> {
+    let name = name.trim();
+
+    let mut query = Query::insert();
+    query
+        .into_table(Contributor::table_ref())
+        .columns([ContributorIden::Name])
+        .values([name.into()])?
+        .on_conflict(
+            OnConflict::column(ContributorIden::Name)
+                .do_nothing()
+                .to_owned(),
+        );
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    query_with(&sql, values).execute(&mut *conn).await?;
+
+    // names are compared ignoring case, the existing spelling is kept
+    let mut query = Query::select();
+    query
+        .column(ContributorIden::Id)
+        .from(Contributor::table_ref())
+        .and_where(Expr::col(ContributorIden::Name).eq(name));
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let (contributor_id,) = query_as_with::<_, (i64,), _>(&sql, values)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let mut query = Query::insert();
+    query
+        .into_table(BookContributor::table_ref())
+        .columns([
+            ContributorIden::BookId,
+            ContributorIden::ContributorId,
+            ContributorIden::Role,
+            ContributorIden::Position,
+        ])
+        .values([
+            book_id.into(),
+            contributor_id.into(),
+            role.into(),
+            position.into(),
+        ])?
+        .on_conflict(
+            OnConflict::columns([
+                ContributorIden::BookId,
+                ContributorIden::ContributorId,
+                ContributorIden::Role,
+            ])
+            .do_nothing()
+            .to_owned(),
+        );
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    query_with(&sql, values).execute(&mut *conn).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::SqlitePool;
+
+    use crate::{
+        model::book::{BookFilter, BookForCreate},
+        state::test_state,
+    };
+
+    use super::*;
+
+    #[test]
+    fn splitting_names() {
+        assert_eq!(
+            split_names("Terry Pratchett & Neil Gaiman"),
+            ["Terry Pratchett", "Neil Gaiman"]
+        );
+        assert_eq!(
+            split_names("Kernighan and Ritchie; Knuth ;"),
+            ["Kernighan", "Ritchie", "Knuth"]
+        );
+        assert_eq!(split_names("Tolkien, J. R. R."), ["Tolkien, J. R. R."]);
+    }
+
+    #[sqlx::test(fixtures("books"))]
+    fn crediting_contributors(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        let id = Book::create(
+            &state,
+            BookForCreate {
+                title: "Good Omens".to_string(),
+                author: "Terry Pratchett & Neil Gaiman".to_string(),
+                isbn: "9780060853983".to_string(),
+                category: None,
+                year: None,
+                photo: None,
+                replacement_cost: None,
+                call_number: None,
+                call_number_sort: None,
+                count: 0,
+            },
+        )
+        .await?;
+        let credits = Contributor::for_book(&state, id).await?;
+        let names: Vec<_> = credits.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["Terry Pratchett", "Neil Gaiman"]);
+
+        let credits = [
+            CreditForSet {
+                name: "neil gaiman".to_string(),
+                role: ContributorRole::Author,
+            },
+            CreditForSet {
+                name: "Some Translator".to_string(),
+                role: ContributorRole::Translator,
+            },
+        ];
+        Contributor::set_for_book(&state, 1, &credits).await?;
+        assert_eq!(&Book::get(&state, 1).await?.author, "neil gaiman");
+
+        // the existing spelling is kept
+        let gaiman = Contributor::list(
+            &state,
+            &ContributorFilter {
+                q: Some("gaiman".to_string()),
+            },
+        )
+        .await?;
+        assert_eq!(gaiman.len(), 1);
+        assert_eq!(&gaiman[0].name, "Neil Gaiman");
+        let books = Contributor::books(&state, gaiman[0].id).await?;
+        assert_eq!(books.len(), 2);
+
+        let filter = BookFilter {
+            q: Some("translator".to_string()),
+            ..Default::default()
+        };
+        let books = Book::search(&state, &filter).await?;
+        assert_eq!(books.len(), 1);
+        assert_eq!(books[0].book.id, 1);
+
+        Ok(())
+    }
+}
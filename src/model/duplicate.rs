@@ -0,0 +1,414 @@
+//! Finding and merging duplicate book records.
+//!
+//! Two books are likely the same when their ISBNs match once normalized, or
+//! when their titles and authors are close enough after ignoring case and
+//! punctuation. Merging moves everything attached to the duplicate onto the
+//! book kept and removes the duplicate.
+
+use std::collections::HashSet;
+
+use modql::field::HasSeaFields;
+use sea_query::{Expr, Func, Iden, OnConflict, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
+use serde::Serialize;
+use sqlx::{query, query_as_with, query_with, SqliteConnection};
+
+use crate::{isbn, state::AppState};
+
+use super::{
+    book::{Book, BookCategory, BookCopy},
+    borrowing::Borrowing,
+    contributor::BookContributor,
+    error::Error,
+    reservation::Reservation,
+    review::Review,
+    series::SeriesBook,
+    stocktake::StocktakeScan,
+    transfer::Transfer,
+    Model, Result,
+};
+
+/// Similarity above which books are reported when no other is asked for.
+pub const MIN_SIMILARITY: f64 = 0.85;
+/// How much the title counts in the similarity, the author making up the rest.
+const TITLE_WEIGHT: f64 = 0.7;
+
+/// Two books that look like the same record.
+#[derive(Debug, Serialize)]
+pub struct Duplicate {
+    pub book_ids: [i64; 2],
+    pub titles: [String; 2],
+    /// The ISBNs are the same once normalized
+    pub same_isbn: bool,
+    /// From 0 (nothing alike) to 1 (same title and author)
+    pub similarity: f64,
+}
+
+/// What a merge moved onto the book kept.
+#[derive(Debug, Serialize)]
+pub struct Merged {
+    pub book_id: i64,
+    pub copies: u64,
+}
+
+#[derive(Clone, Copy, Iden)]
+enum DuplicateIden {
+    Id,
+    BookId,
+    CopyId,
+    CategoryId,
+    ContributorId,
+    Role,
+    Position,
+    SeriesId,
+    Volume,
+    WorkId,
+}
+
+/// A book prepared for comparison.
+struct Candidate {
+    book: Book,
+    isbn: Option<String>,
+    title: HashSet<[char; 2]>,
+    author: HashSet<[char; 2]>,
+}
+
+impl Duplicate {
+    /// Pairs of books at least `min_similarity` alike or with the same ISBN,
+    /// most alike first.
+    pub async fn find(
+        state: &AppState<super::Engine>,
+        min_similarity: f64,
+    ) -> Result<Vec<Duplicate>> {
+        let candidates: Vec<Candidate> = Book::list(state)
+            .await?
+            .into_iter()
+            .map(|book| Candidate {
+                isbn: isbn_key(&book.isbn),
+                title: bigrams(&book.title),
+                author: bigrams(&book.author),
+                book,
+            })
+            .collect();
+
+        let mut duplicates = vec![];
+        for (i, a) in candidates.iter().enumerate() {
+            for b in &candidates[i + 1..] {
+                let same_isbn = a.isbn.is_some() && a.isbn == b.isbn;
+                let similarity = TITLE_WEIGHT * dice(&a.title, &b.title)
+                    + (1.0 - TITLE_WEIGHT) * dice(&a.author, &b.author);
+                if same_isbn || similarity >= min_similarity {
+                    duplicates.push(Duplicate {
+                        book_ids: [a.book.id, b.book.id],
+                        titles: [a.book.title.clone(), b.book.title.clone()],
+                        same_isbn,
+                        similarity,
+                    });
+                }
+            }
+        }
+        duplicates.sort_by(|a, b| {
+            b.same_isbn
+                .cmp(&a.same_isbn)
+                .then(b.similarity.total_cmp(&a.similarity))
+        });
+
+        Ok(duplicates)
+    }
+
+    /// Move the copies, loans, holds, reviews and other records of
+    /// `duplicate_id` onto `book_id` and delete the duplicate, all or nothing.
+    ///
+    /// Copies are numbered after the kept book's own.
+    pub async fn merge(
+        state: &AppState<super::Engine>,
+        book_id: i64,
+        duplicate_id: i64,
+    ) -> Result<Merged> {
+        if book_id == duplicate_id {
+            return Err(Error::Conflict("A book can't be merged into itself"));
+        }
+
+        let mut tx = state.pool.begin().await?;
+        // copies and the rows pointing at them change key one table at a
+        // time, references are checked once everything has moved
+        query("PRAGMA defer_foreign_keys = ON")
+            .execute(&mut *tx)
+            .await?;
+
+        let mut work_ids = vec![];
+        for id in [book_id, duplicate_id] {
+            let mut query = Query::select();
+            query
+                .column(DuplicateIden::WorkId)
+                .from(Book::table_ref())
+                .and_where(Expr::col(DuplicateIden::Id).eq(id));
+            let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+            let (work_id,) = query_as_with::<_, (Option<i64>,), _>(&sql, values)
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or(Error::EntityNotFound {
+                    entity: Book::TABLE,
+                    id,
+                })?;
+            work_ids.push(work_id);
+        }
+
+        let mut query = Query::select();
+        query
+            .expr(Func::coalesce([
+                Func::max(Expr::col(DuplicateIden::Id)).into(),
+                Expr::val(0).into(),
+            ]))
+            .from(BookCopy::table_ref())
+            .and_where(Expr::col(DuplicateIden::BookId).eq(book_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let (offset,) = query_as_with::<_, (i64,), _>(&sql, values)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let mut query = Query::update();
+        query
+            .table(BookCopy::table_ref())
+            .values([
+                (DuplicateIden::BookId, book_id.into()),
+                (DuplicateIden::Id, Expr::col(DuplicateIden::Id).add(offset)),
+            ])
+            .and_where(Expr::col(DuplicateIden::BookId).eq(duplicate_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let copies = query_with(&sql, values)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+        for table in [
+            Borrowing::table_ref(),
+            Reservation::table_ref(),
+            Transfer::table_ref(),
+            StocktakeScan::table_ref(),
+        ] {
+            let mut query = Query::update();
+            query
+                .table(table)
+                .values([
+                    (DuplicateIden::BookId, book_id.into()),
+                    (
+                        DuplicateIden::CopyId,
+                        Expr::col(DuplicateIden::CopyId).add(offset),
+                    ),
+                ])
+                .and_where(Expr::col(DuplicateIden::BookId).eq(duplicate_id));
+            let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+            query_with(&sql, values).execute(&mut *tx).await?;
+        }
+
+        let mut query = Query::update();
+        query
+            .table(Review::table_ref())
+            .value(DuplicateIden::BookId, book_id)
+            .and_where(Expr::col(DuplicateIden::BookId).eq(duplicate_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        // links the kept book already has are left to be deleted with the
+        // duplicate
+        copy_links::<BookCategory>(
+            &mut tx,
+            book_id,
+            duplicate_id,
+            &[DuplicateIden::CategoryId],
+            &[DuplicateIden::CategoryId],
+        )
+        .await?;
+        copy_links::<BookContributor>(
+            &mut tx,
+            book_id,
+            duplicate_id,
+            &[
+                DuplicateIden::ContributorId,
+                DuplicateIden::Role,
+                DuplicateIden::Position,
+            ],
+            &[DuplicateIden::ContributorId, DuplicateIden::Role],
+        )
+        .await?;
+        copy_links::<SeriesBook>(
+            &mut tx,
+            book_id,
+            duplicate_id,
+            &[DuplicateIden::SeriesId, DuplicateIden::Volume],
+            &[DuplicateIden::SeriesId],
+        )
+        .await?;
+
+        if let [None, Some(work_id)] = work_ids[..] {
+            let mut query = Query::update();
+            query
+                .table(Book::table_ref())
+                .value(DuplicateIden::WorkId, work_id)
+                .and_where(Expr::col(DuplicateIden::Id).eq(book_id));
+            let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+            query_with(&sql, values).execute(&mut *tx).await?;
+        }
+
+        let mut query = Query::delete();
+        query
+            .from_table(Book::table_ref())
+            .and_where(Expr::col(DuplicateIden::Id).eq(duplicate_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        tx.commit().await?;
+
+        Ok(Merged { book_id, copies })
+    }
+}
+
+/// Copy the rows of a book link table from `duplicate_id` to `book_id`,
+/// skipping those conflicting with the kept book's.
+async fn copy_links<M: Model>(
+    conn: &mut SqliteConnection,
+    book_id: i64,
+    duplicate_id: i64,
+    columns: &[DuplicateIden],
+    key: &[DuplicateIden],
+) -> Result<()> {
+    let mut select = Query::select();
+    select
+        .expr(Expr::val(book_id))
+        .columns(columns.iter().copied())
+        .from(M::table_ref())
+        .and_where(Expr::col(DuplicateIden::BookId).eq(duplicate_id));
+
+    let with_book = |columns: &[DuplicateIden]| {
+        let mut all = vec![DuplicateIden::BookId];
+        all.extend_from_slice(columns);
+        all
+    };
+    let mut query = Query::insert();
+    query
+        .into_table(M::table_ref())
+        .columns(with_book(columns))
+        .select_from(select)?
+        .on_conflict(OnConflict::columns(with_book(key)).do_nothing().to_owned());
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    query_with(&sql, values).execute(&mut *conn).await?;
+    Ok(())
+}
+
+/// The normalized ISBN, or the ISBN stripped of formatting when it is not a
+/// valid one.
+fn isbn_key(isbn: &str) -> Option<String> {
+    isbn::normalize(isbn).or_else(|| {
+        let stripped: String = isbn
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        (!stripped.is_empty()).then_some(stripped)
+    })
+}
+
+/// Pairs of adjacent characters in `s`, ignoring case and punctuation.
+fn bigrams(s: &str) -> HashSet<[char; 2]> {
+    let words: Vec<String> = s
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let chars: Vec<char> = words.join(" ").chars().collect();
+    chars.windows(2).map(|w| [w[0], w[1]]).collect()
+}
+
+/// Sørensen–Dice coefficient of two sets of bigrams.
+fn dice(a: &HashSet<[char; 2]>, b: &HashSet<[char; 2]>) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    2.0 * a.intersection(b).count() as f64 / (a.len() + b.len()) as f64
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Local;
+    use sqlx::SqlitePool;
+
+    use crate::{
+        model::{
+            book::BookForCreate,
+            circulation::{Circulation, Outcome},
+            review::ReviewForCreate,
+        },
+        state::test_state,
+    };
+
+    use super::*;
+
+    #[test]
+    fn comparing_titles() {
+        let a = bigrams("The Lord of the Rings");
+        assert_eq!(dice(&a, &bigrams("the lord of the rings.")), 1.0);
+        assert!(dice(&a, &bigrams("The Lord of the Ring")) > 0.9);
+        assert!(dice(&a, &bigrams("A Game of Thrones")) < 0.5);
+    }
+
+    #[sqlx::test(fixtures("users", "books"))]
+    fn merging_duplicates(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        let duplicate_id = Book::create(
+            &state,
+            BookForCreate {
+                title: "Book 1.".to_string(),
+                author: "author 1".to_string(),
+                isbn: "12-3456-7890".to_string(),
+                category: None,
+                year: None,
+                photo: None,
+                replacement_cost: None,
+                call_number: None,
+                call_number_sort: None,
+                count: 2,
+            },
+        )
+        .await?;
+
+        let duplicates = Duplicate::find(&state, 0.9).await?;
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].book_ids, [1, duplicate_id]);
+        assert!(duplicates[0].same_isbn);
+
+        let today = Local::now().date_naive();
+        let Outcome::Success { borrowing_id, .. } =
+            Circulation::checkout(&state, 1, duplicate_id, 2, today).await?
+        else {
+            panic!("checkout failed");
+        };
+        Review::create(
+            &state,
+            ReviewForCreate {
+                user_id: 1,
+                book_id: duplicate_id,
+                rating: 4,
+                review_text: "Good".to_string(),
+            },
+        )
+        .await?;
+
+        assert!(matches!(
+            Duplicate::merge(&state, 1, 1).await,
+            Err(Error::Conflict(_))
+        ));
+        let merged = Duplicate::merge(&state, 1, duplicate_id).await?;
+        assert_eq!(merged.copies, 2);
+
+        assert!(Book::get(&state, duplicate_id).await.is_err());
+        assert_eq!(Book::list_copies(&state, 1).await?.len(), 7);
+        let loan = Borrowing::get(&state, borrowing_id).await?;
+        assert_eq!((loan.book_id, loan.copy_id), (1, 7));
+        assert_eq!(Review::list_by_book(&state, 1).await?.len(), 1);
+        assert!(Duplicate::find(&state, 0.9).await?.is_empty());
+
+        Ok(())
+    }
+}
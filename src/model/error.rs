@@ -12,4 +12,7 @@ pub enum Error {
     CountFail,
     #[error("Error hashing password {0}")]
     Hash(String),
+    /// The request clashes with the current state of the entity
+    #[error("{0}")]
+    Conflict(&'static str),
 }
@@ -1,11 +1,18 @@
 use chrono::{NaiveDate, NaiveDateTime};
-use modql::field::Fields;
+use modql::field::{Fields, HasSeaFields};
+use sea_query::{Expr, Iden, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
 use serde::{Deserialize, Serialize};
-use sqlx::prelude::FromRow;
+use sqlx::{prelude::FromRow, query_as_with, Type};
 
 use crate::state::AppState;
 
-use super::{Model, Result};
+use super::{
+    borrowing::Borrowing,
+    error::Error,
+    notification::{notify, Event},
+    Model, Result,
+};
 
 #[derive(Debug, Serialize, FromRow, Fields)]
 pub struct Fine {
@@ -14,15 +21,42 @@ pub struct Fine {
     pub fine_amount: f64,
     pub paid: bool,
     pub paid_date: Option<NaiveDate>,
+    pub reason: FineReason,
     pub updated_at: Option<NaiveDateTime>,
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum FineReason {
+    #[default]
+    Overdue,
+    /// Cost of replacing a lost item
+    Replacement,
+}
+
+impl From<FineReason> for sea_query::Value {
+    fn from(val: FineReason) -> Self {
+        match val {
+            FineReason::Overdue => "overdue".into(),
+            FineReason::Replacement => "replacement".into(),
+        }
+    }
+}
+
+impl sea_query::Nullable for FineReason {
+    fn null() -> sea_query::Value {
+        sea_query::Value::String(None)
+    }
+}
+
 #[derive(Debug, Deserialize, Fields)]
 pub struct FineForCreate {
     pub transaction_id: u64,
     pub fine_amount: f64,
     pub paid: Option<bool>,
     pub paid_date: Option<NaiveDate>,
+    pub reason: Option<FineReason>,
 }
 
 #[derive(Debug, Deserialize, Fields)]
@@ -32,6 +66,13 @@ pub struct FineForUpdate {
     pub paid_date: Option<NaiveDate>,
 }
 
+#[derive(Iden)]
+enum FineIden {
+    Id,
+    UserId,
+    BookId,
+}
+
 impl Model for Fine {
     const TABLE: &'static str = "Fines";
 }
@@ -49,8 +90,46 @@ impl Fine {
         super::update::<Self, _>(state, id, fine).await
     }
 
-    pub async fn create(state: &AppState<super::Engine>, fine: FineForUpdate) -> Result<i64> {
-        super::create::<Self, _>(state, fine).await
+    /// Issue a fine on a loan and let its borrower know.
+    pub async fn create(state: &AppState<super::Engine>, fine: FineForCreate) -> Result<i64> {
+        let mut tx = state.pool.begin().await?;
+
+        let mut query = Query::select();
+        query
+            .columns([FineIden::UserId, FineIden::BookId])
+            .from(Borrowing::table_ref())
+            .and_where(Expr::col(FineIden::Id).eq(fine.transaction_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let (user_id, book_id) = query_as_with::<_, (i64, i64), _>(&sql, values)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(Error::EntityNotFound {
+                entity: Borrowing::TABLE,
+                id: fine.transaction_id as i64,
+            })?;
+
+        let amount = fine.fine_amount;
+        let (columns, values) = fine.not_none_sea_fields().for_sea_insert();
+        let mut query = Query::insert();
+        query
+            .into_table(Self::table_ref())
+            .columns(columns)
+            .values(values)?
+            .returning_col(FineIden::Id);
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let (id,) = query_as_with::<_, (i64,), _>(&sql, values)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let event = Event::FineIssued {
+            fine_id: id,
+            book_id,
+            amount,
+        };
+        notify(&mut tx, state, user_id, event, true).await?;
+
+        tx.commit().await?;
+        Ok(id)
     }
 
     pub async fn list(state: &AppState<super::Engine>) -> Result<Vec<Fine>> {
@@ -1,6 +1,6 @@
 -- Add test users
-INSERT INTO users (name, username, email, password, role)
+INSERT INTO users (name, username, email, password, role, email_verified_at)
 VALUES
-  ('John Doe', 'johndoe', 'johndoe@localhost', 'password123', 'member'),
-  ('Jane Doe', 'janedoe', 'janedoe@localhost', 'password456', 'issuer'),
-  ('Mark Smith', 'marks', 'marks@localhost', 'password789', 'admin');
+  ('John Doe', 'johndoe', 'johndoe@localhost', 'password123', 'member', CURRENT_TIMESTAMP),
+  ('Jane Doe', 'janedoe', 'janedoe@localhost', 'password456', 'issuer', CURRENT_TIMESTAMP),
+  ('Mark Smith', 'marks', 'marks@localhost', 'password789', 'admin', CURRENT_TIMESTAMP);
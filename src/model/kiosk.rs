@@ -0,0 +1,218 @@
+use chrono::NaiveDateTime;
+use modql::field::{Fields, HasSeaFields};
+use sea_query::{Expr, Iden, Order, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
+use serde::{Deserialize, Serialize};
+use sqlx::{query_as_with, query_with, FromRow};
+use uuid::Uuid;
+
+use crate::state::AppState;
+
+use super::{circulation::Action, Model, Result};
+
+#[derive(Debug, Serialize, FromRow, Fields)]
+pub struct Kiosk {
+    pub id: i64,
+    pub name: String,
+    pub location: Option<String>,
+    pub active: bool,
+    /// `jti` of its current token, older ones are refused
+    #[serde(skip)]
+    pub token_id: Option<String>,
+    pub updated_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Fields)]
+pub struct KioskForCreate {
+    pub name: String,
+    pub location: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Fields)]
+pub struct KioskForUpdate {
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub active: Option<bool>,
+}
+
+#[derive(Debug, Serialize, FromRow, Fields)]
+pub struct KioskActivity {
+    pub id: i64,
+    pub kiosk_id: i64,
+    pub user_id: Option<i64>,
+    pub action: Action,
+    pub barcode: Option<String>,
+    pub result: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Fields)]
+pub struct KioskActivityForCreate {
+    pub kiosk_id: i64,
+    pub user_id: Option<i64>,
+    pub action: Action,
+    pub barcode: Option<String>,
+    pub result: String,
+}
+
+#[derive(Iden)]
+enum KioskIden {
+    Id,
+    KioskId,
+    TokenId,
+}
+
+impl Model for Kiosk {
+    const TABLE: &'static str = "Kiosks";
+}
+
+impl Model for KioskActivity {
+    const TABLE: &'static str = "KioskActivity";
+}
+
+impl Kiosk {
+    pub async fn get(state: &AppState<super::Engine>, id: i64) -> Result<Kiosk> {
+        super::get::<Self, _>(state, id).await
+    }
+
+    pub async fn create(state: &AppState<super::Engine>, kiosk: KioskForCreate) -> Result<i64> {
+        super::create::<Self, _>(state, kiosk).await
+    }
+
+    pub async fn update(
+        state: &AppState<super::Engine>,
+        id: i64,
+        kiosk: KioskForUpdate,
+    ) -> Result<()> {
+        super::update::<Self, _>(state, id, kiosk).await
+    }
+
+    pub async fn list(state: &AppState<super::Engine>) -> Result<Vec<Kiosk>> {
+        super::list::<Self, _>(state).await
+    }
+
+    /// Id for a new token of the kiosk, the previous token stops working.
+    pub async fn new_token_id(state: &AppState<super::Engine>, id: i64) -> Result<String> {
+        let db = &state.pool;
+
+        let token_id = Uuid::new_v4().to_string();
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .value(KioskIden::TokenId, &token_id)
+            .and_where(Expr::col(KioskIden::Id).eq(id));
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        match query_with(&sql, values).execute(db).await?.rows_affected() {
+            0 => Err(super::error::Error::EntityNotFound {
+                entity: Self::TABLE,
+                id,
+            }),
+            _ => Ok(token_id),
+        }
+    }
+
+    pub async fn log(
+        state: &AppState<super::Engine>,
+        activity: KioskActivityForCreate,
+    ) -> Result<i64> {
+        super::create::<KioskActivity, _>(state, activity).await
+    }
+
+    /// Activity of a kiosk, most recent first.
+    pub async fn list_activity(
+        state: &AppState<super::Engine>,
+        kiosk_id: i64,
+    ) -> Result<Vec<KioskActivity>> {
+        let db = &state.pool;
+
+        let mut query = Query::select();
+        query
+            .from(KioskActivity::table_ref())
+            .columns(KioskActivity::sea_idens())
+            .and_where(Expr::col(KioskIden::KioskId).eq(kiosk_id))
+            .order_by(KioskIden::Id, Order::Desc);
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let activity = query_as_with::<_, KioskActivity, _>(&sql, values)
+            .fetch_all(db)
+            .await?;
+
+        Ok(activity)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::SqlitePool;
+
+    use crate::state::test_state;
+
+    use super::*;
+
+    #[sqlx::test(fixtures("users"))]
+    fn kiosk_activity(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        let id = Kiosk::create(
+            &state,
+            KioskForCreate {
+                name: "Entrance".to_string(),
+                location: None,
+            },
+        )
+        .await?;
+        assert!(Kiosk::get(&state, id).await?.active);
+
+        for result in ["wrong_pin", "success"] {
+            Kiosk::log(
+                &state,
+                KioskActivityForCreate {
+                    kiosk_id: id,
+                    user_id: Some(1),
+                    action: Action::Checkout,
+                    barcode: None,
+                    result: result.to_string(),
+                },
+            )
+            .await?;
+        }
+        let activity = Kiosk::list_activity(&state, id).await?;
+        assert_eq!(activity.len(), 2);
+        assert_eq!(activity[0].result, "success");
+
+        Kiosk::update(
+            &state,
+            id,
+            KioskForUpdate {
+                active: Some(false),
+                ..Default::default()
+            },
+        )
+        .await?;
+        assert!(!Kiosk::get(&state, id).await?.active);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    fn reissuing_tokens(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        let id = Kiosk::create(
+            &state,
+            KioskForCreate {
+                name: "Entrance".to_string(),
+                location: None,
+            },
+        )
+        .await?;
+        let first = Kiosk::new_token_id(&state, id).await?;
+        let second = Kiosk::new_token_id(&state, id).await?;
+        assert_ne!(first, second);
+        assert_eq!(Kiosk::get(&state, id).await?.token_id, Some(second));
+        assert!(Kiosk::new_token_id(&state, id + 1).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,335 @@
+//! Failed logins, counted by username and by client address, and kiosk
+//! sign ins by card number and kiosk, so guessing passwords and PINs gets
+//! slower with every try and ends in a lockout. Password reset requests
+//! are slowed down the same way, so they can't be used to flood inboxes.
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use sea_query::{Expr, Iden, OnConflict, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
+use serde::{Deserialize, Serialize};
+use sqlx::{query_as_with, query_with, FromRow, Type};
+
+use crate::state::AppState;
+
+use super::{Model, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum ThrottleScope {
+    Username,
+    Ip,
+    Card,
+    Kiosk,
+    ResetEmail,
+    ResetIp,
+}
+
+impl ThrottleScope {
+    /// Accounts get locked out, addresses and kiosks are only slowed down,
+    /// they may be shared by a whole library
+    fn locks(self) -> bool {
+        matches!(self, Self::Username | Self::Card)
+    }
+}
+
+impl From<ThrottleScope> for sea_query::Value {
+    fn from(val: ThrottleScope) -> Self {
+        match val {
+            ThrottleScope::Username => "username".into(),
+            ThrottleScope::Ip => "ip".into(),
+            ThrottleScope::Card => "card".into(),
+            ThrottleScope::Kiosk => "kiosk".into(),
+            ThrottleScope::ResetEmail => "reset_email".into(),
+            ThrottleScope::ResetIp => "reset_ip".into(),
+        }
+    }
+}
+
+impl sea_query::Nullable for ThrottleScope {
+    fn null() -> sea_query::Value {
+        sea_query::Value::String(None)
+    }
+}
+
+#[derive(Debug, FromRow)]
+pub struct LoginThrottle {
+    pub scope: ThrottleScope,
+    pub key: String,
+    pub failures: i64,
+    pub last_failed_at: NaiveDateTime,
+    pub blocked_until: Option<NaiveDateTime>,
+    /// Only ever set for usernames and cards
+    pub locked_until: Option<NaiveDateTime>,
+}
+
+/// Whether a login may be tried now.
+#[derive(Debug, PartialEq)]
+pub enum Throttle {
+    Open,
+    /// Too soon after the last failure
+    Backoff {
+        retry_after: i64,
+    },
+    Locked {
+        retry_after: i64,
+    },
+}
+
+#[derive(Iden)]
+enum LoginThrottleIden {
+    Scope,
+    Key,
+    Failures,
+    LastFailedAt,
+    BlockedUntil,
+    LockedUntil,
+}
+
+impl Model for LoginThrottle {
+    const TABLE: &'static str = "LoginThrottles";
+}
+
+impl LoginThrottle {
+    async fn get(
+        state: &AppState<super::Engine>,
+        scope: ThrottleScope,
+        key: &str,
+    ) -> Result<Option<Self>> {
+        let mut query = Query::select();
+        query
+            .columns([
+                LoginThrottleIden::Scope,
+                LoginThrottleIden::Key,
+                LoginThrottleIden::Failures,
+                LoginThrottleIden::LastFailedAt,
+                LoginThrottleIden::BlockedUntil,
+                LoginThrottleIden::LockedUntil,
+            ])
+            .from(Self::table_ref())
+            .and_where(Expr::col(LoginThrottleIden::Scope).eq(scope))
+            .and_where(Expr::col(LoginThrottleIden::Key).eq(key));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        Ok(query_as_with(&sql, values)
+            .fetch_optional(&state.pool)
+            .await?)
+    }
+
+    /// Whether a login counted against all of `keys` may be tried now.
+    pub async fn check(
+        state: &AppState<super::Engine>,
+        keys: &[(ThrottleScope, &str)],
+    ) -> Result<Throttle> {
+        let now = Utc::now().naive_utc();
+        let mut throttles = vec![];
+        for &(scope, key) in keys {
+            throttles.extend(Self::get(state, scope, key).await?);
+        }
+
+        let wait = |until: Option<NaiveDateTime>| {
+            until
+                .filter(|&until| until > now)
+                .map(|until| (until - now).num_seconds().max(1))
+        };
+        if let Some(retry_after) = throttles.iter().filter_map(|t| wait(t.locked_until)).max() {
+            return Ok(Throttle::Locked { retry_after });
+        }
+        match throttles.iter().filter_map(|t| wait(t.blocked_until)).max() {
+            Some(retry_after) => Ok(Throttle::Backoff { retry_after }),
+            None => Ok(Throttle::Open),
+        }
+    }
+
+    /// Count a failed login against each of `keys`, whether the password,
+    /// PIN or second factor was wrong, or there's no such user.
+    pub async fn failed(
+        state: &AppState<super::Engine>,
+        keys: &[(ThrottleScope, &str)],
+    ) -> Result<()> {
+        let config = &state.config.auth;
+        let now = Utc::now().naive_utc();
+        let window = Duration::minutes(config.lockout_minutes);
+
+        let mut tx = state.pool.begin().await?;
+        for &(scope, key) in keys {
+            // counted in one statement, so failures at the same time all add up
+            let mut query = Query::insert();
+            query
+                .into_table(Self::table_ref())
+                .columns([
+                    LoginThrottleIden::Scope,
+                    LoginThrottleIden::Key,
+                    LoginThrottleIden::Failures,
+                    LoginThrottleIden::LastFailedAt,
+                ])
+                .values([scope.into(), key.into(), 1.into(), now.into()])?
+                .on_conflict(
+                    OnConflict::columns([LoginThrottleIden::Scope, LoginThrottleIden::Key])
+                        .value(
+                            LoginThrottleIden::Failures,
+                            Expr::case(
+                                Expr::col(LoginThrottleIden::LastFailedAt).gt(now - window),
+                                Expr::col(LoginThrottleIden::Failures).add(1),
+                            )
+                            .finally(1),
+                        )
+                        .update_column(LoginThrottleIden::LastFailedAt)
+                        .to_owned(),
+                )
+                .returning_col(LoginThrottleIden::Failures);
+            let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+            let (failures,) = query_as_with::<_, (i64,), _>(&sql, values)
+                .fetch_one(&mut *tx)
+                .await?;
+
+            let blocked_until = backoff(
+                failures,
+                config.login_free_attempts,
+                config.login_max_backoff_seconds,
+            )
+            .map(|seconds| now + Duration::seconds(seconds));
+            let locked_until =
+                (scope.locks() && failures >= config.login_max_failures).then_some(now + window);
+            let mut query = Query::update();
+            query
+                .table(Self::table_ref())
+                .value(LoginThrottleIden::BlockedUntil, blocked_until)
+                .value(LoginThrottleIden::LockedUntil, locked_until)
+                .and_where(Expr::col(LoginThrottleIden::Scope).eq(scope))
+                .and_where(Expr::col(LoginThrottleIden::Key).eq(key));
+            let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+            query_with(&sql, values).execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Forget the failures of a username or card, after it signed in or an
+    /// admin unlocked it. Returns `false` when there were none.
+    pub async fn clear(
+        state: &AppState<super::Engine>,
+        scope: ThrottleScope,
+        key: &str,
+    ) -> Result<bool> {
+        let mut query = Query::delete();
+        query
+            .from_table(Self::table_ref())
+            .and_where(Expr::col(LoginThrottleIden::Scope).eq(scope))
+            .and_where(Expr::col(LoginThrottleIden::Key).eq(key));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let result = query_with(&sql, values).execute(&state.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Seconds to wait after the `failures`th failure in a row, doubling with
+/// each one past the free attempts.
+fn backoff(failures: i64, free: i64, max: i64) -> Option<i64> {
+    let past = failures - free;
+    (past > 0).then(|| (1i64 << (past - 1).min(30)).min(max))
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::SqlitePool;
+
+    use crate::state::test_state;
+
+    use super::*;
+
+    fn keys<'a>(username: &'a str, ip: &'a str) -> [(ThrottleScope, &'a str); 2] {
+        [(ThrottleScope::Username, username), (ThrottleScope::Ip, ip)]
+    }
+
+    fn pin_keys<'a>(card: &'a str, kiosk: &'a str) -> [(ThrottleScope, &'a str); 2] {
+        [(ThrottleScope::Card, card), (ThrottleScope::Kiosk, kiosk)]
+    }
+
+    #[test]
+    fn backing_off() {
+        assert_eq!(backoff(3, 3, 300), None);
+        assert_eq!(backoff(4, 3, 300), Some(1));
+        assert_eq!(backoff(6, 3, 300), Some(4));
+        assert_eq!(backoff(20, 3, 300), Some(300));
+        assert_eq!(backoff(100, 3, 300), Some(300));
+    }
+
+    #[sqlx::test]
+    fn throttling_failed_logins(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        let ip = "10.0.0.1";
+
+        for _ in 0..3 {
+            LoginThrottle::failed(&state, &keys("johndoe", ip)).await?;
+        }
+        assert_eq!(
+            LoginThrottle::check(&state, &keys("johndoe", ip)).await?,
+            Throttle::Open
+        );
+        LoginThrottle::failed(&state, &keys("johndoe", ip)).await?;
+        assert!(matches!(
+            LoginThrottle::check(&state, &keys("johndoe", ip)).await?,
+            Throttle::Backoff { .. }
+        ));
+        // the address is slowed down for other usernames too
+        assert!(matches!(
+            LoginThrottle::check(&state, &keys("janedoe", ip)).await?,
+            Throttle::Backoff { .. }
+        ));
+        assert_eq!(
+            LoginThrottle::check(&state, &keys("janedoe", "10.0.0.2")).await?,
+            Throttle::Open
+        );
+
+        // spread over many addresses, the username still gets locked
+        for i in 0..6 {
+            LoginThrottle::failed(&state, &keys("johndoe", &format!("10.0.1.{i}"))).await?;
+        }
+        assert!(matches!(
+            LoginThrottle::check(&state, &keys("johndoe", "10.0.0.3")).await?,
+            Throttle::Locked { retry_after } if retry_after > 29 * 60
+        ));
+
+        assert!(LoginThrottle::clear(&state, ThrottleScope::Username, "johndoe").await?);
+        assert_eq!(
+            LoginThrottle::check(&state, &keys("johndoe", "10.0.0.3")).await?,
+            Throttle::Open
+        );
+
+        // failures long ago don't add up
+        LoginThrottle::failed(&state, &[(ThrottleScope::Username, "janedoe")]).await?;
+        sqlx::query(
+            "UPDATE LoginThrottles SET failures = 9, last_failed_at = datetime('now', '-1 hour')",
+        )
+        .execute(&state.pool)
+        .await?;
+        LoginThrottle::failed(&state, &[(ThrottleScope::Username, "janedoe")]).await?;
+        assert_eq!(
+            LoginThrottle::check(&state, &[(ThrottleScope::Username, "janedoe")]).await?,
+            Throttle::Open
+        );
+        Ok(())
+    }
+
+    #[sqlx::test]
+    fn throttling_pins(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        for i in 0..10 {
+            LoginThrottle::failed(&state, &pin_keys("C0001", &i.to_string())).await?;
+        }
+        assert!(matches!(
+            LoginThrottle::check(&state, &pin_keys("C0001", "99")).await?,
+            Throttle::Locked { .. }
+        ));
+        // a kiosk trying many cards is slowed down, never locked
+        for i in 0..10 {
+            LoginThrottle::failed(&state, &pin_keys(&format!("C1{i:03}"), "1")).await?;
+        }
+        assert!(matches!(
+            LoginThrottle::check(&state, &pin_keys("C0002", "1")).await?,
+            Throttle::Backoff { .. }
+        ));
+        Ok(())
+    }
+}
@@ -13,12 +13,28 @@ use error::Result;
 
 pub mod book;
 pub mod borrowing;
+pub mod branch;
 pub mod category;
+pub mod circulation;
+pub mod contributor;
+pub mod duplicate;
 pub mod error;
 pub mod fine;
+pub mod kiosk;
+pub mod login_throttle;
+pub mod notice;
+pub mod notification;
+pub mod outbox;
+pub mod password_reset;
 pub mod reservation;
 pub mod review;
+pub mod series;
+pub mod session;
+pub mod stocktake;
+pub mod transfer;
+pub mod two_factor;
 pub mod user;
+pub mod work;
 
 pub type Engine = sqlx::Sqlite;
 type Row = sqlx::sqlite::SqliteRow;
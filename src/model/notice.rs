@@ -0,0 +1,298 @@
+use std::collections::HashSet;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use modql::field::{Fields, HasSeaFields};
+use sea_query::{Expr, Iden, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
+use serde::{Deserialize, Serialize};
+use sqlx::{query_as_with, query_with, FromRow, Type};
+
+use crate::{config::NoticeConfig, notify::mail::Email, state::AppState};
+
+use super::{
+    borrowing::Borrowing,
+    notification::{notify, Event},
+    outbox, Model, Result,
+};
+
+/// A reminder sent to a member about one of their loans.
+#[derive(Debug, Serialize, FromRow, Fields)]
+pub struct Notice {
+    pub id: i64,
+    pub borrowing_id: i64,
+    pub kind: NoticeKind,
+    /// Days before or after the due date the notice was set up for
+    pub days: i64,
+    pub due_date: NaiveDate,
+    pub outbox_id: Option<i64>,
+    pub sent_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum NoticeKind {
+    DueSoon,
+    DueToday,
+    Overdue,
+}
+
+impl NoticeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NoticeKind::DueSoon => "due_soon",
+            NoticeKind::DueToday => "due_today",
+            NoticeKind::Overdue => "overdue",
+        }
+    }
+}
+
+impl From<NoticeKind> for sea_query::Value {
+    fn from(val: NoticeKind) -> Self {
+        val.as_str().into()
+    }
+}
+
+impl sea_query::Nullable for NoticeKind {
+    fn null() -> sea_query::Value {
+        sea_query::Value::String(None)
+    }
+}
+
+#[derive(Fields)]
+struct NoticeForCreate {
+    borrowing_id: i64,
+    kind: NoticeKind,
+    days: i64,
+    due_date: NaiveDate,
+    outbox_id: i64,
+}
+
+/// A loan that reached a notice stage its member wasn't told about yet.
+#[derive(Debug)]
+pub struct DueLoan {
+    pub borrowing: Borrowing,
+    pub kind: NoticeKind,
+    pub days: i64,
+}
+
+#[derive(Iden)]
+enum NoticeIden {
+    BorrowingId,
+    Kind,
+    Days,
+    DueDate,
+}
+
+impl Model for Notice {
+    const TABLE: &'static str = "Notices";
+}
+
+impl Notice {
+    /// Loans that need a notice on `today`, soonest due first.
+    pub async fn pending(
+        state: &AppState<super::Engine>,
+        today: NaiveDate,
+    ) -> Result<Vec<DueLoan>> {
+        let config = &state.config.notices;
+        let ahead = config.due_soon_days.iter().copied().max().unwrap_or(0);
+        let loans: Vec<DueLoan> = Borrowing::list_due(state, today + Duration::days(ahead))
+            .await?
+            .into_iter()
+            .filter_map(|borrowing| {
+                let (kind, days) = stage(config, borrowing.due_date, today)?;
+                Some(DueLoan {
+                    borrowing,
+                    kind,
+                    days,
+                })
+            })
+            .collect();
+
+        let mut query = Query::select();
+        query
+            .from(Self::table_ref())
+            .columns([
+                NoticeIden::BorrowingId,
+                NoticeIden::DueDate,
+                NoticeIden::Kind,
+                NoticeIden::Days,
+            ])
+            .and_where(
+                Expr::col(NoticeIden::BorrowingId)
+                    .is_in(loans.iter().map(|loan| loan.borrowing.id)),
+            );
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let sent: HashSet<(i64, NaiveDate, NoticeKind, i64)> = query_as_with(&sql, values)
+            .fetch_all(&state.pool)
+            .await?
+            .into_iter()
+            .collect();
+
+        Ok(loans
+            .into_iter()
+            .filter(|loan| {
+                let key = (
+                    loan.borrowing.id,
+                    loan.borrowing.due_date,
+                    loan.kind,
+                    loan.days,
+                );
+                !sent.contains(&key)
+            })
+            .collect())
+    }
+
+    /// Queue `email` telling a member about `loans`, and remember they were
+    /// told so the next run doesn't repeat it.
+    pub async fn record(
+        state: &AppState<super::Engine>,
+        loans: &[DueLoan],
+        email: Email,
+    ) -> Result<i64> {
+        let mut tx = state.pool.begin().await?;
+        let outbox_id = outbox::queue(&mut tx, email).await?;
+        for loan in loans {
+            let notice = NoticeForCreate {
+                borrowing_id: loan.borrowing.id,
+                kind: loan.kind,
+                days: loan.days,
+                due_date: loan.borrowing.due_date,
+                outbox_id,
+            };
+            let (columns, values) = notice.not_none_sea_fields().for_sea_insert();
+            let mut query = Query::insert();
+            query
+                .into_table(Self::table_ref())
+                .columns(columns)
+                .values(values)?;
+            let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+            query_with(&sql, values).execute(&mut *tx).await?;
+
+            // the mail already covers it
+            if loan.kind == NoticeKind::Overdue {
+                let event = Event::LoanOverdue {
+                    borrowing_id: loan.borrowing.id,
+                    book_id: loan.borrowing.book_id,
+                    due_date: loan.borrowing.due_date,
+                };
+                notify(&mut tx, state, loan.borrowing.user_id, event, false).await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(outbox_id)
+    }
+}
+
+/// Where a loan due on `due_date` stands on `today`. Before the due date it's
+/// the nearest reminder reached, after it the furthest overdue notice, so a
+/// missed run never sends a backlog of stale notices.
+fn stage(
+    config: &NoticeConfig,
+    due_date: NaiveDate,
+    today: NaiveDate,
+) -> Option<(NoticeKind, i64)> {
+    let days = (due_date - today).num_days();
+    match days {
+        0 => Some((NoticeKind::DueToday, 0)),
+        1.. => config
+            .due_soon_days
+            .iter()
+            .copied()
+            .filter(|&n| n > 0 && days <= n)
+            .min()
+            .map(|n| (NoticeKind::DueSoon, n)),
+        _ => config
+            .overdue_days
+            .iter()
+            .copied()
+            .filter(|&n| n > 0 && -days >= n)
+            .max()
+            .map(|n| (NoticeKind::Overdue, n)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::SqlitePool;
+
+    use crate::{
+        model::{
+            borrowing::{BorrowingForCreate, BorrowingForUpdate},
+            outbox::Outbox,
+        },
+        notify::notices::send_notices,
+        state::test_state,
+    };
+
+    use super::*;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, 1).unwrap()
+    }
+
+    #[test]
+    fn notice_stages() {
+        let config = NoticeConfig::default();
+        let stage = |days: i64| stage(&config, today() + Duration::days(days), today());
+
+        assert_eq!(stage(5), None);
+        assert_eq!(stage(3), Some((NoticeKind::DueSoon, 3)));
+        assert_eq!(stage(1), Some((NoticeKind::DueSoon, 3)));
+        assert_eq!(stage(0), Some((NoticeKind::DueToday, 0)));
+        assert_eq!(stage(-1), Some((NoticeKind::Overdue, 1)));
+        assert_eq!(stage(-6), Some((NoticeKind::Overdue, 1)));
+        assert_eq!(stage(-10), Some((NoticeKind::Overdue, 7)));
+        assert_eq!(stage(-100), Some((NoticeKind::Overdue, 14)));
+    }
+
+    #[sqlx::test(fixtures("users", "books"))]
+    fn sending_due_notices(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        let borrow = |user_id, book_id, copy_id, days| BorrowingForCreate {
+            user_id,
+            book_id,
+            copy_id,
+            due_date: today() + Duration::days(days),
+        };
+        Borrowing::create(&state, borrow(1, 1, 1, 2)).await?;
+        let due_today = Borrowing::create(&state, borrow(1, 2, 1, 0)).await?;
+        Borrowing::create(&state, borrow(1, 3, 1, -8)).await?;
+        Borrowing::create(&state, borrow(2, 1, 2, 10)).await?;
+
+        // one mail listing all three loans of the first member
+        assert_eq!(send_notices(&state, today()).await.unwrap(), 1);
+        let mail: Vec<Outbox> = crate::model::list::<Outbox, _>(&state).await?;
+        assert_eq!(mail.len(), 1);
+        assert_eq!(mail[0].recipient, "johndoe@localhost");
+        let text = &mail[0].text_body;
+        let overdue = text.find("Book 3").unwrap();
+        let today_ = text.find("Book 2").unwrap();
+        let soon = text.find("Book 1").unwrap();
+        assert!(overdue < today_ && today_ < soon);
+
+        // nothing new the same day, nor while the stages stay the same
+        assert_eq!(send_notices(&state, today()).await.unwrap(), 0);
+        assert_eq!(
+            Notice::pending(&state, today() + Duration::days(1))
+                .await?
+                .iter()
+                .map(|loan| (loan.borrowing.id, loan.kind, loan.days))
+                .collect::<Vec<_>>(),
+            [(due_today, NoticeKind::Overdue, 1)]
+        );
+
+        // a renewal gets reminders of its own
+        let renewed = BorrowingForUpdate {
+            return_date: None,
+            status: None,
+            due_date: Some(today() + Duration::days(3)),
+        };
+        Borrowing::update(&state, due_today, renewed).await?;
+        let pending = Notice::pending(&state, today()).await?;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].kind, NoticeKind::DueSoon);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,490 @@
+//! Messages to members about their holds, loans and fines.
+//!
+//! Notifications are created in the transaction of whatever they are about,
+//! and emailed through the outbox to members who asked for that.
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use modql::field::{Fields, HasSeaFields};
+use sea_query::{Expr, Iden, Order, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
+use serde::{Deserialize, Serialize};
+use sqlx::{query_as_with, query_with, FromRow, SqliteConnection, Type};
+use tracing::error;
+
+use crate::{
+    notify::mail::{render, Vars},
+    state::AppState,
+};
+
+use super::{
+    book::Book,
+    outbox,
+    reservation::{Reservation, ReservationStatus},
+    user::User,
+    Model, Result,
+};
+
+#[derive(Debug, Serialize, FromRow, Fields)]
+pub struct Notification {
+    pub id: i64,
+    pub user_id: i64,
+    pub kind: NotificationKind,
+    /// Reservation, loan or fine the notification is about
+    pub entity_id: Option<i64>,
+    pub message: String,
+    pub read_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum NotificationKind {
+    HoldReady,
+    HoldExpiring,
+    LoanOverdue,
+    FineIssued,
+    ReservationDeclined,
+}
+
+impl From<NotificationKind> for sea_query::Value {
+    fn from(val: NotificationKind) -> Self {
+        use NotificationKind as NK;
+        match val {
+            NK::HoldReady => "hold_ready".into(),
+            NK::HoldExpiring => "hold_expiring".into(),
+            NK::LoanOverdue => "loan_overdue".into(),
+            NK::FineIssued => "fine_issued".into(),
+            NK::ReservationDeclined => "reservation_declined".into(),
+        }
+    }
+}
+
+impl sea_query::Nullable for NotificationKind {
+    fn null() -> sea_query::Value {
+        sea_query::Value::String(None)
+    }
+}
+
+impl NotificationKind {
+    /// Subject of the email version.
+    fn title(&self) -> &'static str {
+        use NotificationKind as NK;
+        match self {
+            NK::HoldReady => "Your hold is ready",
+            NK::HoldExpiring => "Your hold is waiting",
+            NK::LoanOverdue => "A loan is overdue",
+            NK::FineIssued => "A fine was issued",
+            NK::ReservationDeclined => "Your reservation was declined",
+        }
+    }
+}
+
+/// Something that happened a member should hear about.
+#[derive(Debug, Clone, Copy)]
+pub enum Event {
+    HoldReady {
+        reservation_id: i64,
+        book_id: i64,
+        until: NaiveDate,
+    },
+    HoldExpiring {
+        reservation_id: i64,
+        book_id: i64,
+        until: NaiveDate,
+    },
+    LoanOverdue {
+        borrowing_id: i64,
+        book_id: i64,
+        due_date: NaiveDate,
+    },
+    FineIssued {
+        fine_id: i64,
+        book_id: i64,
+        amount: f64,
+    },
+    ReservationDeclined {
+        reservation_id: i64,
+        book_id: i64,
+    },
+}
+
+impl Event {
+    fn kind(&self) -> NotificationKind {
+        match self {
+            Event::HoldReady { .. } => NotificationKind::HoldReady,
+            Event::HoldExpiring { .. } => NotificationKind::HoldExpiring,
+            Event::LoanOverdue { .. } => NotificationKind::LoanOverdue,
+            Event::FineIssued { .. } => NotificationKind::FineIssued,
+            Event::ReservationDeclined { .. } => NotificationKind::ReservationDeclined,
+        }
+    }
+
+    fn entity_id(&self) -> i64 {
+        match *self {
+            Event::HoldReady { reservation_id, .. }
+            | Event::HoldExpiring { reservation_id, .. }
+            | Event::ReservationDeclined { reservation_id, .. } => reservation_id,
+            Event::LoanOverdue { borrowing_id, .. } => borrowing_id,
+            Event::FineIssued { fine_id, .. } => fine_id,
+        }
+    }
+
+    fn book_id(&self) -> i64 {
+        match *self {
+            Event::HoldReady { book_id, .. }
+            | Event::HoldExpiring { book_id, .. }
+            | Event::LoanOverdue { book_id, .. }
+            | Event::FineIssued { book_id, .. }
+            | Event::ReservationDeclined { book_id, .. } => book_id,
+        }
+    }
+
+    fn message(&self, title: &str) -> String {
+        match self {
+            Event::HoldReady { until, .. } => {
+                format!("\"{title}\" is ready for you to collect until {until}")
+            }
+            Event::HoldExpiring { until, .. } => {
+                format!("\"{title}\" waits for you until {until}, collect it before then")
+            }
+            Event::LoanOverdue { due_date, .. } => {
+                format!("\"{title}\" was due back on {due_date}")
+            }
+            Event::FineIssued { amount, .. } => {
+                format!("You were fined {amount:.2} for \"{title}\"")
+            }
+            Event::ReservationDeclined { .. } => {
+                format!("Your reservation of \"{title}\" was declined")
+            }
+        }
+    }
+}
+
+#[derive(Fields)]
+struct NotificationForCreate {
+    user_id: i64,
+    kind: NotificationKind,
+    entity_id: i64,
+    message: String,
+}
+
+#[derive(Iden)]
+enum NotificationIden {
+    Id,
+    UserId,
+    Kind,
+    EntityId,
+    ReadAt,
+    Title,
+    Status,
+    ReadyDate,
+}
+
+impl Model for Notification {
+    const TABLE: &'static str = "Notifications";
+}
+
+impl Notification {
+    /// A member's notifications, newest first.
+    pub async fn list_for_user(
+        state: &AppState<super::Engine>,
+        user_id: i64,
+        unread: bool,
+    ) -> Result<Vec<Notification>> {
+        let mut query = Query::select();
+        query
+            .from(Self::table_ref())
+            .columns(Self::sea_idens())
+            .and_where(Expr::col(NotificationIden::UserId).eq(user_id))
+            .order_by(NotificationIden::Id, Order::Desc);
+        if unread {
+            query.and_where(Expr::col(NotificationIden::ReadAt).is_null());
+        }
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let notifications = query_as_with::<_, Notification, _>(&sql, values)
+            .fetch_all(&state.pool)
+            .await?;
+        Ok(notifications)
+    }
+
+    /// Mark one of a member's notifications read, `false` when they have no
+    /// such notification.
+    pub async fn mark_read(state: &AppState<super::Engine>, id: i64, user_id: i64) -> Result<bool> {
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .value(NotificationIden::ReadAt, Utc::now().naive_utc())
+            .and_where(Expr::col(NotificationIden::Id).eq(id))
+            .and_where(Expr::col(NotificationIden::UserId).eq(user_id))
+            .and_where(Expr::col(NotificationIden::ReadAt).is_null());
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let result = query_with(&sql, values).execute(&state.pool).await?;
+        if result.rows_affected() > 0 {
+            return Ok(true);
+        }
+
+        // already read is fine too
+        let mut query = Query::select();
+        query
+            .expr(Expr::col(NotificationIden::Id).count())
+            .from(Self::table_ref())
+            .and_where(Expr::col(NotificationIden::Id).eq(id))
+            .and_where(Expr::col(NotificationIden::UserId).eq(user_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let (count,) = query_as_with::<_, (i64,), _>(&sql, values)
+            .fetch_one(&state.pool)
+            .await?;
+        Ok(count > 0)
+    }
+
+    /// Mark all of a member's notifications read, returning how many were
+    /// unread.
+    pub async fn mark_all_read(state: &AppState<super::Engine>, user_id: i64) -> Result<u64> {
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .value(NotificationIden::ReadAt, Utc::now().naive_utc())
+            .and_where(Expr::col(NotificationIden::UserId).eq(user_id))
+            .and_where(Expr::col(NotificationIden::ReadAt).is_null());
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let result = query_with(&sql, values).execute(&state.pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Remind members of holds that are about to stop waiting for them,
+    /// once per hold. Returns how many were reminded.
+    pub async fn remind_expiring_holds(
+        state: &AppState<super::Engine>,
+        today: NaiveDate,
+    ) -> Result<usize> {
+        let pickup_days = state.config.circulation.hold_pickup_days;
+        let warning_days = state.config.notices.hold_expiring_days;
+        let reminded = Query::select()
+            .column(NotificationIden::EntityId)
+            .from(Self::table_ref())
+            .and_where(Expr::col(NotificationIden::Kind).eq(NotificationKind::HoldExpiring))
+            .to_owned();
+
+        let mut query = Query::select();
+        query
+            .from(Reservation::table_ref())
+            .columns(Reservation::sea_idens())
+            .and_where(Expr::col(NotificationIden::Status).eq(ReservationStatus::Active))
+            .and_where(
+                Expr::col(NotificationIden::ReadyDate)
+                    .lte(today - Duration::days(pickup_days - warning_days)),
+            )
+            .and_where(Expr::col(NotificationIden::Id).not_in_subquery(reminded));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let holds = query_as_with::<_, Reservation, _>(&sql, values)
+            .fetch_all(&state.pool)
+            .await?;
+
+        let mut tx = state.pool.begin().await?;
+        for hold in &holds {
+            let Some(ready_date) = hold.ready_date else {
+                continue;
+            };
+            let event = Event::HoldExpiring {
+                reservation_id: hold.id,
+                book_id: hold.book_id,
+                until: ready_date + Duration::days(pickup_days),
+            };
+            notify(&mut tx, state, hold.user_id, event, true).await?;
+        }
+        tx.commit().await?;
+
+        Ok(holds.len())
+    }
+}
+
+/// Tell `user_id` about `event`, and email it when `email` is set and they
+/// want notifications by email.
+pub(super) async fn notify(
+    conn: &mut SqliteConnection,
+    state: &AppState<super::Engine>,
+    user_id: i64,
+    event: Event,
+    email: bool,
+) -> Result<i64> {
+    let mut query = Query::select();
+    query
+        .column(NotificationIden::Title)
+        .from(Book::table_ref())
+        .and_where(Expr::col(NotificationIden::Id).eq(event.book_id()));
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let (title,) = query_as_with::<_, (String,), _>(&sql, values)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let notification = NotificationForCreate {
+        user_id,
+        kind: event.kind(),
+        entity_id: event.entity_id(),
+        message: event.message(&title),
+    };
+    let (columns, values) = notification.not_none_sea_fields().for_sea_insert();
+    let mut query = Query::insert();
+    query
+        .into_table(Notification::table_ref())
+        .columns(columns)
+        .values(values)?
+        .returning_col(NotificationIden::Id);
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let (id,) = query_as_with::<_, (i64,), _>(&sql, values)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    if !email {
+        return Ok(id);
+    }
+    let mut query = Query::select();
+    query
+        .from(User::table_ref())
+        .columns(User::sea_idens())
+        .and_where(Expr::col(NotificationIden::Id).eq(user_id));
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let user = query_as_with::<_, User, _>(&sql, values)
+        .fetch_one(&mut *conn)
+        .await?;
+    if user.email_notifications {
+        let vars = Vars::new()
+            .user(&user)
+            .set("title", event.kind().title())
+            .set("message", event.message(&title))
+            .set("link", format!("{}/notifications", state.config.app_url));
+        // a broken template shouldn't undo what the notification is about
+        match render("notification", &user.email, &vars) {
+            Ok(email) => {
+                outbox::queue(conn, email).await?;
+            }
+            Err(e) => error!("{e}"),
+        }
+    }
+
+    Ok(id)
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::SqlitePool;
+
+    use crate::{
+        model::{
+            circulation::{Circulation, Outcome},
+            outbox::Outbox,
+            reservation::{ReservationForCreate, ReservationForUpdate},
+            user::UserForUpdate,
+        },
+        state::test_state,
+    };
+
+    use super::*;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, 1).unwrap()
+    }
+
+    #[sqlx::test(fixtures("users", "books"))]
+    fn notifying_members(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        let kinds = |notifications: Vec<Notification>| {
+            notifications
+                .into_iter()
+                .map(|n| n.kind)
+                .collect::<Vec<_>>()
+        };
+        let opt_in = UserForUpdate {
+            email_notifications: Some(true),
+            ..Default::default()
+        };
+        User::update(&state, 1, opt_in).await?;
+
+        // a copy coming back is put aside for the member waiting for it
+        Circulation::checkout(&state, 2, 1, 1, today()).await?;
+        let reservation = ReservationForCreate {
+            copy_id: 1,
+            book_id: 1,
+            user_id: 1,
+            reservation_date: None,
+            pickup_branch_id: None,
+            any_edition: false,
+        };
+        let reservation_id = Reservation::create(&state, reservation).await?;
+        let outcome = Circulation::checkin(&state, 1, 1, today()).await?;
+        assert!(matches!(outcome, Outcome::Success { hold: Some(_), .. }));
+
+        let hold = Reservation::get(&state, reservation_id).await?;
+        assert!(matches!(hold.status, ReservationStatus::Active));
+        assert_eq!(hold.ready_date, Some(today()));
+        let notifications = Notification::list_for_user(&state, 1, true).await?;
+        assert_eq!(notifications[0].entity_id, Some(reservation_id));
+        assert_eq!(
+            notifications[0].message,
+            "\"Book 1\" is ready for you to collect until 2025-03-08"
+        );
+        let mail: Vec<Outbox> = crate::model::list::<Outbox, _>(&state).await?;
+        assert_eq!(mail.len(), 1);
+        assert_eq!(mail[0].subject, "Your hold is ready");
+
+        // reminded once before the hold stops waiting
+        let soon = today() + Duration::days(4);
+        assert_eq!(Notification::remind_expiring_holds(&state, soon).await?, 0);
+        let soon = today() + Duration::days(5);
+        assert_eq!(Notification::remind_expiring_holds(&state, soon).await?, 1);
+        assert_eq!(Notification::remind_expiring_holds(&state, soon).await?, 0);
+
+        // declining tells the member, nobody else hears about it
+        let reservation = ReservationForCreate {
+            copy_id: 1,
+            book_id: 2,
+            user_id: 1,
+            reservation_date: None,
+            pickup_branch_id: None,
+            any_edition: false,
+        };
+        let declined = Reservation::create(&state, reservation).await?;
+        let update = ReservationForUpdate {
+            status: ReservationStatus::Declined,
+            pickup_branch_id: None,
+        };
+        Reservation::update(&state, declined, update).await?;
+        assert_eq!(
+            kinds(Notification::list_for_user(&state, 1, true).await?),
+            [
+                NotificationKind::ReservationDeclined,
+                NotificationKind::HoldExpiring,
+                NotificationKind::HoldReady,
+            ]
+        );
+        assert!(Notification::list_for_user(&state, 2, false)
+            .await?
+            .is_empty());
+
+        // a lost item's fee
+        let loan = Circulation::checkout(&state, 2, 2, 2, today()).await?;
+        let Outcome::Success { borrowing_id, .. } = loan else {
+            panic!("{loan:?}");
+        };
+        Circulation::declare_lost(&state, borrowing_id).await?;
+        let notifications = Notification::list_for_user(&state, 2, false).await?;
+        assert_eq!(kinds(notifications), [NotificationKind::FineIssued]);
+        // not emailed, the member didn't ask for it
+        let mail: Vec<Outbox> = crate::model::list::<Outbox, _>(&state).await?;
+        assert_eq!(mail.len(), 3);
+
+        // reading them
+        let first = Notification::list_for_user(&state, 1, false).await?[2].id;
+        assert!(Notification::mark_read(&state, first, 1).await?);
+        assert!(Notification::mark_read(&state, first, 1).await?);
+        assert!(!Notification::mark_read(&state, first, 2).await?);
+        assert_eq!(Notification::list_for_user(&state, 1, true).await?.len(), 2);
+        assert_eq!(Notification::mark_all_read(&state, 1).await?, 2);
+        assert!(Notification::list_for_user(&state, 1, true)
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+}
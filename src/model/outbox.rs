@@ -0,0 +1,248 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use modql::field::{Fields, HasSeaFields};
+use sea_query::{Expr, Iden, Order, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
+use serde::{Deserialize, Serialize};
+use sqlx::{query_as_with, query_with, FromRow, SqliteConnection, Type};
+
+use crate::{notify::mail::Email, state::AppState};
+
+use super::{Model, Result};
+
+/// A mail waiting to be sent, or the record of one.
+#[derive(Debug, Serialize, FromRow, Fields)]
+pub struct Outbox {
+    pub id: i64,
+    pub recipient: String,
+    pub subject: String,
+    pub text_body: String,
+    pub html_body: Option<String>,
+    pub attempts: i64,
+    pub next_attempt_at: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+    pub status: OutboxStatus,
+    pub sent_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum OutboxStatus {
+    #[default]
+    Pending,
+    Sent,
+    /// Given up on after too many attempts
+    Failed,
+}
+
+impl From<OutboxStatus> for sea_query::Value {
+    fn from(val: OutboxStatus) -> Self {
+        match val {
+            OutboxStatus::Pending => "pending".into(),
+            OutboxStatus::Sent => "sent".into(),
+            OutboxStatus::Failed => "failed".into(),
+        }
+    }
+}
+
+impl sea_query::Nullable for OutboxStatus {
+    fn null() -> sea_query::Value {
+        sea_query::Value::String(None)
+    }
+}
+
+#[derive(Fields)]
+struct OutboxForCreate {
+    recipient: String,
+    subject: String,
+    text_body: String,
+    html_body: Option<String>,
+}
+
+#[derive(Iden)]
+enum OutboxIden {
+    Id,
+    Attempts,
+    NextAttemptAt,
+    LastError,
+    Status,
+    SentAt,
+}
+
+impl Model for Outbox {
+    const TABLE: &'static str = "Outbox";
+}
+
+impl Outbox {
+    /// Queue a mail, it goes out with the next outbox run.
+    pub async fn queue(state: &AppState<super::Engine>, email: Email) -> Result<i64> {
+        let mut conn = state.pool.acquire().await?;
+        queue(&mut conn, email).await
+    }
+
+    /// Pending mail whose turn it is, oldest first.
+    pub async fn due(state: &AppState<super::Engine>, limit: u64) -> Result<Vec<Outbox>> {
+        let mut query = Query::select();
+        query
+            .from(Self::table_ref())
+            .columns(Self::sea_idens())
+            .and_where(Expr::col(OutboxIden::Status).eq(OutboxStatus::Pending))
+            .and_where(Expr::col(OutboxIden::NextAttemptAt).lte(Utc::now().naive_utc()))
+            .order_by(OutboxIden::Id, Order::Asc)
+            .limit(limit);
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let mail = query_as_with::<_, Outbox, _>(&sql, values)
+            .fetch_all(&state.pool)
+            .await?;
+        Ok(mail)
+    }
+
+    pub async fn sent(state: &AppState<super::Engine>, id: i64) -> Result<()> {
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .values([
+                (OutboxIden::Status, OutboxStatus::Sent.into()),
+                (OutboxIden::SentAt, Utc::now().naive_utc().into()),
+                (OutboxIden::Attempts, Expr::col(OutboxIden::Attempts).add(1)),
+            ])
+            .and_where(Expr::col(OutboxIden::Id).eq(id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&state.pool).await?;
+        Ok(())
+    }
+
+    /// Record a failed attempt. The mail is tried again later, waiting twice
+    /// as long each time, until `max_attempts` is reached.
+    pub async fn failed(
+        state: &AppState<super::Engine>,
+        id: i64,
+        error: &str,
+        max_attempts: i64,
+    ) -> Result<()> {
+        let mail: Outbox = super::get::<Self, _>(state, id).await?;
+        let attempts = mail.attempts + 1;
+        let status = match attempts >= max_attempts {
+            true => OutboxStatus::Failed,
+            false => OutboxStatus::Pending,
+        };
+        let delay = Duration::minutes(1 << attempts.min(16));
+
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .values([
+                (OutboxIden::Attempts, attempts.into()),
+                (OutboxIden::Status, status.into()),
+                (OutboxIden::LastError, error.into()),
+                (
+                    OutboxIden::NextAttemptAt,
+                    (Utc::now().naive_utc() + delay).into(),
+                ),
+            ])
+            .and_where(Expr::col(OutboxIden::Id).eq(id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&state.pool).await?;
+        Ok(())
+    }
+}
+
+/// Queue a mail inside a transaction, so it only goes out if the rest of the
+/// work is committed.
+pub(super) async fn queue(conn: &mut SqliteConnection, email: Email) -> Result<i64> {
+    let mail = OutboxForCreate {
+        recipient: email.to,
+        subject: email.subject,
+        text_body: email.text,
+        html_body: email.html,
+    };
+    let (columns, values) = mail.not_none_sea_fields().for_sea_insert();
+    let mut query = Query::insert();
+    query
+        .into_table(Outbox::table_ref())
+        .columns(columns)
+        .values(values)?
+        .returning_col(OutboxIden::Id);
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let (id,) = query_as_with::<_, (i64,), _>(&sql, values)
+        .fetch_one(conn)
+        .await?;
+    Ok(id)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use sqlx::SqlitePool;
+
+    use crate::{
+        notify::mail::{self, deliver_outbox, Mailer},
+        state::test_state,
+    };
+
+    use super::*;
+
+    /// Fails until told otherwise, remembering what it sent.
+    #[derive(Default)]
+    struct FlakyMailer {
+        up: Mutex<bool>,
+        sent: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Mailer for FlakyMailer {
+        async fn send(&self, _from: &str, email: &Email) -> mail::Result<()> {
+            if !*self.up.lock().unwrap() {
+                return Err(mail::Error::Io(std::io::Error::other("down")));
+            }
+            self.sent.lock().unwrap().push(email.to.clone());
+            Ok(())
+        }
+    }
+
+    #[sqlx::test]
+    fn retrying_mail(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        let email = |to: &str| Email {
+            to: to.to_string(),
+            subject: "Hello".to_string(),
+            text: "Hi".to_string(),
+            html: None,
+        };
+        let mailer = FlakyMailer::default();
+
+        let id = Outbox::queue(&state, email("a@example.org")).await?;
+        assert_eq!(deliver_outbox(&state, &mailer).await?, 0);
+
+        // waiting before the next attempt
+        let mail: Outbox = crate::model::get::<Outbox, _>(&state, id).await?;
+        assert_eq!((mail.attempts, mail.status), (1, OutboxStatus::Pending));
+        assert_eq!(mail.last_error.as_deref(), Some("down"));
+        assert!(Outbox::due(&state, 10).await?.is_empty());
+
+        *mailer.up.lock().unwrap() = true;
+        sqlx::query("UPDATE Outbox SET next_attempt_at = datetime('now', '-1 minute')")
+            .execute(&state.pool)
+            .await?;
+        Outbox::queue(&state, email("b@example.org")).await?;
+        assert_eq!(deliver_outbox(&state, &mailer).await?, 2);
+        assert_eq!(
+            *mailer.sent.lock().unwrap(),
+            ["a@example.org", "b@example.org"]
+        );
+        assert_eq!(deliver_outbox(&state, &mailer).await?, 0);
+
+        // given up on eventually
+        let id = Outbox::queue(&state, email("c@example.org")).await?;
+        for _ in 0..state.config.mail.max_attempts {
+            Outbox::failed(&state, id, "down", state.config.mail.max_attempts).await?;
+        }
+        let mail: Outbox = crate::model::get::<Outbox, _>(&state, id).await?;
+        assert_eq!(mail.status, OutboxStatus::Failed);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+//! Forgotten passwords, reset with a single use token sent by email.
+
+use chrono::{Duration, Utc};
+use sea_query::{Expr, Iden, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
+use sqlx::{query_as_with, query_with};
+
+use crate::{
+    auth::{hash, random_token, token_hash},
+    state::AppState,
+};
+
+use super::{error::Error, session, user, Model, Result};
+
+pub struct PasswordReset;
+
+#[derive(Iden)]
+enum PasswordResetIden {
+    Id,
+    UserId,
+    TokenHash,
+    ExpiresAt,
+    UsedAt,
+}
+
+impl Model for PasswordReset {
+    const TABLE: &'static str = "PasswordResets";
+}
+
+impl PasswordReset {
+    /// Issue a reset token for the user, earlier unused ones stop working.
+    pub async fn create(state: &AppState<super::Engine>, user_id: i64) -> Result<String> {
+        let now = Utc::now().naive_utc();
+        let token = random_token();
+
+        let mut tx = state.pool.begin().await?;
+
+        let mut query = Query::delete();
+        query
+            .from_table(Self::table_ref())
+            .and_where(Expr::col(PasswordResetIden::UserId).eq(user_id))
+            .and_where(Expr::col(PasswordResetIden::UsedAt).is_null());
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        let expires_at = now + Duration::minutes(state.config.auth.password_reset_minutes);
+        let mut query = Query::insert();
+        query
+            .into_table(Self::table_ref())
+            .columns([
+                PasswordResetIden::UserId,
+                PasswordResetIden::TokenHash,
+                PasswordResetIden::ExpiresAt,
+            ])
+            .values([user_id.into(), token_hash(&token).into(), expires_at.into()])?;
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        tx.commit().await?;
+
+        Ok(token)
+    }
+
+    /// Set a new password with a reset token, signing the user out
+    /// everywhere. Returns the user's id, `None` for a used, expired or
+    /// unknown token.
+    pub async fn consume(
+        state: &AppState<super::Engine>,
+        token: &str,
+        password: &str,
+    ) -> Result<Option<i64>> {
+        let now = Utc::now().naive_utc();
+        let password = hash(password).map_err(|e| Error::Hash(e.to_string()))?;
+
+        let mut tx = state.pool.begin().await?;
+
+        // claiming the token first, a second use finds nothing to update
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .value(PasswordResetIden::UsedAt, now)
+            .and_where(Expr::col(PasswordResetIden::TokenHash).eq(token_hash(token)))
+            .and_where(Expr::col(PasswordResetIden::UsedAt).is_null())
+            .and_where(Expr::col(PasswordResetIden::ExpiresAt).gt(now))
+            .returning_col(PasswordResetIden::UserId);
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let Some((user_id,)) = query_as_with::<_, (i64,), _>(&sql, values)
+            .fetch_optional(&mut *tx)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        user::set_password(&mut tx, user_id, &password).await?;
+        session::revoke_all(&mut tx, state, user_id, None).await?;
+        tx.commit().await?;
+
+        Ok(Some(user_id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::SqlitePool;
+
+    use crate::{
+        auth::verify,
+        model::{session::Session, user::User},
+        state::test_state,
+    };
+
+    use super::*;
+
+    #[sqlx::test(fixtures("users"))]
+    fn resetting_a_password(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        let (session, _) = Session::start(&state, 1, None, None).await?;
+        let stale = PasswordReset::create(&state, 1).await?;
+        let token = PasswordReset::create(&state, 1).await?;
+
+        // only the latest token works, and only once
+        assert_eq!(PasswordReset::consume(&state, &stale, "new").await?, None);
+        assert_eq!(
+            PasswordReset::consume(&state, &token, "new").await?,
+            Some(1)
+        );
+        assert_eq!(PasswordReset::consume(&state, &token, "again").await?, None);
+
+        let user: User = User::get(&state, 1).await?;
+        assert!(verify(&user.password, "new").is_ok());
+        assert!(Session::list_for_user(&state, 1, Some(&session))
+            .await?
+            .is_empty());
+
+        // expired
+        let token = PasswordReset::create(&state, 2).await?;
+        sqlx::query("UPDATE PasswordResets SET expires_at = datetime('now', '-1 minute')")
+            .execute(&state.pool)
+            .await?;
+        assert_eq!(PasswordReset::consume(&state, &token, "new").await?, None);
+
+        Ok(())
+    }
+}
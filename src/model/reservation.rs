@@ -1,14 +1,22 @@
 use std::future::Pending;
 
 use chrono::{NaiveDate, NaiveDateTime};
-use modql::field::Fields;
-use sea_query::Iden;
+use modql::field::{Fields, HasSeaFields};
+use sea_query::{Expr, Iden, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
 use serde::{Deserialize, Serialize};
-use sqlx::prelude::{FromRow, Type};
+use sqlx::{
+    prelude::{FromRow, Type},
+    query_as_with, query_with,
+};
 
 use crate::state::AppState;
 
-use super::{Model, Result};
+use super::{
+    error::Error,
+    notification::{notify, Event},
+    Model, Result,
+};
 
 #[derive(Debug, Serialize, FromRow, Fields)]
 pub struct Reservation {
@@ -18,6 +26,12 @@ pub struct Reservation {
     pub user_id: i64,
     pub reservation_date: NaiveDate,
     pub status: ReservationStatus,
+    /// Branch the patron collects the copy from
+    pub pickup_branch_id: Option<i64>,
+    /// Any edition in the book's work can satisfy the hold
+    pub any_edition: bool,
+    /// Day the copy was put aside for collection
+    pub ready_date: Option<NaiveDate>,
     pub updated_at: Option<NaiveDateTime>,
 }
 
@@ -30,6 +44,7 @@ pub enum ReservationStatus {
     Declined,
     Expired,
     Cancelled,
+    Fulfilled,
 }
 
 impl From<ReservationStatus> for sea_query::Value {
@@ -41,6 +56,7 @@ impl From<ReservationStatus> for sea_query::Value {
             RS::Expired => "expired".into(),
             RS::Cancelled => "cancelled".into(),
             RS::Declined => "declined".into(),
+            RS::Fulfilled => "fulfilled".into(),
         }
     }
 }
@@ -57,11 +73,15 @@ pub struct ReservationForCreate {
     pub book_id: i64,
     pub user_id: i64,
     pub reservation_date: Option<NaiveDate>,
+    pub pickup_branch_id: Option<i64>,
+    #[serde(default)]
+    pub any_edition: bool,
 }
 
 #[derive(Debug, Deserialize, Fields)]
 pub struct ReservationForUpdate {
     pub status: ReservationStatus,
+    pub pickup_branch_id: Option<i64>,
 }
 
 #[derive(Iden)]
@@ -80,12 +100,47 @@ impl Reservation {
         super::get::<Self, _>(state, id).await
     }
 
+    /// Declining a reservation lets its member know.
     pub async fn update(
         state: &AppState<super::Engine>,
         id: i64,
         review: ReservationForUpdate,
     ) -> Result<()> {
-        super::update::<Self, _>(state, id, review).await
+        let declined = matches!(review.status, ReservationStatus::Declined);
+        let mut tx = state.pool.begin().await?;
+
+        let mut query = Query::select();
+        query
+            .from(Self::table_ref())
+            .columns(Self::sea_idens())
+            .and_where(Expr::col(ReservationIden::Id).eq(id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let reservation = query_as_with::<_, Reservation, _>(&sql, values)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(Error::EntityNotFound {
+                entity: Self::TABLE,
+                id,
+            })?;
+
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .values(review.not_none_sea_fields().for_sea_update())
+            .and_where(Expr::col(ReservationIden::Id).eq(id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        if declined && !matches!(reservation.status, ReservationStatus::Declined) {
+            let event = Event::ReservationDeclined {
+                reservation_id: id,
+                book_id: reservation.book_id,
+            };
+            notify(&mut tx, state, reservation.user_id, event, true).await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
     }
 
     pub async fn create(
@@ -0,0 +1,323 @@
+use chrono::NaiveDateTime;
+use modql::{
+    field::{Fields, HasSeaFields},
+    SIden,
+};
+use sea_query::{Expr, Func, Iden, OnConflict, Order, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
+use serde::{Deserialize, Serialize};
+use sqlx::{query_as_with, query_with, FromRow, SqlitePool};
+
+use crate::state::AppState;
+
+use super::{book::Book, borrowing::Borrowing, error::Error, Model, Result};
+
+#[derive(Debug, Serialize, FromRow, Fields)]
+pub struct Series {
+    pub id: i64,
+    pub title: String,
+    pub description: Option<String>,
+    pub updated_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Fields)]
+pub struct SeriesForCreate {
+    pub title: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Fields)]
+pub struct SeriesForUpdate {
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+/// A book in a series.
+#[derive(Debug, Serialize, FromRow)]
+pub struct Volume {
+    /// Fractional for books between two others, e.g. 2.5
+    pub volume: f64,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub book: Book,
+}
+
+/// The book following another in a series.
+#[derive(Debug, PartialEq, Serialize, FromRow)]
+pub struct NextInSeries {
+    pub series_id: i64,
+    pub series_title: String,
+    pub volume: f64,
+    pub book_id: i64,
+    pub title: String,
+    pub author: String,
+}
+
+pub(super) struct SeriesBook;
+
+#[derive(Iden)]
+enum SeriesIden {
+    Id,
+    Title,
+    Author,
+    SeriesId,
+    SeriesTitle,
+    BookId,
+    Volume,
+    UserId,
+}
+
+impl Model for Series {
+    const TABLE: &'static str = "Series";
+}
+
+impl Model for SeriesBook {
+    const TABLE: &'static str = "SeriesBooks";
+}
+
+impl Series {
+    pub async fn get(state: &AppState<super::Engine>, id: i64) -> Result<Series> {
+        super::get::<Self, _>(state, id).await
+    }
+
+    pub async fn list(state: &AppState<super::Engine>) -> Result<Vec<Series>> {
+        super::list::<Self, _>(state).await
+    }
+
+    pub async fn create(state: &AppState<super::Engine>, series: SeriesForCreate) -> Result<i64> {
+        super::create::<Self, _>(state, series).await
+    }
+
+    pub async fn update(
+        state: &AppState<super::Engine>,
+        id: i64,
+        series: SeriesForUpdate,
+    ) -> Result<()> {
+        super::update::<Self, _>(state, id, series).await
+    }
+
+    pub async fn delete(state: &AppState<super::Engine>, id: i64) -> Result<()> {
+        super::delete::<Self>(state, id).await
+    }
+
+    /// Books in the series in volume order.
+    pub async fn volumes(state: &AppState<super::Engine>, id: i64) -> Result<Vec<Volume>> {
+        let db = &state.pool;
+        let books = SIden(Book::TABLE);
+        let series_books = SIden(SeriesBook::TABLE);
+
+        let mut query = Query::select();
+        query
+            .column((series_books, SeriesIden::Volume))
+            .columns(Book::sea_column_refs_with_rel(books))
+            .from(SeriesBook::table_ref())
+            .inner_join(
+                Book::table_ref(),
+                Expr::col((books, SeriesIden::Id)).equals((series_books, SeriesIden::BookId)),
+            )
+            .and_where(Expr::col((series_books, SeriesIden::SeriesId)).eq(id))
+            .order_by((series_books, SeriesIden::Volume), Order::Asc);
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let volumes = query_as_with::<_, Volume, _>(&sql, values)
+            .fetch_all(db)
+            .await?;
+
+        Ok(volumes)
+    }
+
+    /// Put a book in the series, or move it to another volume.
+    pub async fn set_volume(
+        state: &AppState<super::Engine>,
+        id: i64,
+        book_id: i64,
+        volume: f64,
+    ) -> Result<()> {
+        if !volume.is_finite() || volume < 0.0 {
+            return Err(Error::Conflict("Volume must be a positive number"));
+        }
+
+        let mut query = Query::insert();
+        query
+            .into_table(SeriesBook::table_ref())
+            .columns([SeriesIden::SeriesId, SeriesIden::BookId, SeriesIden::Volume])
+            .values([id.into(), book_id.into(), volume.into()])?
+            .on_conflict(
+                OnConflict::columns([SeriesIden::SeriesId, SeriesIden::BookId])
+                    .update_column(SeriesIden::Volume)
+                    .to_owned(),
+            );
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&state.pool).await?;
+        Ok(())
+    }
+
+    pub async fn remove_book(state: &AppState<super::Engine>, id: i64, book_id: i64) -> Result<()> {
+        let mut query = Query::delete();
+        query
+            .from_table(SeriesBook::table_ref())
+            .and_where(Expr::col(SeriesIden::SeriesId).eq(id))
+            .and_where(Expr::col(SeriesIden::BookId).eq(book_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        match query_with(&sql, values)
+            .execute(&state.pool)
+            .await?
+            .rows_affected()
+        {
+            0 => Err(Error::EntityNotFound {
+                entity: SeriesBook::TABLE,
+                id: book_id,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The book after this one in each series it is part of.
+    pub async fn next_for_book(
+        state: &AppState<super::Engine>,
+        book_id: i64,
+    ) -> Result<Vec<NextInSeries>> {
+        let mut query = Query::select();
+        query
+            .columns([SeriesIden::SeriesId, SeriesIden::Volume])
+            .from(SeriesBook::table_ref())
+            .and_where(Expr::col(SeriesIden::BookId).eq(book_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let volumes = query_as_with::<_, (i64, f64), _>(&sql, values)
+            .fetch_all(&state.pool)
+            .await?;
+
+        next_after(&state.pool, volumes).await
+    }
+
+    /// The book after the furthest one a member has borrowed, in each series
+    /// they have borrowed from.
+    pub async fn next_for_user(
+        state: &AppState<super::Engine>,
+        user_id: i64,
+    ) -> Result<Vec<NextInSeries>> {
+        let series_books = SIden(SeriesBook::TABLE);
+        let borrowing = SIden(Borrowing::TABLE);
+
+        let mut query = Query::select();
+        query
+            .column((series_books, SeriesIden::SeriesId))
+            .expr(Func::max(Expr::col((series_books, SeriesIden::Volume))))
+            .from(SeriesBook::table_ref())
+            .inner_join(
+                Borrowing::table_ref(),
+                Expr::col((borrowing, SeriesIden::BookId))
+                    .equals((series_books, SeriesIden::BookId)),
+            )
+            .and_where(Expr::col((borrowing, SeriesIden::UserId)).eq(user_id))
+            .group_by_col((series_books, SeriesIden::SeriesId));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let volumes = query_as_with::<_, (i64, f64), _>(&sql, values)
+            .fetch_all(&state.pool)
+            .await?;
+
+        next_after(&state.pool, volumes).await
+    }
+}
+
+/// The first book after each `(series_id, volume)`, for series that go on.
+async fn next_after(db: &SqlitePool, volumes: Vec<(i64, f64)>) -> Result<Vec<NextInSeries>> {
+    let series = SIden(Series::TABLE);
+    let books = SIden(Book::TABLE);
+    let series_books = SIden(SeriesBook::TABLE);
+
+    let mut next = vec![];
+    for (series_id, volume) in volumes {
+        let mut query = Query::select();
+        query
+            .column((series_books, SeriesIden::SeriesId))
+            .expr_as(
+                Expr::col((series, SeriesIden::Title)),
+                SeriesIden::SeriesTitle,
+            )
+            .columns([
+                (series_books, SeriesIden::Volume),
+                (series_books, SeriesIden::BookId),
+            ])
+            .columns([(books, SeriesIden::Title), (books, SeriesIden::Author)])
+            .from(SeriesBook::table_ref())
+            .inner_join(
+                Series::table_ref(),
+                Expr::col((series, SeriesIden::Id)).equals((series_books, SeriesIden::SeriesId)),
+            )
+            .inner_join(
+                Book::table_ref(),
+                Expr::col((books, SeriesIden::Id)).equals((series_books, SeriesIden::BookId)),
+            )
+            .and_where(Expr::col((series_books, SeriesIden::SeriesId)).eq(series_id))
+            .and_where(Expr::col((series_books, SeriesIden::Volume)).gt(volume))
+            .order_by((series_books, SeriesIden::Volume), Order::Asc)
+            .limit(1);
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        if let Some(book) = query_as_with::<_, NextInSeries, _>(&sql, values)
+            .fetch_optional(db)
+            .await?
+        {
+            next.push(book);
+        }
+    }
+
+    Ok(next)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Local;
+    use sqlx::SqlitePool;
+
+    use crate::{model::circulation::Circulation, state::test_state};
+
+    use super::*;
+
+    #[sqlx::test(fixtures("users", "books"))]
+    fn following_a_series(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        let id = Series::create(
+            &state,
+            SeriesForCreate {
+                title: "Trilogy".to_string(),
+                description: None,
+            },
+        )
+        .await?;
+        Series::set_volume(&state, id, 1, 1.0).await?;
+        Series::set_volume(&state, id, 3, 2.0).await?;
+        // a novella between the two, added later
+        Series::set_volume(&state, id, 2, 1.5).await?;
+        assert!(matches!(
+            Series::set_volume(&state, id, 2, f64::NAN).await,
+            Err(Error::Conflict(_))
+        ));
+
+        let volumes = Series::volumes(&state, id).await?;
+        let order: Vec<_> = volumes.iter().map(|v| (v.volume, v.book.id)).collect();
+        assert_eq!(order, [(1.0, 1), (1.5, 2), (2.0, 3)]);
+
+        let next = Series::next_for_book(&state, 1).await?;
+        assert_eq!(next.len(), 1);
+        assert_eq!((next[0].book_id, next[0].volume), (2, 1.5));
+        assert_eq!(&next[0].series_title, "Trilogy");
+        assert!(Series::next_for_book(&state, 3).await?.is_empty());
+
+        // members are pointed past the furthest book they borrowed
+        let today = Local::now().date_naive();
+        assert!(Series::next_for_user(&state, 1).await?.is_empty());
+        Circulation::checkout(&state, 1, 2, 1, today).await?;
+        let next = Series::next_for_user(&state, 1).await?;
+        assert_eq!(next.len(), 1);
+        assert_eq!(next[0].book_id, 3);
+
+        Series::remove_book(&state, id, 2).await?;
+        let next = Series::next_for_book(&state, 1).await?;
+        assert_eq!(next[0].book_id, 3);
+
+        Ok(())
+    }
+}
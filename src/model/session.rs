@@ -0,0 +1,484 @@
+//! Login sessions and the refresh tokens renewing them.
+//!
+//! A refresh token is the session id and a secret, the secret changes every
+//! time the token is used. A secret that has already been swapped coming back
+//! means the token was copied, so the whole session is revoked.
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use modql::field::{Fields, HasSeaFields};
+use sea_query::{Expr, Iden, OnConflict, Order, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
+use serde::Serialize;
+use sqlx::{query_as_with, query_with, FromRow, SqliteConnection};
+use uuid::Uuid;
+
+use crate::{
+    auth::{random_token, token_hash},
+    state::AppState,
+};
+
+use super::{Model, Result};
+
+#[derive(Debug, Serialize, FromRow, Fields)]
+pub struct Session {
+    pub id: String,
+    pub user_id: i64,
+    #[serde(skip)]
+    pub refresh_hash: String,
+    /// The refresh token before the current one
+    #[serde(skip)]
+    pub previous_refresh_hash: Option<String>,
+    #[serde(skip)]
+    pub access_jti: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub expires_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub last_seen_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    /// The session making the request
+    #[field(skip)]
+    #[sqlx(skip)]
+    pub current: bool,
+}
+
+#[derive(Fields)]
+struct SessionForInsert {
+    id: String,
+    user_id: i64,
+    refresh_hash: String,
+    user_agent: Option<String>,
+    ip: Option<String>,
+    expires_at: NaiveDateTime,
+    last_seen_at: NaiveDateTime,
+}
+
+/// What became of a refresh token.
+#[derive(Debug)]
+pub enum Refresh {
+    Rotated {
+        session_id: String,
+        user_id: i64,
+        /// To use next time, the one given is spent
+        refresh_token: String,
+    },
+    /// Unknown, expired or revoked
+    Invalid,
+    /// The one before the current token, the session was revoked
+    Reused,
+}
+
+struct RevokedToken;
+
+#[derive(Iden)]
+enum SessionIden {
+    Id,
+    UserId,
+    RefreshHash,
+    PreviousRefreshHash,
+    AccessJti,
+    ExpiresAt,
+    RevokedAt,
+    UserAgent,
+    Ip,
+    LastSeenAt,
+    Jti,
+}
+
+impl Model for Session {
+    const TABLE: &'static str = "Sessions";
+}
+
+impl Model for RevokedToken {
+    const TABLE: &'static str = "RevokedTokens";
+}
+
+impl Session {
+    /// Open a session, returning its id and first refresh token.
+    pub async fn start(
+        state: &AppState<super::Engine>,
+        user_id: i64,
+        user_agent: Option<&str>,
+        ip: Option<&str>,
+    ) -> Result<(String, String)> {
+        let config = &state.config.auth;
+        let id = Uuid::new_v4().to_string();
+        let secret = random_token();
+        let now = Utc::now().naive_utc();
+
+        let session = SessionForInsert {
+            id: id.clone(),
+            user_id,
+            refresh_hash: token_hash(&secret),
+            user_agent: user_agent.map(str::to_owned),
+            ip: ip.map(str::to_owned),
+            expires_at: now + Duration::days(config.refresh_token_days),
+            last_seen_at: now,
+        };
+        let fields = session.not_none_sea_fields();
+        let (columns, values) = fields.for_sea_insert();
+        let mut query = Query::insert();
+        query
+            .into_table(Self::table_ref())
+            .columns(columns)
+            .values(values)?;
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&state.pool).await?;
+
+        let refresh_token = format!("{id}.{secret}");
+        Ok((id, refresh_token))
+    }
+
+    /// Swap a refresh token for a new one, extending the session.
+    pub async fn refresh(
+        state: &AppState<super::Engine>,
+        refresh_token: &str,
+        user_agent: Option<&str>,
+        ip: Option<&str>,
+    ) -> Result<Refresh> {
+        let config = &state.config.auth;
+        let Some((id, secret)) = refresh_token.split_once('.') else {
+            return Ok(Refresh::Invalid);
+        };
+        let now = Utc::now().naive_utc();
+
+        let mut tx = state.pool.begin().await?;
+
+        let Some(session) = get(&mut tx, id).await? else {
+            return Ok(Refresh::Invalid);
+        };
+        if session.revoked_at.is_some() || session.expires_at < now {
+            return Ok(Refresh::Invalid);
+        }
+        let hash = token_hash(secret);
+        if hash != session.refresh_hash {
+            // a spent token, anything else is only a guess at the session
+            if session.previous_refresh_hash.as_ref() != Some(&hash) {
+                return Ok(Refresh::Invalid);
+            }
+            revoke(&mut tx, state, id).await?;
+            tx.commit().await?;
+            return Ok(Refresh::Reused);
+        }
+
+        let secret = random_token();
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .values([
+                (SessionIden::RefreshHash, token_hash(&secret).into()),
+                (SessionIden::PreviousRefreshHash, hash.into()),
+                (
+                    SessionIden::ExpiresAt,
+                    (now + Duration::days(config.refresh_token_days)).into(),
+                ),
+                (SessionIden::UserAgent, user_agent.into()),
+                (SessionIden::Ip, ip.into()),
+                (SessionIden::LastSeenAt, now.into()),
+            ])
+            .and_where(Expr::col(SessionIden::Id).eq(id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        tx.commit().await?;
+
+        Ok(Refresh::Rotated {
+            session_id: session.id,
+            user_id: session.user_id,
+            refresh_token: format!("{id}.{secret}"),
+        })
+    }
+
+    /// Remember the access token last handed out for the session.
+    pub async fn set_access(state: &AppState<super::Engine>, id: &str, jti: &str) -> Result<()> {
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .value(SessionIden::AccessJti, jti)
+            .and_where(Expr::col(SessionIden::Id).eq(id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&state.pool).await?;
+        Ok(())
+    }
+
+    /// End a session, its refresh token and access tokens stop working.
+    pub async fn revoke(state: &AppState<super::Engine>, id: &str) -> Result<()> {
+        let mut tx = state.pool.begin().await?;
+        revoke(&mut tx, state, id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// A member's live sessions, most recently used first.
+    pub async fn list_for_user(
+        state: &AppState<super::Engine>,
+        user_id: i64,
+        current: Option<&str>,
+    ) -> Result<Vec<Session>> {
+        let mut query = Query::select();
+        query
+            .from(Self::table_ref())
+            .columns(Self::sea_idens())
+            .and_where(Expr::col(SessionIden::UserId).eq(user_id))
+            .and_where(Expr::col(SessionIden::RevokedAt).is_null())
+            .and_where(Expr::col(SessionIden::ExpiresAt).gt(Utc::now().naive_utc()))
+            .order_by(SessionIden::LastSeenAt, Order::Desc);
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let mut sessions = query_as_with::<_, Session, _>(&sql, values)
+            .fetch_all(&state.pool)
+            .await?;
+
+        for session in &mut sessions {
+            session.current = Some(session.id.as_str()) == current;
+        }
+        Ok(sessions)
+    }
+
+    /// End one of a member's sessions, `false` if they have no such session.
+    pub async fn revoke_for_user(
+        state: &AppState<super::Engine>,
+        id: &str,
+        user_id: i64,
+    ) -> Result<bool> {
+        let mut tx = state.pool.begin().await?;
+        match get(&mut tx, id).await? {
+            Some(session) if session.user_id == user_id && session.revoked_at.is_none() => {
+                revoke(&mut tx, state, id).await?;
+                tx.commit().await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// End every session of a user but `except`, returning how many there
+    /// were.
+    pub async fn revoke_all(
+        state: &AppState<super::Engine>,
+        user_id: i64,
+        except: Option<&str>,
+    ) -> Result<usize> {
+        let mut tx = state.pool.begin().await?;
+        let revoked = revoke_all(&mut tx, state, user_id, except).await?;
+        tx.commit().await?;
+        Ok(revoked)
+    }
+
+    /// Whether an access token was revoked, by itself or with its session.
+    pub async fn is_revoked(
+        state: &AppState<super::Engine>,
+        jti: &str,
+        session_id: &str,
+    ) -> Result<bool> {
+        let token = Query::select()
+            .expr(Expr::val(1))
+            .from(RevokedToken::table_ref())
+            .and_where(Expr::col(SessionIden::Jti).eq(jti))
+            .to_owned();
+        let session = Query::select()
+            .expr(Expr::val(1))
+            .from(Self::table_ref())
+            .and_where(Expr::col(SessionIden::Id).eq(session_id))
+            .and_where(Expr::col(SessionIden::RevokedAt).is_not_null())
+            .to_owned();
+
+        let mut query = Query::select();
+        query.expr(Expr::exists(token).or(Expr::exists(session)));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let (revoked,) = query_as_with::<_, (bool,), _>(&sql, values)
+            .fetch_one(&state.pool)
+            .await?;
+        Ok(revoked)
+    }
+}
+
+async fn get(conn: &mut SqliteConnection, id: &str) -> Result<Option<Session>> {
+    let mut query = Query::select();
+    query
+        .from(Session::table_ref())
+        .columns(Session::sea_idens())
+        .and_where(Expr::col(SessionIden::Id).eq(id));
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let session = query_as_with::<_, Session, _>(&sql, values)
+        .fetch_optional(conn)
+        .await?;
+    Ok(session)
+}
+
+async fn revoke(
+    conn: &mut SqliteConnection,
+    state: &AppState<super::Engine>,
+    id: &str,
+) -> Result<()> {
+    let now = Utc::now().naive_utc();
+
+    let mut query = Query::update();
+    query
+        .table(Session::table_ref())
+        .value(SessionIden::RevokedAt, now)
+        .and_where(Expr::col(SessionIden::Id).eq(id))
+        .and_where(Expr::col(SessionIden::RevokedAt).is_null())
+        .returning_col(SessionIden::AccessJti);
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let jti = query_as_with::<_, (Option<String>,), _>(&sql, values)
+        .fetch_optional(&mut *conn)
+        .await?
+        .and_then(|(jti,)| jti);
+
+    if let Some(jti) = jti {
+        let expires_at = now + Duration::minutes(state.config.auth.access_token_minutes);
+        revoke_token(conn, &jti, expires_at).await?;
+    }
+    Ok(())
+}
+
+/// End every session of a user but `except` as part of a larger change,
+/// returning how many there were.
+pub(super) async fn revoke_all(
+    conn: &mut SqliteConnection,
+    state: &AppState<super::Engine>,
+    user_id: i64,
+    except: Option<&str>,
+) -> Result<usize> {
+    let mut query = Query::select();
+    query
+        .column(SessionIden::Id)
+        .from(Session::table_ref())
+        .and_where(Expr::col(SessionIden::UserId).eq(user_id))
+        .and_where(Expr::col(SessionIden::RevokedAt).is_null())
+        .and_where_option(except.map(|id| Expr::col(SessionIden::Id).ne(id)));
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let ids = query_as_with::<_, (String,), _>(&sql, values)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    for (id,) in &ids {
+        revoke(conn, state, id).await?;
+    }
+    Ok(ids.len())
+}
+
+/// Put an access token on the revocation list until it would have expired.
+async fn revoke_token(
+    conn: &mut SqliteConnection,
+    jti: &str,
+    expires_at: NaiveDateTime,
+) -> Result<()> {
+    // tokens past their expiry are refused anyway
+    let mut query = Query::delete();
+    query
+        .from_table(RevokedToken::table_ref())
+        .and_where(Expr::col(SessionIden::ExpiresAt).lt(Utc::now().naive_utc()));
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    query_with(&sql, values).execute(&mut *conn).await?;
+
+    let mut query = Query::insert();
+    query
+        .into_table(RevokedToken::table_ref())
+        .columns([SessionIden::Jti, SessionIden::ExpiresAt])
+        .values([jti.into(), expires_at.into()])?
+        .on_conflict(OnConflict::column(SessionIden::Jti).do_nothing().to_owned());
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    query_with(&sql, values).execute(&mut *conn).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::SqlitePool;
+
+    use crate::state::test_state;
+
+    use super::*;
+
+    #[sqlx::test(fixtures("users"))]
+    fn rotating_refresh_tokens(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        let (id, first) = Session::start(&state, 1, None, None).await?;
+        Session::set_access(&state, &id, "first-jti").await?;
+        assert!(!Session::is_revoked(&state, "first-jti", &id).await?);
+
+        let Refresh::Rotated {
+            session_id,
+            user_id,
+            refresh_token: second,
+        } = Session::refresh(&state, &first, None, None).await?
+        else {
+            panic!("refresh failed");
+        };
+        assert_eq!((session_id.as_str(), user_id), (id.as_str(), 1));
+        let Refresh::Rotated { .. } = Session::refresh(&state, &second, None, None).await? else {
+            panic!("refresh failed");
+        };
+
+        // wrong secrets for the session are turned away, leaving it be
+        for token in [first.clone(), format!("{id}.garbage")] {
+            assert!(matches!(
+                Session::refresh(&state, &token, None, None).await?,
+                Refresh::Invalid
+            ));
+        }
+        assert!(!Session::is_revoked(&state, "first-jti", &id).await?);
+
+        // the second token was already swapped, someone else has it
+        assert!(matches!(
+            Session::refresh(&state, &second, None, None).await?,
+            Refresh::Reused
+        ));
+        assert!(Session::is_revoked(&state, "first-jti", &id).await?);
+        assert!(matches!(
+            Session::refresh(&state, "garbage", None, None).await?,
+            Refresh::Invalid
+        ));
+
+        // logging out
+        let (id, token) = Session::start(&state, 1, None, None).await?;
+        Session::set_access(&state, &id, "other-jti").await?;
+        Session::revoke(&state, &id).await?;
+        assert!(Session::is_revoked(&state, "other-jti", &id).await?);
+        assert!(matches!(
+            Session::refresh(&state, &token, None, None).await?,
+            Refresh::Invalid
+        ));
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("users"))]
+    fn managing_sessions(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        let (phone, _) = Session::start(&state, 1, Some("Phone"), Some("10.0.0.2")).await?;
+        let (laptop, token) = Session::start(&state, 1, Some("Laptop"), None).await?;
+        Session::start(&state, 2, None, None).await?;
+
+        // seen again from elsewhere
+        Session::refresh(&state, &token, Some("Laptop"), Some("10.0.0.3")).await?;
+        let sessions = Session::list_for_user(&state, 1, Some(&laptop)).await?;
+        let seen: Vec<_> = sessions
+            .iter()
+            .map(|s| (s.id.as_str(), s.ip.as_deref(), s.current))
+            .collect();
+        assert_eq!(
+            seen,
+            [
+                (laptop.as_str(), Some("10.0.0.3"), true),
+                (phone.as_str(), Some("10.0.0.2"), false),
+            ]
+        );
+
+        // members only sign out their own sessions
+        assert!(!Session::revoke_for_user(&state, &phone, 2).await?);
+        assert!(Session::revoke_for_user(&state, &phone, 1).await?);
+        assert!(!Session::revoke_for_user(&state, &phone, 1).await?);
+        assert_eq!(Session::list_for_user(&state, 1, None).await?.len(), 1);
+
+        assert_eq!(Session::revoke_all(&state, 1, None).await?, 1);
+        assert!(Session::list_for_user(&state, 1, None).await?.is_empty());
+        assert_eq!(Session::list_for_user(&state, 2, None).await?.len(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,523 @@
+//! Shelf audits.
+//!
+//! Staff open a stocktake for a branch, or one shelf in it, and upload the
+//! barcodes they scan there. The report compares them with the copies that
+//! should be on those shelves.
+
+use std::collections::HashSet;
+
+use chrono::NaiveDateTime;
+use modql::{
+    field::{Fields, HasSeaFields},
+    SIden,
+};
+use sea_query::{Expr, Iden, OnConflict, Order, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
+use serde::{Deserialize, Serialize};
+use sqlx::{query_as_with, query_with, FromRow, SqliteConnection, Type};
+
+use crate::state::AppState;
+
+use super::{
+    book::{Book, BookCopy, BorrowStatus},
+    branch::Branch,
+    error::Error,
+    Model, Result,
+};
+
+#[derive(Debug, Clone, Serialize, FromRow, Fields)]
+pub struct Stocktake {
+    pub id: i64,
+    pub branch_id: i64,
+    /// Only this shelf is audited when set, otherwise the whole branch
+    pub shelf_location_id: Option<i64>,
+    pub status: StocktakeStatus,
+    pub started_by: Option<i64>,
+    pub started_at: NaiveDateTime,
+    pub closed_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum StocktakeStatus {
+    #[default]
+    Open,
+    Closed,
+}
+
+impl From<StocktakeStatus> for sea_query::Value {
+    fn from(val: StocktakeStatus) -> Self {
+        match val {
+            StocktakeStatus::Open => "open".into(),
+            StocktakeStatus::Closed => "closed".into(),
+        }
+    }
+}
+
+impl sea_query::Nullable for StocktakeStatus {
+    fn null() -> sea_query::Value {
+        sea_query::Value::String(None)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StocktakeForCreate {
+    pub branch_id: i64,
+    pub shelf_location_id: Option<i64>,
+}
+
+#[derive(Debug, Fields)]
+struct StocktakeForInsert {
+    branch_id: i64,
+    shelf_location_id: Option<i64>,
+    started_by: i64,
+}
+
+/// A copy as found by a stocktake.
+#[derive(Debug, Serialize, FromRow)]
+pub struct StockedCopy {
+    pub book_id: i64,
+    pub copy_id: i64,
+    pub title: String,
+    pub barcode: Option<String>,
+    pub status: Option<BorrowStatus>,
+    pub branch_id: Option<i64>,
+    pub shelf_location_id: Option<i64>,
+}
+
+/// Barcodes added to a stocktake.
+#[derive(Debug, Serialize)]
+pub struct Scanned {
+    pub added: u64,
+    /// Already scanned in this stocktake
+    pub duplicates: u64,
+    /// Matching no copy
+    pub unknown: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StocktakeReport {
+    pub stocktake: Stocktake,
+    /// Copies that should be on the audited shelves
+    pub expected: usize,
+    pub seen: usize,
+    /// Expected but not scanned
+    pub not_seen: Vec<StockedCopy>,
+    /// Scanned but recorded at another branch or shelf
+    pub wrong_location: Vec<StockedCopy>,
+    /// Scanned but recorded as on loan, lost, withdrawn...
+    pub wrong_status: Vec<StockedCopy>,
+    /// Scanned barcodes matching no copy
+    pub unknown: Vec<String>,
+}
+
+pub(super) struct StocktakeScan;
+
+#[derive(Iden)]
+enum StocktakeIden {
+    Id,
+    BookId,
+    CopyId,
+    StocktakeId,
+    Barcode,
+    Title,
+    Status,
+    StatusReason,
+    BranchId,
+    ShelfLocationId,
+    ClosedAt,
+}
+
+impl Model for Stocktake {
+    const TABLE: &'static str = "Stocktakes";
+}
+
+impl Model for StocktakeScan {
+    const TABLE: &'static str = "StocktakeScans";
+}
+
+impl Stocktake {
+    pub async fn get(state: &AppState<super::Engine>, id: i64) -> Result<Stocktake> {
+        super::get::<Self, _>(state, id).await
+    }
+
+    pub async fn list(state: &AppState<super::Engine>) -> Result<Vec<Stocktake>> {
+        super::list::<Self, _>(state).await
+    }
+
+    pub async fn create(
+        state: &AppState<super::Engine>,
+        stocktake: StocktakeForCreate,
+        user_id: i64,
+    ) -> Result<i64> {
+        if let Some(shelf_id) = stocktake.shelf_location_id {
+            let shelf = Branch::get_shelf(state, shelf_id).await?;
+            if shelf.branch_id != stocktake.branch_id {
+                return Err(Error::Conflict("Shelf is not in that branch"));
+            }
+        }
+
+        let stocktake = StocktakeForInsert {
+            branch_id: stocktake.branch_id,
+            shelf_location_id: stocktake.shelf_location_id,
+            started_by: user_id,
+        };
+        super::create::<Self, _>(state, stocktake).await
+    }
+
+    /// Record scanned copy barcodes, scanning a copy twice is harmless.
+    pub async fn scan(
+        state: &AppState<super::Engine>,
+        id: i64,
+        barcodes: &[String],
+    ) -> Result<Scanned> {
+        let stocktake = Self::get(state, id).await?;
+        if stocktake.status != StocktakeStatus::Open {
+            return Err(Error::Conflict("Stocktake is closed"));
+        }
+
+        let mut scanned = Scanned {
+            added: 0,
+            duplicates: 0,
+            unknown: vec![],
+        };
+        let mut copies = vec![];
+        for barcode in barcodes {
+            let copy = Book::get_copy_by_barcode(state, barcode).await?;
+            if copy.is_none() {
+                scanned.unknown.push(barcode.clone());
+            }
+            copies.push((barcode, copy));
+        }
+
+        let mut tx = state.pool.begin().await?;
+        for (barcode, copy) in copies {
+            let mut query = Query::insert();
+            query
+                .into_table(StocktakeScan::table_ref())
+                .columns([
+                    StocktakeIden::StocktakeId,
+                    StocktakeIden::Barcode,
+                    StocktakeIden::BookId,
+                    StocktakeIden::CopyId,
+                ])
+                .values([
+                    id.into(),
+                    barcode.into(),
+                    copy.as_ref().map(|c| c.book_id).into(),
+                    copy.as_ref().map(|c| c.id).into(),
+                ])?
+                .on_conflict(
+                    OnConflict::columns([StocktakeIden::StocktakeId, StocktakeIden::Barcode])
+                        .do_nothing()
+                        .to_owned(),
+                );
+            let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+            match query_with(&sql, values)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected()
+            {
+                0 => scanned.duplicates += 1,
+                _ => scanned.added += 1,
+            }
+        }
+        tx.commit().await?;
+
+        Ok(scanned)
+    }
+
+    pub async fn report(state: &AppState<super::Engine>, id: i64) -> Result<StocktakeReport> {
+        let stocktake = Self::get(state, id).await?;
+        let mut conn = state.pool.acquire().await?;
+
+        let expected = expected_copies(&mut conn, &stocktake).await?;
+        let seen = seen_copies(&mut conn, id).await?;
+        let seen_ids: HashSet<_> = seen.iter().map(|c| (c.book_id, c.copy_id)).collect();
+
+        let expected_count = expected.len();
+        let not_seen = expected
+            .into_iter()
+            .filter(|c| !seen_ids.contains(&(c.book_id, c.copy_id)))
+            .collect();
+
+        let mut wrong_location = vec![];
+        let mut wrong_status = vec![];
+        let seen_count = seen.len();
+        for copy in seen {
+            if !stocktake.covers(&copy) {
+                wrong_location.push(copy);
+            } else if !matches!(
+                copy.status,
+                Some(BorrowStatus::Available | BorrowStatus::Reserved)
+            ) {
+                wrong_status.push(copy);
+            }
+        }
+
+        let mut query = Query::select();
+        query
+            .column(StocktakeIden::Barcode)
+            .from(StocktakeScan::table_ref())
+            .and_where(Expr::col(StocktakeIden::StocktakeId).eq(id))
+            .and_where(Expr::col(StocktakeIden::BookId).is_null())
+            .order_by(StocktakeIden::Id, Order::Asc);
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let unknown = query_as_with::<_, (String,), _>(&sql, values)
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|(barcode,)| barcode)
+            .collect();
+
+        Ok(StocktakeReport {
+            stocktake,
+            expected: expected_count,
+            seen: seen_count,
+            not_seen,
+            wrong_location,
+            wrong_status,
+            unknown,
+        })
+    }
+
+    /// Finish a stocktake, optionally marking the copies that weren't seen as
+    /// missing. Returns how many were.
+    pub async fn close(
+        state: &AppState<super::Engine>,
+        id: i64,
+        mark_missing: bool,
+    ) -> Result<u64> {
+        let stocktake = Self::get(state, id).await?;
+        if stocktake.status != StocktakeStatus::Open {
+            return Err(Error::Conflict("Stocktake is closed"));
+        }
+        let mut tx = state.pool.begin().await?;
+
+        let mut missing = 0;
+        if mark_missing {
+            let seen: HashSet<_> = seen_copies(&mut tx, id)
+                .await?
+                .into_iter()
+                .map(|c| (c.book_id, c.copy_id))
+                .collect();
+            let reason = format!("Not seen in stocktake {id}");
+            for copy in expected_copies(&mut tx, &stocktake).await? {
+                if seen.contains(&(copy.book_id, copy.copy_id)) {
+                    continue;
+                }
+                let mut query = Query::update();
+                query
+                    .table(BookCopy::table_ref())
+                    .values([
+                        (StocktakeIden::Status, BorrowStatus::Missing.into()),
+                        (StocktakeIden::StatusReason, reason.as_str().into()),
+                    ])
+                    .and_where(Expr::col(StocktakeIden::BookId).eq(copy.book_id))
+                    .and_where(Expr::col(StocktakeIden::Id).eq(copy.copy_id));
+                let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+                missing += query_with(&sql, values)
+                    .execute(&mut *tx)
+                    .await?
+                    .rows_affected();
+            }
+        }
+
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .values([
+                (StocktakeIden::Status, StocktakeStatus::Closed.into()),
+                (StocktakeIden::ClosedAt, Expr::current_timestamp().into()),
+            ])
+            .and_where(Expr::col(StocktakeIden::Id).eq(id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        tx.commit().await?;
+
+        Ok(missing)
+    }
+
+    /// Whether `copy` is recorded on the shelves this stocktake audits.
+    fn covers(&self, copy: &StockedCopy) -> bool {
+        copy.branch_id == Some(self.branch_id)
+            && self
+                .shelf_location_id
+                .is_none_or(|shelf| copy.shelf_location_id == Some(shelf))
+    }
+}
+
+fn stocked_copy_query() -> sea_query::SelectStatement {
+    let books = SIden(Book::TABLE);
+    let book_copies = SIden(BookCopy::TABLE);
+
+    let mut query = Query::select();
+    query
+        .column((book_copies, StocktakeIden::BookId))
+        .expr_as(
+            Expr::col((book_copies, StocktakeIden::Id)),
+            StocktakeIden::CopyId,
+        )
+        .column((books, StocktakeIden::Title))
+        .columns([
+            (book_copies, StocktakeIden::Barcode),
+            (book_copies, StocktakeIden::Status),
+            (book_copies, StocktakeIden::BranchId),
+            (book_copies, StocktakeIden::ShelfLocationId),
+        ])
+        .from(BookCopy::table_ref())
+        .inner_join(
+            Book::table_ref(),
+            Expr::col((books, StocktakeIden::Id)).equals((book_copies, StocktakeIden::BookId)),
+        )
+        .order_by((book_copies, StocktakeIden::BookId), Order::Asc)
+        .order_by((book_copies, StocktakeIden::Id), Order::Asc);
+    query
+}
+
+/// Copies that should be on the shelves, those on loan, in transit or
+/// otherwise away are not expected.
+async fn expected_copies(
+    conn: &mut SqliteConnection,
+    stocktake: &Stocktake,
+) -> Result<Vec<StockedCopy>> {
+    let book_copies = SIden(BookCopy::TABLE);
+
+    let mut query = stocked_copy_query();
+    query
+        .and_where(Expr::col((book_copies, StocktakeIden::BranchId)).eq(stocktake.branch_id))
+        .and_where(Expr::col((book_copies, StocktakeIden::Status)).eq(BorrowStatus::Available));
+    if let Some(shelf) = stocktake.shelf_location_id {
+        query.and_where(Expr::col((book_copies, StocktakeIden::ShelfLocationId)).eq(shelf));
+    }
+
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let copies = query_as_with::<_, StockedCopy, _>(&sql, values)
+        .fetch_all(conn)
+        .await?;
+    Ok(copies)
+}
+
+async fn seen_copies(conn: &mut SqliteConnection, id: i64) -> Result<Vec<StockedCopy>> {
+    let book_copies = SIden(BookCopy::TABLE);
+    let scans = SIden(StocktakeScan::TABLE);
+
+    let mut query = stocked_copy_query();
+    query
+        .inner_join(
+            StocktakeScan::table_ref(),
+            Expr::col((scans, StocktakeIden::BookId))
+                .equals((book_copies, StocktakeIden::BookId))
+                .and(
+                    Expr::col((scans, StocktakeIden::CopyId))
+                        .equals((book_copies, StocktakeIden::Id)),
+                ),
+        )
+        .and_where(Expr::col((scans, StocktakeIden::StocktakeId)).eq(id));
+
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let copies = query_as_with::<_, StockedCopy, _>(&sql, values)
+        .fetch_all(conn)
+        .await?;
+    Ok(copies)
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::SqlitePool;
+
+    use crate::{
+        model::{
+            book::BookCopyForUpdate,
+            branch::{BranchForCreate, ShelfLocationForCreate},
+            circulation::Circulation,
+        },
+        state::test_state,
+    };
+
+    use super::*;
+
+    #[sqlx::test(fixtures("users", "books"))]
+    fn auditing_a_shelf(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        Book::assign_barcodes(&state).await?;
+        let branch = Branch::create(
+            &state,
+            BranchForCreate {
+                name: "Main".to_string(),
+                code: None,
+                address: None,
+            },
+        )
+        .await?;
+        let shelf = Branch::add_shelf(
+            &state,
+            ShelfLocationForCreate {
+                branch_id: branch,
+                floor: None,
+                name: "A".to_string(),
+            },
+        )
+        .await?;
+        let other = Branch::add_shelf(
+            &state,
+            ShelfLocationForCreate {
+                branch_id: branch,
+                floor: None,
+                name: "B".to_string(),
+            },
+        )
+        .await?;
+        for (copy_id, shelf) in [(1, shelf), (2, shelf), (3, shelf), (4, other)] {
+            Book::update_copy(
+                &state,
+                copy_id,
+                1,
+                BookCopyForUpdate {
+                    shelf_location_id: Some(shelf),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        }
+        Circulation::checkout(&state, 1, 1, 3, chrono::Utc::now().date_naive()).await?;
+
+        let id = Stocktake::create(
+            &state,
+            StocktakeForCreate {
+                branch_id: branch,
+                shelf_location_id: Some(shelf),
+            },
+            2,
+        )
+        .await?;
+        let mut barcodes = vec![];
+        for copy_id in [1, 3, 4, 1] {
+            barcodes.push(Book::get_copy(&state, copy_id, 1).await?.barcode.unwrap());
+        }
+        barcodes.push("unknown".to_string());
+        let scanned = Stocktake::scan(&state, id, &barcodes).await?;
+        assert_eq!((scanned.added, scanned.duplicates), (4, 1));
+
+        let report = Stocktake::report(&state, id).await?;
+        assert_eq!((report.expected, report.seen), (2, 3));
+        let ids = |copies: &[StockedCopy]| copies.iter().map(|c| c.copy_id).collect::<Vec<_>>();
+        assert_eq!(ids(&report.not_seen), [2]);
+        assert_eq!(ids(&report.wrong_location), [4]);
+        assert_eq!(ids(&report.wrong_status), [3]);
+        assert_eq!(report.unknown, ["unknown"]);
+
+        assert_eq!(Stocktake::close(&state, id, true).await?, 1);
+        let copy = Book::get_copy(&state, 2, 1).await?;
+        assert!(matches!(copy.status, Some(BorrowStatus::Missing)));
+        assert!(matches!(
+            Stocktake::scan(&state, id, &barcodes).await,
+            Err(Error::Conflict(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,458 @@
+use chrono::{NaiveDateTime, Utc};
+use modql::field::{Fields, HasSeaFields};
+use sea_query::{Condition, Expr, Iden, Order, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
+use serde::{Deserialize, Serialize};
+use sqlx::{query_as_with, query_with, FromRow, SqliteConnection, Type};
+
+use crate::state::AppState;
+
+use super::{
+    book::{BookCopy, BorrowStatus},
+    circulation::{get_copy, ready_hold, set_copy_status, waiting_hold, Hold},
+    error::Error,
+    reservation::ReservationStatus,
+    Model, Result,
+};
+
+#[derive(Debug, Serialize, FromRow, Fields)]
+pub struct Transfer {
+    pub id: i64,
+    pub book_id: i64,
+    pub copy_id: i64,
+    pub from_branch_id: i64,
+    pub to_branch_id: i64,
+    pub status: TransferStatus,
+    pub sent_by: Option<i64>,
+    pub received_by: Option<i64>,
+    pub sent_at: NaiveDateTime,
+    pub received_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum TransferStatus {
+    #[default]
+    InTransit,
+    Received,
+    Cancelled,
+}
+
+impl From<TransferStatus> for sea_query::Value {
+    fn from(val: TransferStatus) -> Self {
+        match val {
+            TransferStatus::InTransit => "in_transit".into(),
+            TransferStatus::Received => "received".into(),
+            TransferStatus::Cancelled => "cancelled".into(),
+        }
+    }
+}
+
+impl sea_query::Nullable for TransferStatus {
+    fn null() -> sea_query::Value {
+        sea_query::Value::String(None)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransferForCreate {
+    pub book_id: i64,
+    pub copy_id: i64,
+    pub to_branch_id: i64,
+}
+
+#[derive(Debug, Fields)]
+struct TransferForInsert {
+    book_id: i64,
+    copy_id: i64,
+    from_branch_id: i64,
+    to_branch_id: i64,
+    sent_by: i64,
+}
+
+/// Filters of the transfer list.
+#[derive(Debug, Default, Deserialize)]
+pub struct TransferFilter {
+    /// Transfers headed to this branch
+    pub to_branch: Option<i64>,
+    pub status: Option<TransferStatus>,
+}
+
+/// What happened to a copy on arrival.
+#[derive(Debug, Serialize)]
+pub struct Receipt {
+    pub transfer_id: i64,
+    /// Hold the copy is now put aside for
+    pub hold: Option<Hold>,
+}
+
+#[derive(Iden)]
+enum TransferIden {
+    Id,
+    BookId,
+    CopyId,
+    ToBranchId,
+    Status,
+    ReceivedBy,
+    ReceivedAt,
+    BranchId,
+    ShelfLocationId,
+}
+
+impl Model for Transfer {
+    const TABLE: &'static str = "Transfers";
+}
+
+impl Transfer {
+    pub async fn get(state: &AppState<super::Engine>, id: i64) -> Result<Transfer> {
+        super::get::<Self, _>(state, id).await
+    }
+
+    pub async fn list(
+        state: &AppState<super::Engine>,
+        filter: &TransferFilter,
+    ) -> Result<Vec<Transfer>> {
+        let db = &state.pool;
+
+        let mut cond = Condition::all();
+        if let Some(to_branch) = filter.to_branch {
+            cond = cond.add(Expr::col(TransferIden::ToBranchId).eq(to_branch));
+        }
+        if let Some(status) = filter.status {
+            cond = cond.add(Expr::col(TransferIden::Status).eq(status));
+        }
+
+        let mut query = Query::select();
+        query
+            .from(Self::table_ref())
+            .columns(Self::sea_idens())
+            .cond_where(cond)
+            .order_by(TransferIden::Id, Order::Desc);
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let transfers = query_as_with::<_, Transfer, _>(&sql, values)
+            .fetch_all(db)
+            .await?;
+
+        Ok(transfers)
+    }
+
+    /// Send a copy from the branch it is at to another one.
+    ///
+    /// Copies on the shelf or put aside for a hold can be sent.
+    pub async fn send(
+        state: &AppState<super::Engine>,
+        transfer: TransferForCreate,
+        user_id: i64,
+    ) -> Result<i64> {
+        let mut tx = state.pool.begin().await?;
+
+        let copy = get_copy(&mut tx, transfer.book_id, transfer.copy_id)
+            .await?
+            .ok_or(Error::EntityNotFound {
+                entity: BookCopy::TABLE,
+                id: transfer.copy_id,
+            })?;
+        let Some(from_branch_id) = copy.branch_id else {
+            return Err(Error::Conflict("Copy is not at any branch"));
+        };
+        if from_branch_id == transfer.to_branch_id {
+            return Err(Error::Conflict("Copy is already at that branch"));
+        }
+        if !matches!(
+            copy.status,
+            Some(BorrowStatus::Available | BorrowStatus::Reserved)
+        ) {
+            return Err(Error::Conflict("Copy is not on the shelf"));
+        }
+
+        let fields = TransferForInsert {
+            book_id: transfer.book_id,
+            copy_id: transfer.copy_id,
+            from_branch_id,
+            to_branch_id: transfer.to_branch_id,
+            sent_by: user_id,
+        }
+        .not_none_sea_fields();
+        let (columns, sea_values) = fields.for_sea_insert();
+
+        let mut query = Query::insert();
+        query
+            .into_table(Self::table_ref())
+            .columns(columns)
+            .values(sea_values)?
+            .returning_col(TransferIden::Id);
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let (id,) = query_as_with::<_, (i64,), _>(&sql, values)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        set_copy_status(
+            &mut tx,
+            transfer.book_id,
+            transfer.copy_id,
+            BorrowStatus::InTransit,
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(id)
+    }
+
+    /// Confirm a copy arrived.
+    ///
+    /// A copy someone is waiting for is put aside, and their hold becomes
+    /// ready for collection when this is their pickup branch.
+    pub async fn receive(
+        state: &AppState<super::Engine>,
+        id: i64,
+        user_id: i64,
+    ) -> Result<Receipt> {
+        let mut tx = state.pool.begin().await?;
+
+        let transfer = Self::close(&mut tx, id, TransferStatus::Received, Some(user_id)).await?;
+
+        // shelves belong to the branch the copy left
+        let mut query = Query::update();
+        query
+            .table(BookCopy::table_ref())
+            .values([
+                (TransferIden::BranchId, transfer.to_branch_id.into()),
+                (TransferIden::ShelfLocationId, Option::<i64>::None.into()),
+            ])
+            .and_where(Expr::col(TransferIden::BookId).eq(transfer.book_id))
+            .and_where(Expr::col(TransferIden::Id).eq(transfer.copy_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        let hold = waiting_hold(&mut tx, transfer.book_id, transfer.copy_id).await?;
+        let status = match &hold {
+            Some(hold) => {
+                let today = Utc::now().date_naive();
+                ready_hold(
+                    &mut tx,
+                    state,
+                    hold,
+                    transfer.book_id,
+                    Some(transfer.to_branch_id),
+                    today,
+                )
+                .await?;
+                BorrowStatus::Reserved
+            }
+            None => BorrowStatus::Available,
+        };
+        set_copy_status(&mut tx, transfer.book_id, transfer.copy_id, status).await?;
+
+        tx.commit().await?;
+
+        Ok(Receipt {
+            transfer_id: id,
+            hold,
+        })
+    }
+
+    /// Call off a transfer, the copy stays at the branch it was sent from.
+    pub async fn cancel(state: &AppState<super::Engine>, id: i64) -> Result<()> {
+        let mut tx = state.pool.begin().await?;
+
+        let transfer = Self::close(&mut tx, id, TransferStatus::Cancelled, None).await?;
+        let status = match waiting_hold(&mut tx, transfer.book_id, transfer.copy_id).await? {
+            Some(_) => BorrowStatus::Reserved,
+            None => BorrowStatus::Available,
+        };
+        set_copy_status(&mut tx, transfer.book_id, transfer.copy_id, status).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Move an in transit transfer to `status`.
+    async fn close(
+        conn: &mut SqliteConnection,
+        id: i64,
+        status: TransferStatus,
+        received_by: Option<i64>,
+    ) -> Result<Transfer> {
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .value(TransferIden::Status, status)
+            .and_where(Expr::col(TransferIden::Id).eq(id))
+            .and_where(Expr::col(TransferIden::Status).eq(TransferStatus::InTransit))
+            .returning(Query::returning().columns(Self::sea_idens()));
+        if let Some(user_id) = received_by {
+            query.values([
+                (TransferIden::ReceivedBy, user_id.into()),
+                (TransferIden::ReceivedAt, Expr::current_timestamp().into()),
+            ]);
+        }
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        match query_as_with::<_, Transfer, _>(&sql, values)
+            .fetch_optional(&mut *conn)
+            .await?
+        {
+            Some(transfer) => Ok(transfer),
+            None => {
+                let mut query = Query::select();
+                query
+                    .column(TransferIden::Id)
+                    .from(Self::table_ref())
+                    .and_where(Expr::col(TransferIden::Id).eq(id));
+                let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+                match query_as_with::<_, (i64,), _>(&sql, values)
+                    .fetch_optional(&mut *conn)
+                    .await?
+                {
+                    Some(_) => Err(Error::Conflict("Transfer is no longer in transit")),
+                    None => Err(Error::EntityNotFound {
+                        entity: Self::TABLE,
+                        id,
+                    }),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+    use sqlx::SqlitePool;
+
+    use crate::{
+        model::{
+            book::{Book, BookCopyForUpdate},
+            branch::{Branch, BranchForCreate},
+            circulation::{Action, BlockReason, Circulation, Outcome},
+            reservation::{Reservation, ReservationForCreate},
+        },
+        state::test_state,
+    };
+
+    use super::*;
+
+    async fn branch(state: &AppState<super::super::Engine>, name: &str) -> Result<i64> {
+        Branch::create(
+            state,
+            BranchForCreate {
+                name: name.to_string(),
+                code: None,
+                address: None,
+            },
+        )
+        .await
+    }
+
+    #[sqlx::test(fixtures("users", "books"))]
+    fn holds_are_ready_on_receipt(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        let main = branch(&state, "Main").await?;
+        let east = branch(&state, "East").await?;
+        Book::update_copy(
+            &state,
+            1,
+            1,
+            BookCopyForUpdate {
+                branch_id: Some(main),
+                ..Default::default()
+            },
+        )
+        .await?;
+        let reservation_id = Reservation::create(
+            &state,
+            ReservationForCreate {
+                copy_id: 1,
+                book_id: 1,
+                user_id: 2,
+                reservation_date: None,
+                pickup_branch_id: Some(east),
+                any_edition: false,
+            },
+        )
+        .await?;
+
+        let transfer = TransferForCreate {
+            book_id: 1,
+            copy_id: 1,
+            to_branch_id: east,
+        };
+        let id = Transfer::send(&state, transfer, 3).await?;
+        let copy = Book::get_copy(&state, 1, 1).await?;
+        assert!(matches!(copy.status, Some(BorrowStatus::InTransit)));
+
+        let today = NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+        let outcome = Circulation::checkout(&state, 2, 1, 1, today).await?;
+        assert_eq!(
+            outcome,
+            Outcome::Blocked {
+                action: Action::Checkout,
+                reason: BlockReason::NotAvailable
+            }
+        );
+
+        let receipt = Transfer::receive(&state, id, 3).await?;
+        assert_eq!(receipt.hold.map(|h| h.reservation_id), Some(reservation_id));
+        let copy = Book::get_copy(&state, 1, 1).await?;
+        assert!(matches!(copy.status, Some(BorrowStatus::Reserved)));
+        assert_eq!(copy.branch_id, Some(east));
+        let reservation = Reservation::get(&state, reservation_id).await?;
+        assert!(matches!(reservation.status, ReservationStatus::Active));
+
+        assert!(matches!(
+            Transfer::receive(&state, id, 3).await,
+            Err(Error::Conflict(_))
+        ));
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("users", "books"))]
+    fn cancelling_a_transfer(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        let main = branch(&state, "Main").await?;
+        let east = branch(&state, "East").await?;
+
+        let transfer = TransferForCreate {
+            book_id: 1,
+            copy_id: 1,
+            to_branch_id: east,
+        };
+        // the copy isn't at a branch yet
+        assert!(matches!(
+            Transfer::send(&state, transfer, 3).await,
+            Err(Error::Conflict(_))
+        ));
+
+        Book::update_copy(
+            &state,
+            1,
+            1,
+            BookCopyForUpdate {
+                branch_id: Some(main),
+                ..Default::default()
+            },
+        )
+        .await?;
+        let transfer = TransferForCreate {
+            book_id: 1,
+            copy_id: 1,
+            to_branch_id: east,
+        };
+        let id = Transfer::send(&state, transfer, 3).await?;
+        Transfer::cancel(&state, id).await?;
+
+        let copy = Book::get_copy(&state, 1, 1).await?;
+        assert!(matches!(copy.status, Some(BorrowStatus::Available)));
+        assert_eq!(copy.branch_id, Some(main));
+        let transfer = Transfer::get(&state, id).await?;
+        assert_eq!(transfer.status, TransferStatus::Cancelled);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,468 @@
+//! Second factor for signing in: a TOTP authenticator app, with single use
+//! recovery codes for when the device is lost.
+
+use chrono::{NaiveDateTime, Utc};
+use sea_query::{Expr, Iden, OnConflict, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
+use serde::Serialize;
+use sqlx::{query_as_with, query_with, FromRow, SqliteConnection};
+
+use crate::{
+    auth::{random_token, token_hash},
+    state::AppState,
+    totp,
+};
+
+use super::{
+    error::Error,
+    session,
+    user::{self, UserRole},
+    Model, Result,
+};
+
+/// Recovery codes handed out at a time.
+const RECOVERY_CODES: usize = 10;
+
+#[derive(Debug, FromRow)]
+pub struct TwoFactor {
+    pub user_id: i64,
+    pub secret: String,
+    /// Unset while enrollment waits for the first code
+    pub enabled_at: Option<NaiveDateTime>,
+    pub last_step: Option<i64>,
+}
+
+/// What a user sees of their second factor.
+#[derive(Debug, Serialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    /// Their role's policy says they can't do without
+    pub required: bool,
+    pub recovery_codes: i64,
+}
+
+/// Who has to sign in with a second factor, set by admins.
+#[derive(Debug, Serialize, FromRow)]
+pub struct TwoFactorPolicy {
+    /// Admins and issuers can't do without
+    pub require_staff: bool,
+    pub updated_by: Option<i64>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Iden)]
+enum TwoFactorPolicyIden {
+    Id,
+    RequireStaff,
+    UpdatedBy,
+    UpdatedAt,
+}
+
+impl Model for TwoFactorPolicy {
+    const TABLE: &'static str = "TwoFactorPolicy";
+}
+
+#[derive(Iden)]
+enum TwoFactorIden {
+    UserId,
+    Secret,
+    EnabledAt,
+    LastStep,
+}
+
+struct RecoveryCode;
+
+impl Model for RecoveryCode {
+    const TABLE: &'static str = "RecoveryCodes";
+}
+
+#[derive(Iden)]
+enum RecoveryCodeIden {
+    UserId,
+    CodeHash,
+    UsedAt,
+}
+
+impl Model for TwoFactor {
+    const TABLE: &'static str = "TwoFactors";
+}
+
+impl TwoFactorPolicy {
+    pub async fn get(state: &AppState<super::Engine>) -> Result<Self> {
+        let mut query = Query::select();
+        query
+            .columns([
+                TwoFactorPolicyIden::RequireStaff,
+                TwoFactorPolicyIden::UpdatedBy,
+                TwoFactorPolicyIden::UpdatedAt,
+            ])
+            .from(Self::table_ref())
+            .and_where(Expr::col(TwoFactorPolicyIden::Id).eq(1));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        Ok(query_as_with(&sql, values).fetch_one(&state.pool).await?)
+    }
+
+    /// Whether users of `role` have to sign in with a second factor.
+    pub async fn requires(state: &AppState<super::Engine>, role: &UserRole) -> Result<bool> {
+        Ok(matches!(role, UserRole::Admin | UserRole::Issuer)
+            && Self::get(state).await?.require_staff)
+    }
+
+    /// Change the policy. Turning it on ends the sessions of staff without a
+    /// second factor, they set one up at their next login. Returns how many
+    /// sessions were ended.
+    pub async fn set(
+        state: &AppState<super::Engine>,
+        require_staff: bool,
+        admin_id: i64,
+    ) -> Result<usize> {
+        let mut tx = state.pool.begin().await?;
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .value(TwoFactorPolicyIden::RequireStaff, require_staff)
+            .value(TwoFactorPolicyIden::UpdatedBy, admin_id)
+            .value(TwoFactorPolicyIden::UpdatedAt, Utc::now().naive_utc())
+            .and_where(Expr::col(TwoFactorPolicyIden::Id).eq(1));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        let mut ended = 0;
+        if require_staff {
+            let mut query = Query::select();
+            query
+                .column(TwoFactorIden::UserId)
+                .from(TwoFactor::table_ref())
+                .and_where(Expr::col(TwoFactorIden::EnabledAt).is_not_null());
+            let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+            let enabled: Vec<(i64,)> = query_as_with(&sql, values).fetch_all(&mut *tx).await?;
+
+            for user_id in user::staff_ids(&mut tx).await? {
+                if !enabled.contains(&(user_id,)) {
+                    ended += session::revoke_all(&mut tx, state, user_id, None).await?;
+                }
+            }
+        }
+        tx.commit().await?;
+        Ok(ended)
+    }
+}
+
+impl TwoFactor {
+    pub async fn get(state: &AppState<super::Engine>, user_id: i64) -> Result<Option<Self>> {
+        let mut query = Query::select();
+        query
+            .columns([
+                TwoFactorIden::UserId,
+                TwoFactorIden::Secret,
+                TwoFactorIden::EnabledAt,
+                TwoFactorIden::LastStep,
+            ])
+            .from(Self::table_ref())
+            .and_where(Expr::col(TwoFactorIden::UserId).eq(user_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        Ok(query_as_with(&sql, values)
+            .fetch_optional(&state.pool)
+            .await?)
+    }
+
+    pub async fn is_enabled(state: &AppState<super::Engine>, user_id: i64) -> Result<bool> {
+        Ok(Self::get(state, user_id)
+            .await?
+            .is_some_and(|two_factor| two_factor.enabled_at.is_some()))
+    }
+
+    pub async fn status(
+        state: &AppState<super::Engine>,
+        user_id: i64,
+        role: &UserRole,
+    ) -> Result<TwoFactorStatus> {
+        let mut query = Query::select();
+        query
+            .expr(Expr::col(RecoveryCodeIden::CodeHash).count())
+            .from(RecoveryCode::table_ref())
+            .and_where(Expr::col(RecoveryCodeIden::UserId).eq(user_id))
+            .and_where(Expr::col(RecoveryCodeIden::UsedAt).is_null());
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let (recovery_codes,) = query_as_with::<_, (i64,), _>(&sql, values)
+            .fetch_one(&state.pool)
+            .await?;
+
+        Ok(TwoFactorStatus {
+            enabled: Self::is_enabled(state, user_id).await?,
+            required: TwoFactorPolicy::requires(state, role).await?,
+            recovery_codes,
+        })
+    }
+
+    /// Start enrolling with a new secret, replacing one that was never
+    /// confirmed. Returns the secret for the authenticator app.
+    pub async fn start(state: &AppState<super::Engine>, user_id: i64) -> Result<String> {
+        let secret = totp::generate_secret();
+        let mut query = Query::insert();
+        query
+            .into_table(Self::table_ref())
+            .columns([TwoFactorIden::UserId, TwoFactorIden::Secret])
+            .values([user_id.into(), secret.clone().into()])?
+            .on_conflict(
+                OnConflict::column(TwoFactorIden::UserId)
+                    .update_column(TwoFactorIden::Secret)
+                    .action_and_where(Expr::col(TwoFactorIden::EnabledAt).is_null())
+                    .to_owned(),
+            );
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        match query_with(&sql, values)
+            .execute(&state.pool)
+            .await?
+            .rows_affected()
+        {
+            0 => Err(Error::Conflict(
+                "Two-factor authentication is already enabled",
+            )),
+            _ => Ok(secret),
+        }
+    }
+
+    /// Finish enrolling with the first code from the app. Returns the
+    /// recovery codes, `None` when the code is wrong.
+    pub async fn enable(
+        state: &AppState<super::Engine>,
+        user_id: i64,
+        code: &str,
+    ) -> Result<Option<Vec<String>>> {
+        let Some(two_factor) = Self::get(state, user_id).await? else {
+            return Ok(None);
+        };
+        if two_factor.enabled_at.is_some() {
+            return Err(Error::Conflict(
+                "Two-factor authentication is already enabled",
+            ));
+        }
+        let Some(step) = totp::verify(&two_factor.secret, code, Utc::now().timestamp()) else {
+            return Ok(None);
+        };
+
+        let mut tx = state.pool.begin().await?;
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .value(TwoFactorIden::EnabledAt, Utc::now().naive_utc())
+            .value(TwoFactorIden::LastStep, step)
+            .and_where(Expr::col(TwoFactorIden::UserId).eq(user_id))
+            .and_where(Expr::col(TwoFactorIden::EnabledAt).is_null());
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        if query_with(&sql, values)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+            == 0
+        {
+            return Err(Error::Conflict(
+                "Two-factor authentication is already enabled",
+            ));
+        }
+        let codes = replace_recovery_codes(&mut tx, user_id).await?;
+        tx.commit().await?;
+        Ok(Some(codes))
+    }
+
+    /// Check a code from the app. A code is only good once, even while it's
+    /// still current.
+    pub async fn verify(state: &AppState<super::Engine>, user_id: i64, code: &str) -> Result<bool> {
+        let Some(two_factor) = Self::get(state, user_id).await? else {
+            return Ok(false);
+        };
+        if two_factor.enabled_at.is_none() {
+            return Ok(false);
+        }
+        let Some(step) = totp::verify(&two_factor.secret, code, Utc::now().timestamp()) else {
+            return Ok(false);
+        };
+
+        // two requests racing with the same code, only one moves the step on
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .value(TwoFactorIden::LastStep, step)
+            .and_where(Expr::col(TwoFactorIden::UserId).eq(user_id))
+            .and_where(
+                Expr::col(TwoFactorIden::LastStep)
+                    .is_null()
+                    .or(Expr::col(TwoFactorIden::LastStep).lt(step)),
+            );
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let result = query_with(&sql, values).execute(&state.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Use up one of the recovery codes in place of a code from the app.
+    pub async fn recover(
+        state: &AppState<super::Engine>,
+        user_id: i64,
+        code: &str,
+    ) -> Result<bool> {
+        let mut query = Query::update();
+        query
+            .table(RecoveryCode::table_ref())
+            .value(RecoveryCodeIden::UsedAt, Utc::now().naive_utc())
+            .and_where(Expr::col(RecoveryCodeIden::UserId).eq(user_id))
+            .and_where(Expr::col(RecoveryCodeIden::CodeHash).eq(recovery_hash(code)))
+            .and_where(Expr::col(RecoveryCodeIden::UsedAt).is_null());
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let result = query_with(&sql, values).execute(&state.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// A fresh set of recovery codes, the earlier ones stop working.
+    pub async fn regenerate_recovery_codes(
+        state: &AppState<super::Engine>,
+        user_id: i64,
+    ) -> Result<Vec<String>> {
+        let mut tx = state.pool.begin().await?;
+        let codes = replace_recovery_codes(&mut tx, user_id).await?;
+        tx.commit().await?;
+        Ok(codes)
+    }
+
+    /// Remove the second factor and its recovery codes. Returns `false` when
+    /// there was none.
+    pub async fn disable(state: &AppState<super::Engine>, user_id: i64) -> Result<bool> {
+        let mut tx = state.pool.begin().await?;
+        let mut query = Query::delete();
+        query
+            .from_table(RecoveryCode::table_ref())
+            .and_where(Expr::col(RecoveryCodeIden::UserId).eq(user_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        let mut query = Query::delete();
+        query
+            .from_table(Self::table_ref())
+            .and_where(Expr::col(TwoFactorIden::UserId).eq(user_id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let result = query_with(&sql, values).execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+async fn replace_recovery_codes(conn: &mut SqliteConnection, user_id: i64) -> Result<Vec<String>> {
+    let mut query = Query::delete();
+    query
+        .from_table(RecoveryCode::table_ref())
+        .and_where(Expr::col(RecoveryCodeIden::UserId).eq(user_id));
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    query_with(&sql, values).execute(&mut *conn).await?;
+
+    // short enough to type, e.g. 3f9a1-c04be
+    let codes: Vec<String> = (0..RECOVERY_CODES)
+        .map(|_| {
+            let token = random_token();
+            format!("{}-{}", &token[..5], &token[5..10])
+        })
+        .collect();
+    let mut query = Query::insert();
+    query
+        .into_table(RecoveryCode::table_ref())
+        .columns([RecoveryCodeIden::UserId, RecoveryCodeIden::CodeHash]);
+    for code in &codes {
+        query.values([user_id.into(), recovery_hash(code).into()])?;
+    }
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    query_with(&sql, values).execute(&mut *conn).await?;
+    Ok(codes)
+}
+
+/// Hash of a recovery code, forgiving case and the dash.
+fn recovery_hash(code: &str) -> String {
+    let code: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    token_hash(&code)
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::SqlitePool;
+
+    use crate::{model::session::Session, state::test_state};
+
+    use super::*;
+
+    fn current_code(secret: &str) -> String {
+        totp::code(secret, Utc::now().timestamp() / 30).unwrap()
+    }
+
+    #[sqlx::test(fixtures("users"))]
+    fn enrolling_and_verifying(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        // starting over before confirming replaces the secret
+        TwoFactor::start(&state, 3).await?;
+        let secret = TwoFactor::start(&state, 3).await?;
+        assert!(!TwoFactor::is_enabled(&state, 3).await?);
+        assert_eq!(TwoFactor::enable(&state, 3, "000000").await?, None);
+        let code = current_code(&secret);
+        let codes = TwoFactor::enable(&state, 3, &code).await?.unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODES);
+        assert!(TwoFactor::is_enabled(&state, 3).await?);
+        assert!(matches!(
+            TwoFactor::start(&state, 3).await,
+            Err(Error::Conflict(_))
+        ));
+
+        // the code used to enroll can't sign in again
+        assert!(!TwoFactor::verify(&state, 3, &code).await?);
+        sqlx::query("UPDATE TwoFactors SET last_step = last_step - 2")
+            .execute(&state.pool)
+            .await?;
+        assert!(TwoFactor::verify(&state, 3, &code).await?);
+
+        // recovery codes work once each
+        assert!(TwoFactor::recover(&state, 3, &codes[0].to_uppercase()).await?);
+        assert!(!TwoFactor::recover(&state, 3, &codes[0]).await?);
+        let status = TwoFactor::status(&state, 3, &UserRole::Admin).await?;
+        assert_eq!(status.recovery_codes, RECOVERY_CODES as i64 - 1);
+        assert!(!status.required);
+
+        let fresh = TwoFactor::regenerate_recovery_codes(&state, 3).await?;
+        assert!(!TwoFactor::recover(&state, 3, &codes[1]).await?);
+        assert!(TwoFactor::recover(&state, 3, &fresh[1]).await?);
+
+        assert!(TwoFactor::disable(&state, 3).await?);
+        assert!(!TwoFactor::is_enabled(&state, 3).await?);
+        assert!(!TwoFactor::recover(&state, 3, &fresh[2]).await?);
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("users"))]
+    fn staff_policy(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        assert!(!TwoFactorPolicy::requires(&state, &UserRole::Admin).await?);
+
+        // the issuer has a second factor, the admin doesn't
+        let secret = TwoFactor::start(&state, 2).await?;
+        TwoFactor::enable(&state, 2, &current_code(&secret)).await?;
+        for user_id in 1..=3 {
+            Session::start(&state, user_id, None, None).await?;
+        }
+
+        assert_eq!(TwoFactorPolicy::set(&state, true, 3).await?, 1);
+        let policy = TwoFactorPolicy::get(&state).await?;
+        assert!(policy.require_staff);
+        assert_eq!(policy.updated_by, Some(3));
+        assert!(TwoFactorPolicy::requires(&state, &UserRole::Admin).await?);
+        assert!(TwoFactorPolicy::requires(&state, &UserRole::Issuer).await?);
+        assert!(!TwoFactorPolicy::requires(&state, &UserRole::Member).await?);
+        for (user_id, sessions) in [(1, 1), (2, 1), (3, 0)] {
+            let live = Session::list_for_user(&state, user_id, None).await?;
+            assert_eq!(live.len(), sessions);
+        }
+
+        assert_eq!(TwoFactorPolicy::set(&state, false, 3).await?, 0);
+        assert!(!TwoFactorPolicy::requires(&state, &UserRole::Admin).await?);
+        Ok(())
+    }
+}
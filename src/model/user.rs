@@ -1,14 +1,18 @@
-use chrono::NaiveDateTime;
+use chrono::{Duration, NaiveDateTime, Utc};
 use modql::field::{Fields, HasSeaFields, SeaFieldValue};
-use sea_query::{Expr, Iden, Query, SimpleExpr, SqliteQueryBuilder};
+use sea_query::{Expr, Func, Iden, Query, SimpleExpr, SqliteQueryBuilder};
 use sea_query_binder::SqlxBinder;
 use serde::{Deserialize, Serialize};
-use sqlx::{query_as_with, FromRow, Type};
+use sqlx::{query_as_with, query_with, FromRow, SqliteConnection, Type};
 use uuid::Uuid;
 
-use crate::{auth::hash, state::AppState};
+use crate::{
+    auth::{dummy_hash, hash, verify},
+    barcode,
+    state::AppState,
+};
 
-use super::{Model, Result};
+use super::{session, Model, Result};
 
 #[derive(Debug, Serialize, FromRow, Fields)]
 pub struct User {
@@ -22,6 +26,11 @@ pub struct User {
     pub phone: Option<String>,
     pub photo: Option<String>,
     pub address: Option<String>,
+    pub card_number: Option<String>,
+    /// Notifications are emailed as well as shown in the app
+    pub email_notifications: bool,
+    /// Unverified members can't borrow
+    pub email_verified_at: Option<NaiveDateTime>,
     pub updated_at: Option<NaiveDateTime>,
     pub created_at: NaiveDateTime,
 }
@@ -34,6 +43,8 @@ pub enum UserRole {
     Member,
     Issuer,
     Admin,
+    /// Self-checkout station, only ever found in JWT claims
+    Kiosk,
 }
 
 impl From<UserRole> for sea_query::Value {
@@ -42,6 +53,7 @@ impl From<UserRole> for sea_query::Value {
             UserRole::Member => "member".into(),
             UserRole::Issuer => "issuer".into(),
             UserRole::Admin => "admin".into(),
+            UserRole::Kiosk => "kiosk".into(),
         }
     }
 }
@@ -75,8 +87,11 @@ pub struct UserForUpdate {
     pub password: Option<String>,
     pub email: Option<String>,
     pub phone: Option<String>,
+    /// Set by uploading one, never taken as is
+    #[serde(skip_deserializing)]
     pub photo: Option<String>,
     pub address: Option<String>,
+    pub email_notifications: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, FromRow, Fields)]
@@ -85,6 +100,17 @@ pub struct UserForLogin {
     pub password: String,
 }
 
+/// Whether a verification mail should go out.
+#[derive(Debug, PartialEq)]
+pub enum Verification {
+    Send,
+    Verified,
+    /// One was sent moments ago
+    TooSoon {
+        retry_after: i64,
+    },
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PasswordUpdate {
     pub old: String,
@@ -100,7 +126,13 @@ impl UserBy for UserForLogin {}
 enum UserIden {
     Id,
     Username,
+    Email,
     Password,
+    CardNumber,
+    Pin,
+    Role,
+    EmailVerifiedAt,
+    VerificationSentAt,
 }
 
 impl Model for User {
@@ -138,10 +170,147 @@ impl User {
         Ok(user)
     }
 
+    pub async fn get_by_email<E>(state: &AppState<super::Engine>, email: &str) -> Result<Option<E>>
+    where
+        E: UserBy,
+    {
+        let db = &state.pool;
+
+        let mut query = Query::select();
+        query
+            .from(Self::table_ref())
+            .columns(E::sea_idens())
+            .and_where(Expr::col(UserIden::Email).eq(email));
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let user = query_as_with::<_, E, _>(&sql, values)
+            .fetch_optional(db)
+            .await?;
+
+        Ok(user)
+    }
+
+    pub async fn get_by_card_number<E>(
+        state: &AppState<super::Engine>,
+        card_number: &str,
+    ) -> Result<Option<E>>
+    where
+        E: UserBy,
+    {
+        let db = &state.pool;
+
+        let mut query = Query::select();
+        query
+            .from(Self::table_ref())
+            .columns(E::sea_idens())
+            .and_where(Expr::col(UserIden::CardNumber).eq(card_number));
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let user = query_as_with::<_, E, _>(&sql, values)
+            .fetch_optional(db)
+            .await?;
+
+        Ok(user)
+    }
+
     pub async fn create(state: &AppState<super::Engine>, mut user: UserForCreate) -> Result<i64> {
         let password =
             hash(&user.password).map_err(|e| super::error::Error::Hash(e.to_string()))?;
-        super::create::<Self, _>(state, UserForCreate { password, ..user }).await
+        let id = super::create::<Self, _>(state, UserForCreate { password, ..user }).await?;
+        Self::assign_card_number(state, id).await?;
+        Ok(id)
+    }
+
+    /// Generate a library card number for the user unless they have one.
+    async fn assign_card_number(state: &AppState<super::Engine>, id: i64) -> Result<()> {
+        let db = &state.pool;
+        let config = &state.config.barcode;
+
+        let card_number = barcode::generate(&config.card_prefix, id, config.length);
+
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .value(UserIden::CardNumber, card_number)
+            .and_where(Expr::col(UserIden::Id).eq(id))
+            .and_where(Expr::col(UserIden::CardNumber).is_null());
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(db).await?;
+
+        Ok(())
+    }
+
+    /// Give every user without a library card number a generated one.
+    pub async fn assign_card_numbers(state: &AppState<super::Engine>) -> Result<usize> {
+        let db = &state.pool;
+
+        let mut query = Query::select();
+        query
+            .column(UserIden::Id)
+            .from(Self::table_ref())
+            .and_where(Expr::col(UserIden::CardNumber).is_null());
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let ids = query_as_with::<_, (i64,), _>(&sql, values)
+            .fetch_all(db)
+            .await?;
+
+        for (id,) in &ids {
+            Self::assign_card_number(state, *id).await?;
+        }
+
+        Ok(ids.len())
+    }
+
+    /// Set the PIN a user enters at kiosks, stored hashed like passwords.
+    pub async fn set_pin(state: &AppState<super::Engine>, id: i64, pin: &str) -> Result<()> {
+        let db = &state.pool;
+
+        let pin = hash(pin).map_err(|e| super::error::Error::Hash(e.to_string()))?;
+
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .value(UserIden::Pin, pin)
+            .and_where(Expr::col(UserIden::Id).eq(id));
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        match query_with(&sql, values).execute(db).await?.rows_affected() {
+            0 => Err(super::error::Error::EntityNotFound {
+                entity: Self::TABLE,
+                id,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Id of the user holding `card_number` if `pin` is theirs.
+    ///
+    /// Users who never set a PIN can't sign in at a kiosk.
+    pub async fn verify_pin(
+        state: &AppState<super::Engine>,
+        card_number: &str,
+        pin: &str,
+    ) -> Result<Option<i64>> {
+        let db = &state.pool;
+
+        let mut query = Query::select();
+        query
+            .columns([UserIden::Id, UserIden::Pin])
+            .from(Self::table_ref())
+            .and_where(Expr::col(UserIden::CardNumber).eq(card_number))
+            .and_where(Expr::col(UserIden::Pin).is_not_null());
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let user = query_as_with::<_, (i64, String), _>(&sql, values)
+            .fetch_optional(db)
+            .await?;
+
+        // checked either way, so unknown cards take as long as known ones
+        let hashed = user.as_ref().map_or(dummy_hash(), |(_, hashed)| hashed);
+        let valid = verify(hashed, pin).is_ok();
+        Ok(user.filter(|_| valid).map(|(id, _)| id))
     }
 
     pub async fn update(
@@ -153,7 +322,127 @@ impl User {
             user.password =
                 Some(hash(&password).map_err(|e| super::error::Error::Hash(e.to_string()))?);
         };
-        super::update::<Self, _>(state, id, user).await
+        let email_changed = match &user.email {
+            Some(email) => Self::get::<User>(state, id).await?.email != *email,
+            None => false,
+        };
+        super::update::<Self, _>(state, id, user).await?;
+
+        // a new address has to be verified again
+        if email_changed {
+            let mut query = Query::update();
+            query
+                .table(Self::table_ref())
+                .values([
+                    (
+                        UserIden::EmailVerifiedAt,
+                        Option::<NaiveDateTime>::None.into(),
+                    ),
+                    (
+                        UserIden::VerificationSentAt,
+                        Option::<NaiveDateTime>::None.into(),
+                    ),
+                ])
+                .and_where(Expr::col(UserIden::Id).eq(id));
+            let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+            query_with(&sql, values).execute(&state.pool).await?;
+        }
+        Ok(())
+    }
+
+    /// Set a new password and sign the user out of every session but
+    /// `keep`, both or neither.
+    pub async fn change_password(
+        state: &AppState<super::Engine>,
+        id: i64,
+        password: &str,
+        keep: Option<&str>,
+    ) -> Result<()> {
+        let password = hash(password).map_err(|e| super::error::Error::Hash(e.to_string()))?;
+
+        let mut tx = state.pool.begin().await?;
+        set_password(&mut tx, id, &password).await?;
+        session::revoke_all(&mut tx, state, id, keep).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Mark the address verified if `email` is still the user's. Returns
+    /// `false` when it isn't, e.g. it changed since the mail was sent.
+    pub async fn verify_email(
+        state: &AppState<super::Engine>,
+        id: i64,
+        email: &str,
+    ) -> Result<bool> {
+        let now = Utc::now().naive_utc();
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .value(
+                UserIden::EmailVerifiedAt,
+                Func::coalesce([Expr::col(UserIden::EmailVerifiedAt).into(), now.into()]),
+            )
+            .and_where(Expr::col(UserIden::Id).eq(id))
+            .and_where(Expr::col(UserIden::Email).eq(email));
+
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let result = query_with(&sql, values).execute(&state.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Claim the right to send the user a verification mail, at most one
+    /// every `verification_resend_seconds`.
+    pub async fn start_verification(
+        state: &AppState<super::Engine>,
+        id: i64,
+    ) -> Result<Verification> {
+        let now = Utc::now().naive_utc();
+        let interval = Duration::seconds(state.config.auth.verification_resend_seconds);
+
+        let mut query = Query::select();
+        query
+            .columns([UserIden::EmailVerifiedAt, UserIden::VerificationSentAt])
+            .from(Self::table_ref())
+            .and_where(Expr::col(UserIden::Id).eq(id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let (verified_at, sent_at) =
+            query_as_with::<_, (Option<NaiveDateTime>, Option<NaiveDateTime>), _>(&sql, values)
+                .fetch_optional(&state.pool)
+                .await?
+                .ok_or(super::error::Error::EntityNotFound {
+                    entity: Self::TABLE,
+                    id,
+                })?;
+        if verified_at.is_some() {
+            return Ok(Verification::Verified);
+        }
+
+        // only one of two requests racing each other gets through
+        let mut query = Query::update();
+        query
+            .table(Self::table_ref())
+            .value(UserIden::VerificationSentAt, now)
+            .and_where(Expr::col(UserIden::Id).eq(id))
+            .and_where(
+                Expr::col(UserIden::VerificationSentAt)
+                    .is_null()
+                    .or(Expr::col(UserIden::VerificationSentAt).lte(now - interval)),
+            );
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        match query_with(&sql, values)
+            .execute(&state.pool)
+            .await?
+            .rows_affected()
+        {
+            0 => {
+                let retry_after = sent_at.map_or(interval, |sent| sent + interval - now);
+                Ok(Verification::TooSoon {
+                    retry_after: retry_after.num_seconds().max(1),
+                })
+            }
+            _ => Ok(Verification::Send),
+        }
     }
 
     pub async fn list<E>(state: &AppState<super::Engine>) -> Result<Vec<E>>
@@ -184,24 +473,45 @@ impl User {
     }
 }
 
+/// Ids of the admins and issuers.
+pub(super) async fn staff_ids(conn: &mut SqliteConnection) -> Result<Vec<i64>> {
+    let mut query = Query::select();
+    query
+        .column(UserIden::Id)
+        .from(User::table_ref())
+        .and_where(Expr::col(UserIden::Role).is_in([UserRole::Admin, UserRole::Issuer]));
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    let ids = query_as_with::<_, (i64,), _>(&sql, values)
+        .fetch_all(conn)
+        .await?;
+    Ok(ids.into_iter().map(|(id,)| id).collect())
+}
+
+/// Replace the password of a user with an already hashed one.
+pub(super) async fn set_password(conn: &mut SqliteConnection, id: i64, hashed: &str) -> Result<()> {
+    let mut query = Query::update();
+    query
+        .table(User::table_ref())
+        .value(UserIden::Password, hashed)
+        .and_where(Expr::col(UserIden::Id).eq(id));
+    let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+    query_with(&sql, values).execute(conn).await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
-    use std::sync::Arc;
-
     use chrono::Utc;
     use sqlx::SqlitePool;
     use tokio::time::{self, Duration};
 
-    use crate::state::AppStateInner;
+    use crate::{model::session::Session, state::test_state};
 
     use super::*;
 
     #[sqlx::test(fixtures("users"))]
     fn getting_user_by_id(pool: SqlitePool) -> Result<()> {
-        let state = Arc::new(AppStateInner {
-            pool,
-            jwt_secret: "secret".to_string(),
-        });
+        let state = test_state(pool);
         let user: UserForLogin = User::get(&state, 1).await?;
 
         assert_eq!(&user.username, "johndoe");
@@ -211,19 +521,13 @@ mod test {
     #[sqlx::test(fixtures("users"))]
     #[should_panic]
     fn getting_user_by_id_fail(pool: SqlitePool) {
-        let state = Arc::new(AppStateInner {
-            pool,
-            jwt_secret: "secret".to_string(),
-        });
+        let state = test_state(pool);
         let _: UserForLogin = User::get(&state, 10).await.unwrap();
     }
 
     #[sqlx::test(fixtures("users"))]
     fn getting_user_by_username(pool: SqlitePool) -> Result<()> {
-        let state = Arc::new(AppStateInner {
-            pool,
-            jwt_secret: "secret".to_string(),
-        });
+        let state = test_state(pool);
         let user: Option<UserForLogin> =
             User::get_by_username(&state, "johndoe".to_string()).await?;
 
@@ -233,10 +537,7 @@ mod test {
 
     #[sqlx::test(fixtures("users"))]
     fn getting_user_by_username_fail(pool: SqlitePool) -> Result<()> {
-        let state = Arc::new(AppStateInner {
-            pool,
-            jwt_secret: "secret".to_string(),
-        });
+        let state = test_state(pool);
         let user: Option<UserForLogin> = User::get_by_username(&state, "jdoe".to_string()).await?;
 
         assert!(&user.is_none());
@@ -245,10 +546,7 @@ mod test {
 
     #[sqlx::test]
     fn create_user(pool: SqlitePool) -> Result<()> {
-        let state = Arc::new(AppStateInner {
-            pool,
-            jwt_secret: "secret".to_string(),
-        });
+        let state = test_state(pool);
 
         let user = UserForCreate {
             name: "John Doe".to_string(),
@@ -264,13 +562,51 @@ mod test {
         Ok(())
     }
 
+    #[sqlx::test]
+    fn issuing_card_numbers(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        let user = UserForCreate {
+            name: "John Doe".to_string(),
+            role: UserRole::Member,
+            username: "jdoe".to_string(),
+            password: "password".to_string(),
+            email: "B0oDZ@example.com".to_string(),
+            ..Default::default()
+        };
+        let id = User::create(&state, user).await?;
+
+        let user: User = User::get(&state, id).await?;
+        let card_number = user.card_number.unwrap();
+        assert!(barcode::is_valid(&card_number));
+
+        let user: Option<User> = User::get_by_card_number(&state, &card_number).await?;
+        assert!(user.is_some());
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("users"))]
+    fn verifying_pin(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        User::assign_card_numbers(&state).await?;
+        let card_number = User::get::<User>(&state, 1).await?.card_number.unwrap();
+
+        // no PIN set yet
+        assert_eq!(User::verify_pin(&state, &card_number, "1234").await?, None);
+
+        User::set_pin(&state, 1, "1234").await?;
+        assert_eq!(
+            User::verify_pin(&state, &card_number, "1234").await?,
+            Some(1)
+        );
+        assert_eq!(User::verify_pin(&state, &card_number, "4321").await?, None);
+        Ok(())
+    }
+
     #[sqlx::test(fixtures("users"))]
     #[should_panic]
     fn create_user_email_fail(pool: SqlitePool) {
-        let state = Arc::new(AppStateInner {
-            pool,
-            jwt_secret: "secret".to_string(),
-        });
+        let state = test_state(pool);
 
         let user = UserForCreate {
             name: "John Doe".to_string(),
@@ -286,10 +622,7 @@ mod test {
     #[sqlx::test(fixtures("users"))]
     #[should_panic]
     fn create_user_username_fail(pool: SqlitePool) {
-        let state = Arc::new(AppStateInner {
-            pool,
-            jwt_secret: "secret".to_string(),
-        });
+        let state = test_state(pool);
 
         let user = UserForCreate {
             name: "John Doe".to_string(),
@@ -302,12 +635,59 @@ mod test {
         let _ = User::create(&state, user).await.unwrap();
     }
 
+    #[sqlx::test(fixtures("users"))]
+    fn verifying_email(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        assert!(matches!(
+            User::start_verification(&state, 1).await?,
+            Verification::Verified
+        ));
+
+        // a new address has to be verified again
+        let user = UserForUpdate {
+            email: Some("jdoe@localhost".to_string()),
+            ..Default::default()
+        };
+        User::update(&state, 1, user).await?;
+        let user: User = User::get(&state, 1).await?;
+        assert!(user.email_verified_at.is_none());
+
+        assert!(matches!(
+            User::start_verification(&state, 1).await?,
+            Verification::Send
+        ));
+        assert!(matches!(
+            User::start_verification(&state, 1).await?,
+            Verification::TooSoon { retry_after } if retry_after > 0
+        ));
+
+        // a link sent to the old address is no good
+        assert!(!User::verify_email(&state, 1, "johndoe@localhost").await?);
+        assert!(User::verify_email(&state, 1, "jdoe@localhost").await?);
+        let user: User = User::get(&state, 1).await?;
+        assert!(user.email_verified_at.is_some());
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures("users"))]
+    fn changing_password(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        let (current, _) = Session::start(&state, 1, None, None).await?;
+        Session::start(&state, 1, None, None).await?;
+        User::change_password(&state, 1, "new", Some(&current)).await?;
+
+        let user: User = User::get(&state, 1).await?;
+        assert!(verify(&user.password, "new").is_ok());
+        let sessions = Session::list_for_user(&state, 1, Some(&current)).await?;
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id, current);
+        Ok(())
+    }
+
     #[sqlx::test(fixtures("users"))]
     fn update_user(pool: SqlitePool) -> Result<()> {
-        let state = Arc::new(AppStateInner {
-            pool,
-            jwt_secret: "secret".to_string(),
-        });
+        let state = test_state(pool);
 
         let before = Utc::now().naive_utc();
         dbg!(&before);
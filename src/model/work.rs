@@ -0,0 +1,227 @@
+//! Works grouping the editions of a title.
+//!
+//! A book outside any work is an edition on its own. Merging books puts them
+//! (and every edition already grouped with them) in one work, splitting takes
+//! editions back out.
+
+use chrono::NaiveDateTime;
+use modql::field::Fields;
+use sea_query::{Expr, Iden, Query, SqliteQueryBuilder};
+use sea_query_binder::SqlxBinder;
+use serde::{Deserialize, Serialize};
+use sqlx::{query_as_with, query_with, FromRow};
+
+use crate::state::AppState;
+
+use super::{book::Book, error::Error, Model, Result};
+
+#[derive(Debug, Serialize, FromRow, Fields)]
+pub struct Work {
+    pub id: i64,
+    pub title: String,
+    pub updated_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Fields)]
+pub struct WorkForUpdate {
+    pub title: String,
+}
+
+#[derive(Iden)]
+enum WorkIden {
+    Id,
+    Title,
+    WorkId,
+}
+
+impl Model for Work {
+    const TABLE: &'static str = "Works";
+}
+
+impl Work {
+    pub async fn get(state: &AppState<super::Engine>, id: i64) -> Result<Work> {
+        super::get::<Self, _>(state, id).await
+    }
+
+    pub async fn update(
+        state: &AppState<super::Engine>,
+        id: i64,
+        work: WorkForUpdate,
+    ) -> Result<()> {
+        super::update::<Self, _>(state, id, work).await
+    }
+
+    /// Books grouped in the work.
+    pub async fn editions(state: &AppState<super::Engine>, id: i64) -> Result<Vec<Book>> {
+        super::list_where::<Book, _, _, _>(state, WorkIden::WorkId, id).await
+    }
+
+    /// Group books as editions of one work, returning the work.
+    ///
+    /// Works the books are already in are merged into the oldest of them. When
+    /// none is, a work is started with the title of the first book.
+    pub async fn merge(state: &AppState<super::Engine>, book_ids: &[i64]) -> Result<i64> {
+        if book_ids.len() < 2 {
+            return Err(Error::Conflict("At least two books are needed"));
+        }
+
+        let mut tx = state.pool.begin().await?;
+
+        let mut query = Query::select();
+        query
+            .columns([WorkIden::Id, WorkIden::Title, WorkIden::WorkId])
+            .from(Book::table_ref())
+            .and_where(Expr::col(WorkIden::Id).is_in(book_ids.iter().copied()));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let books = query_as_with::<_, (i64, String, Option<i64>), _>(&sql, values)
+            .fetch_all(&mut *tx)
+            .await?;
+        if let Some(&id) = book_ids
+            .iter()
+            .find(|&&id| !books.iter().any(|(book_id, ..)| *book_id == id))
+        {
+            return Err(Error::EntityNotFound {
+                entity: Book::TABLE,
+                id,
+            });
+        }
+
+        let mut works: Vec<i64> = books.iter().filter_map(|(.., work_id)| *work_id).collect();
+        works.sort();
+        works.dedup();
+
+        let work_id = match works.first() {
+            Some(&work_id) => work_id,
+            None => {
+                let (_, title, _) = books
+                    .iter()
+                    .find(|(id, ..)| *id == book_ids[0])
+                    .expect("first book was found");
+                let mut query = Query::insert();
+                query
+                    .into_table(Self::table_ref())
+                    .columns([WorkIden::Title])
+                    .values([title.into()])?
+                    .returning_col(WorkIden::Id);
+                let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+                let (work_id,) = query_as_with::<_, (i64,), _>(&sql, values)
+                    .fetch_one(&mut *tx)
+                    .await?;
+                work_id
+            }
+        };
+
+        let mut query = Query::update();
+        query
+            .table(Book::table_ref())
+            .value(WorkIden::WorkId, work_id)
+            .cond_where(
+                Expr::col(WorkIden::Id)
+                    .is_in(book_ids.iter().copied())
+                    .or(Expr::col(WorkIden::WorkId).is_in(works.iter().copied())),
+            );
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        let mut query = Query::delete();
+        query
+            .from_table(Self::table_ref())
+            .and_where(Expr::col(WorkIden::Id).is_in(works.iter().skip(1).copied()));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        query_with(&sql, values).execute(&mut *tx).await?;
+
+        tx.commit().await?;
+        Ok(work_id)
+    }
+
+    /// Take editions out of a work, returning how many were.
+    ///
+    /// The work is removed once no edition is left in it.
+    pub async fn split(state: &AppState<super::Engine>, id: i64, book_ids: &[i64]) -> Result<u64> {
+        let mut tx = state.pool.begin().await?;
+
+        let mut query = Query::update();
+        query
+            .table(Book::table_ref())
+            .value(WorkIden::WorkId, Option::<i64>::None)
+            .and_where(Expr::col(WorkIden::WorkId).eq(id))
+            .and_where(Expr::col(WorkIden::Id).is_in(book_ids.iter().copied()));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let split = query_with(&sql, values)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+        let mut query = Query::select();
+        query
+            .expr(Expr::col(WorkIden::Id).count())
+            .from(Book::table_ref())
+            .and_where(Expr::col(WorkIden::WorkId).eq(id));
+        let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+        let (left,) = query_as_with::<_, (i64,), _>(&sql, values)
+            .fetch_one(&mut *tx)
+            .await?;
+        if left == 0 {
+            let mut query = Query::delete();
+            query
+                .from_table(Self::table_ref())
+                .and_where(Expr::col(WorkIden::Id).eq(id));
+            let (sql, values) = query.build_sqlx(SqliteQueryBuilder);
+            query_with(&sql, values).execute(&mut *tx).await?;
+        }
+
+        tx.commit().await?;
+        Ok(split)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::SqlitePool;
+
+    use crate::{
+        model::book::{Book, BookFilter},
+        state::test_state,
+    };
+
+    use super::*;
+
+    #[sqlx::test(fixtures("books"))]
+    fn grouping_editions(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+
+        assert!(matches!(
+            Work::merge(&state, &[1]).await,
+            Err(Error::Conflict(_))
+        ));
+        assert!(matches!(
+            Work::merge(&state, &[1, 99]).await,
+            Err(Error::EntityNotFound { id: 99, .. })
+        ));
+
+        let work_id = Work::merge(&state, &[1, 2]).await?;
+        let work = Work::get(&state, work_id).await?;
+        assert_eq!(work.title, Book::get(&state, 1).await?.title);
+
+        // merging into a grouped book extends its work
+        assert_eq!(Work::merge(&state, &[3, 2]).await?, work_id);
+        assert_eq!(Work::editions(&state, work_id).await?.len(), 3);
+
+        let filter = BookFilter {
+            collapse: true,
+            ..Default::default()
+        };
+        let books = Book::search(&state, &filter).await?;
+        assert_eq!(books.len(), 1);
+        assert_eq!(books[0].editions, Some(3));
+        assert_eq!(books[0].copies, 10);
+
+        assert_eq!(Work::split(&state, work_id, &[1, 2, 3]).await?, 3);
+        assert!(Work::get(&state, work_id).await.is_err());
+        let books = Book::search(&state, &filter).await?;
+        assert_eq!(books.len(), 3);
+
+        Ok(())
+    }
+}
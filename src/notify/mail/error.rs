@@ -0,0 +1,15 @@
+use std::io;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Invalid address '{0}'")]
+    InvalidAddress(String),
+    #[error(transparent)]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("Unknown template '{0}'")]
+    UnknownTemplate(String),
+}
@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::fs;
+use tracing::info;
+use uuid::Uuid;
+
+use super::{Email, Mailer, Result};
+
+/// For development: mail is written to the log, or to `.eml` files under
+/// `dir` that any mail client opens.
+pub struct LogMailer {
+    dir: Option<PathBuf>,
+}
+
+impl LogMailer {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir }
+    }
+}
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, from: &str, email: &Email) -> Result<()> {
+        let message = email.to_message(from)?;
+        match &self.dir {
+            Some(dir) => {
+                fs::create_dir_all(dir).await?;
+                let name = format!(
+                    "{}-{}.eml",
+                    Utc::now().format("%Y%m%d%H%M%S"),
+                    Uuid::new_v4()
+                );
+                let path = dir.join(name);
+                fs::write(&path, message).await?;
+                info!("Mail to {} written to {}", email.to, path.display());
+            }
+            None => info!("Mail to {}: {}\n{}", email.to, email.subject, email.text),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::Utc;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    config::{MailConfig, MailTransport},
+    model::{outbox::Outbox, Engine},
+    state::AppState,
+};
+
+pub use self::{
+    error::{Error, Result},
+    log::LogMailer,
+    smtp::SmtpMailer,
+    template::{render, Vars},
+};
+
+pub mod error;
+pub mod log;
+pub mod smtp;
+pub mod template;
+
+/// A message ready to go out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    pub html: Option<String>,
+}
+
+/// Way of delivering mail.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, from: &str, email: &Email) -> Result<()>;
+}
+
+/// Build the mailer selected by the configuration.
+pub fn from_config(config: &MailConfig) -> Result<Arc<dyn Mailer>> {
+    let mailer: Arc<dyn Mailer> = match &config.transport {
+        MailTransport::Log { dir } => Arc::new(LogMailer::new(dir.clone())),
+        MailTransport::Smtp {
+            host,
+            port,
+            security,
+            username,
+            password,
+        } => Arc::new(SmtpMailer::new(
+            host,
+            *port,
+            *security,
+            username.clone().zip(password.clone()),
+        )?),
+    };
+    Ok(mailer)
+}
+
+impl Email {
+    /// The message in RFC 5322 form, with CRLF line endings.
+    pub fn to_message(&self, from: &str) -> Result<String> {
+        let domain = address(from)?
+            .split_once('@')
+            .map(|(_, domain)| domain.to_string())
+            .unwrap_or_default();
+        address(&self.to)?;
+
+        let mut message = String::new();
+        for (name, value) in [
+            ("From", from.to_string()),
+            ("To", self.to.clone()),
+            ("Subject", encode_header(&self.subject)),
+            ("Date", Utc::now().to_rfc2822()),
+            ("Message-ID", format!("<{}@{domain}>", Uuid::new_v4())),
+            ("MIME-Version", "1.0".to_string()),
+        ] {
+            message.push_str(&format!("{name}: {value}\r\n"));
+        }
+
+        match &self.html {
+            Some(html) => {
+                let boundary = format!("=_{}", Uuid::new_v4().simple());
+                message.push_str(&format!(
+                    "Content-Type: multipart/alternative; boundary=\"{boundary}\"\r\n\r\n"
+                ));
+                for (content_type, body) in [("text/plain", &self.text), ("text/html", html)] {
+                    message.push_str(&format!("--{boundary}\r\n"));
+                    message.push_str(&part(content_type, body));
+                }
+                message.push_str(&format!("--{boundary}--\r\n"));
+            }
+            None => message.push_str(&part("text/plain", &self.text)),
+        }
+        Ok(message)
+    }
+}
+
+/// A body part, base64 so any text survives the trip.
+fn part(content_type: &str, body: &str) -> String {
+    let encoded = STANDARD.encode(body);
+    let mut part = format!(
+        "Content-Type: {content_type}; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n"
+    );
+    for line in encoded.as_bytes().chunks(76) {
+        part.push_str(std::str::from_utf8(line).unwrap_or_default());
+        part.push_str("\r\n");
+    }
+    part
+}
+
+/// RFC 2047 encoded unless plain ASCII, never spanning lines.
+fn encode_header(value: &str) -> String {
+    let value: String = value.chars().filter(|c| !c.is_control()).collect();
+    if value.is_ascii() {
+        value
+    } else {
+        format!("=?UTF-8?B?{}?=", STANDARD.encode(value))
+    }
+}
+
+/// The bare address of `Name <user@host>` or `user@host`, refusing anything
+/// that could break out of a header or SMTP command.
+pub fn address(mailbox: &str) -> Result<&str> {
+    let address = match mailbox.rsplit_once('<') {
+        Some((_, rest)) => rest.strip_suffix('>').unwrap_or(rest),
+        None => mailbox,
+    }
+    .trim();
+    let valid = address.split_once('@').is_some_and(|(local, domain)| {
+        !local.is_empty() && !domain.is_empty() && !domain.contains('@')
+    }) && !address
+        .chars()
+        .any(|c| c.is_whitespace() || c.is_control() || "<>,;\"".contains(c));
+    if !valid || mailbox.chars().any(|c| c.is_control()) {
+        return Err(Error::InvalidAddress(mailbox.to_string()));
+    }
+    Ok(address)
+}
+
+/// Send what is due in the outbox, returning how many went out.
+pub async fn deliver_outbox(
+    state: &AppState<Engine>,
+    mailer: &dyn Mailer,
+) -> crate::model::error::Result<usize> {
+    let config = &state.config.mail;
+    let mut sent = 0;
+    for mail in Outbox::due(state, 50).await? {
+        let email = Email {
+            to: mail.recipient,
+            subject: mail.subject,
+            text: mail.text_body,
+            html: mail.html_body,
+        };
+        match mailer.send(&config.from, &email).await {
+            Ok(_) => {
+                Outbox::sent(state, mail.id).await?;
+                sent += 1;
+            }
+            Err(e) => {
+                warn!("Mail {} to {} failed: {e}", mail.id, email.to);
+                Outbox::failed(state, mail.id, &e.to_string(), config.max_attempts).await?;
+            }
+        }
+    }
+    Ok(sent)
+}
+
+/// Keep emptying the outbox in the background.
+pub fn spawn_outbox(state: AppState<Engine>, mailer: Arc<dyn Mailer>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(state.config.mail.outbox_interval));
+        loop {
+            interval.tick().await;
+            match deliver_outbox(&state, mailer.as_ref()).await {
+                Ok(0) => {}
+                Ok(sent) => info!("Sent {sent} mails"),
+                Err(e) => error!("{e}"),
+            }
+        }
+    })
+}
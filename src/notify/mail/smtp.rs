@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use lettre::{
+    address::Envelope,
+    transport::smtp::{authentication::Credentials, extension::ClientId, AsyncSmtpTransport},
+    Address, AsyncTransport, Tokio1Executor,
+};
+
+use crate::config::SmtpSecurity;
+
+use super::{address, Email, Error, Mailer, Result};
+
+/// Gives up on a server that stops answering.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sends through an SMTP server, one connection per message.
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        host: &str,
+        port: u16,
+        security: SmtpSecurity,
+        credentials: Option<(String, String)>,
+    ) -> Result<Self> {
+        let builder = match security {
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+        };
+        let mut builder = builder
+            .port(port)
+            .hello_name(ClientId::Domain("localhost".to_string()))
+            .timeout(Some(TIMEOUT));
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+}
+
+/// The bare address of a mailbox, for the envelope.
+fn envelope_address(mailbox: &str) -> Result<Address> {
+    address(mailbox)?
+        .parse()
+        .map_err(|_| Error::InvalidAddress(mailbox.to_string()))
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, from: &str, email: &Email) -> Result<()> {
+        let message = email.to_message(from)?;
+        let envelope = Envelope::new(
+            Some(envelope_address(from)?),
+            vec![envelope_address(&email.to)?],
+        )
+        .map_err(|_| Error::InvalidAddress(email.to.clone()))?;
+        self.transport
+            .send_raw(&envelope, message.as_bytes())
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Accept one message like MailHog would, returning the session.
+    async fn sink(listener: TcpListener) -> Vec<String> {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        let mut seen = vec![];
+
+        write.write_all(b"220 sink ready\r\n").await.unwrap();
+        let mut in_data = false;
+        while let Some(line) = lines.next_line().await.unwrap() {
+            seen.push(line.clone());
+            let reply: &[u8] = if in_data {
+                if line != "." {
+                    continue;
+                }
+                in_data = false;
+                b"250 queued\r\n"
+            } else if line.starts_with("EHLO") {
+                b"250-sink\r\n250 AUTH PLAIN\r\n"
+            } else if line.starts_with("AUTH") {
+                b"235 ok\r\n"
+            } else if line == "DATA" {
+                in_data = true;
+                b"354 go ahead\r\n"
+            } else if line.starts_with("RCPT") && line.contains("nobody") {
+                b"550 no such user\r\n"
+            } else if line == "QUIT" {
+                write.write_all(b"221 bye\r\n").await.unwrap();
+                break;
+            } else {
+                b"250 ok\r\n"
+            };
+            write.write_all(reply).await.unwrap();
+        }
+        seen
+    }
+
+    #[tokio::test]
+    async fn sending_over_smtp() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let server = tokio::spawn(sink(listener));
+
+        let credentials = Some(("library".to_string(), "hunter2".to_string()));
+        let mailer = SmtpMailer::new("127.0.0.1", port, SmtpSecurity::None, credentials)?;
+        let email = Email {
+            to: "John Doe <john@example.org>".to_string(),
+            subject: "Hello".to_string(),
+            text: "Hi\n.\nBye".to_string(),
+            html: None,
+        };
+        mailer.send("Library <library@example.org>", &email).await?;
+
+        let seen = server.await.unwrap();
+        assert_eq!(seen[0], "EHLO localhost");
+        assert_eq!(
+            seen[1],
+            format!("AUTH PLAIN {}", STANDARD.encode("\0library\0hunter2"))
+        );
+        assert_eq!(seen[2], "MAIL FROM:<library@example.org>");
+        assert_eq!(seen[3], "RCPT TO:<john@example.org>");
+        assert!(seen.contains(&"Subject: Hello".to_string()));
+        assert_eq!(seen.last().unwrap(), "QUIT");
+
+        // refused recipients fail the send
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        tokio::spawn(sink(listener));
+        let mailer = SmtpMailer::new("127.0.0.1", port, SmtpSecurity::None, None)?;
+        let email = Email {
+            to: "nobody@example.org".to_string(),
+            ..email
+        };
+        assert!(matches!(
+            mailer.send("library@example.org", &email).await,
+            Err(Error::Smtp(e)) if e.is_permanent()
+        ));
+
+        Ok(())
+    }
+}
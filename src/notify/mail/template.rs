@@ -0,0 +1,206 @@
+//! Mail templates, a text and an HTML version of each.
+//!
+//! `{{name}}` is replaced by a variable, HTML escaped in the HTML version,
+//! and `{{#items}}...{{/items}}` is repeated for each entry of a list, so an
+//! empty list leaves a section out. The first line of the text version is the
+//! subject.
+
+use std::collections::BTreeMap;
+
+use crate::model::{book::Book, borrowing::Borrowing, user::User};
+
+use super::{Email, Error, Result};
+
+/// `(name, text, html)`
+const TEMPLATES: &[(&str, &str, &str)] = &[
+    (
+        "password_reset",
+        include_str!("../../../templates/mail/password_reset.txt"),
+        include_str!("../../../templates/mail/password_reset.html"),
+    ),
+    (
+        "due_notice",
+        include_str!("../../../templates/mail/due_notice.txt"),
+        include_str!("../../../templates/mail/due_notice.html"),
+    ),
+    (
+        "notification",
+        include_str!("../../../templates/mail/notification.txt"),
+        include_str!("../../../templates/mail/notification.html"),
+    ),
+    (
+        "verify_email",
+        include_str!("../../../templates/mail/verify_email.txt"),
+        include_str!("../../../templates/mail/verify_email.html"),
+    ),
+];
+
+const LAYOUT: &str = include_str!("../../../templates/mail/layout.html");
+
+/// Values a template is filled in with.
+#[derive(Debug, Default, Clone)]
+pub struct Vars {
+    values: BTreeMap<String, String>,
+    lists: BTreeMap<String, Vec<Vars>>,
+}
+
+impl Vars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(mut self, name: &str, value: impl ToString) -> Self {
+        self.values.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn list(mut self, name: &str, items: Vec<Vars>) -> Self {
+        self.lists.insert(name.to_string(), items);
+        self
+    }
+
+    /// `user.name`, `user.username`, `user.email` and `user.card_number`
+    pub fn user(self, user: &User) -> Self {
+        self.set("user.name", &user.name)
+            .set("user.username", &user.username)
+            .set("user.email", &user.email)
+            .set(
+                "user.card_number",
+                user.card_number.as_deref().unwrap_or_default(),
+            )
+    }
+
+    /// `book.id`, `book.title`, `book.author`, `book.isbn` and
+    /// `book.call_number`
+    pub fn book(self, book: &Book) -> Self {
+        self.set("book.id", book.id)
+            .set("book.title", &book.title)
+            .set("book.author", &book.author)
+            .set("book.isbn", &book.isbn)
+            .set(
+                "book.call_number",
+                book.call_number.as_deref().unwrap_or_default(),
+            )
+    }
+
+    /// `borrowing.copy_id`, `borrowing.borrow_date`, `borrowing.due_date` and
+    /// `borrowing.renewals`
+    pub fn borrowing(self, borrowing: &Borrowing) -> Self {
+        self.set("borrowing.copy_id", borrowing.copy_id)
+            .set("borrowing.borrow_date", borrowing.borrow_date)
+            .set("borrowing.due_date", borrowing.due_date)
+            .set("borrowing.renewals", borrowing.renewals)
+    }
+
+    fn get<'a>(&'a self, name: &str, outer: &[&'a Vars]) -> Option<&'a str> {
+        self.values
+            .get(name)
+            .or_else(|| outer.iter().rev().find_map(|vars| vars.values.get(name)))
+            .map(String::as_str)
+    }
+}
+
+/// Fill in template `name` for a mail to `to`.
+pub fn render(name: &str, to: &str, vars: &Vars) -> Result<Email> {
+    let (_, text, html) = TEMPLATES
+        .iter()
+        .find(|(template, ..)| *template == name)
+        .ok_or(Error::UnknownTemplate(name.to_string()))?;
+
+    let text = fill(text, vars, &[], false);
+    let (subject, text) = text.split_once('\n').unwrap_or((&text, ""));
+    let html = LAYOUT
+        .replace("{{subject}}", &escape(subject))
+        .replace("{{content}}", &fill(html, vars, &[], true));
+
+    Ok(Email {
+        to: to.to_string(),
+        subject: subject.trim().to_string(),
+        text: text.trim_start().to_string(),
+        html: Some(html),
+    })
+}
+
+fn fill(template: &str, vars: &Vars, outer: &[&Vars], html: bool) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        let tag = rest[start + 2..start + end].trim();
+        rest = &rest[start + end + 2..];
+
+        if let Some(name) = tag.strip_prefix('#') {
+            let close = format!("{{{{/{name}}}}}");
+            let (section, after) = rest.split_once(close.as_str()).unwrap_or((rest, ""));
+            let mut scope = outer.to_vec();
+            scope.push(vars);
+            for item in vars.lists.get(name).into_iter().flatten() {
+                out.push_str(&fill(section, item, &scope, html));
+            }
+            rest = after;
+        } else {
+            let value = vars.get(tag, outer).unwrap_or_default();
+            match html {
+                true => out.push_str(&escape(value)),
+                false => out.push_str(value),
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn filling_in_templates() {
+        let vars = Vars::new()
+            .set("name", "Tom & Jerry")
+            .set("library", "Maktaba")
+            .list(
+                "items",
+                vec![
+                    Vars::new().set("title", "Dune"),
+                    Vars::new().set("title", "<Emma>"),
+                ],
+            );
+
+        let template =
+            "Hi {{name}},\n{{#items}}- {{title}} from {{library}}\n{{/items}}{{missing}}";
+        assert_eq!(
+            fill(template, &vars, &[], false),
+            "Hi Tom & Jerry,\n- Dune from Maktaba\n- <Emma> from Maktaba\n"
+        );
+        assert_eq!(
+            fill(template, &vars, &[], true),
+            "Hi Tom &amp; Jerry,\n- Dune from Maktaba\n- &lt;Emma&gt; from Maktaba\n"
+        );
+
+        let vars = Vars::new()
+            .set("user.name", "Jane")
+            .set("link", "http://localhost/reset?token=abc&x=1");
+        let email = render("password_reset", "jane@example.org", &vars).unwrap();
+        assert_eq!(email.to, "jane@example.org");
+        assert!(!email.subject.is_empty());
+        assert!(email.text.contains("http://localhost/reset?token=abc&x=1"));
+        assert!(email
+            .html
+            .unwrap()
+            .contains("http://localhost/reset?token=abc&amp;x=1"));
+        assert!(render("nope", "jane@example.org", &vars).is_err());
+    }
+}
@@ -0,0 +1,4 @@
+//! Telling members about things, by email.
+
+pub mod mail;
+pub mod notices;
@@ -0,0 +1,89 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use chrono::{NaiveDate, Utc};
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+use crate::{
+    error::Result,
+    model::{
+        book::Book,
+        notice::{DueLoan, Notice, NoticeKind},
+        notification::Notification,
+        user::User,
+        Engine,
+    },
+    state::AppState,
+};
+
+use super::mail::{render, Vars};
+
+/// Queue one mail for every member with loans due soon, due today or overdue
+/// they weren't told about yet, returning how many were queued.
+pub async fn send_notices(state: &AppState<Engine>, today: NaiveDate) -> Result<usize> {
+    let mut members: BTreeMap<i64, Vec<DueLoan>> = BTreeMap::new();
+    for loan in Notice::pending(state, today).await? {
+        members
+            .entry(loan.borrowing.user_id)
+            .or_default()
+            .push(loan);
+    }
+
+    let mut queued = 0;
+    for (user_id, loans) in members {
+        // one member's trouble shouldn't hold up the others
+        match notify_member(state, user_id, &loans).await {
+            Ok(_) => queued += 1,
+            Err(e) => error!("Notice for user {user_id} failed: {e}"),
+        }
+    }
+    Ok(queued)
+}
+
+async fn notify_member(state: &AppState<Engine>, user_id: i64, loans: &[DueLoan]) -> Result<()> {
+    let user: User = User::get(state, user_id).await?;
+    let mut vars = Vars::new().user(&user);
+    for kind in [
+        NoticeKind::Overdue,
+        NoticeKind::DueToday,
+        NoticeKind::DueSoon,
+    ] {
+        let mut items = vec![];
+        for loan in loans.iter().filter(|loan| loan.kind == kind) {
+            let book = Book::get(state, loan.borrowing.book_id).await?;
+            items.push(Vars::new().book(&book).borrowing(&loan.borrowing));
+        }
+        // a section per kind, left out when there's nothing in it
+        let section = match items.is_empty() {
+            true => vec![],
+            false => vec![Vars::new().list("items", items)],
+        };
+        vars = vars.list(kind.as_str(), section);
+    }
+
+    let email = render("due_notice", &user.email, &vars)?;
+    Notice::record(state, loans, email).await?;
+    Ok(())
+}
+
+/// Look for loans and holds needing a notice in the background.
+pub fn spawn_notices(state: AppState<Engine>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(state.config.notices.interval));
+        loop {
+            interval.tick().await;
+            let today = Utc::now().date_naive();
+            match send_notices(&state, today).await {
+                Ok(0) => {}
+                Ok(queued) => info!("Queued {queued} loan notices"),
+                Err(e) => error!("{e}"),
+            }
+            match Notification::remind_expiring_holds(&state, today).await {
+                Ok(0) => {}
+                Ok(reminded) => info!("Reminded {reminded} members of holds waiting"),
+                Err(e) => error!("{e}"),
+            }
+        }
+    })
+}
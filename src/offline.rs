@@ -0,0 +1,256 @@
+//! Circulation recorded while the server was unreachable.
+//!
+//! Staff keep a spreadsheet during outages and upload it as CSV once back
+//! online, with a header row and one event per line:
+//!
+//! ```text
+//! timestamp,action,card,barcode
+//! 2025-03-01 10:15:00,checkout,20000000000013,30000000000017
+//! 2025-03-01 11:02:00,checkin,,30000000000017
+//! ```
+//!
+//! Actions are `checkout`, `checkin` and `renew`, the card may be left empty
+//! for check-ins.
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    model::{
+        book::Book,
+        circulation::{Action, Circulation, Outcome},
+        error::Result,
+        user::User,
+        Engine,
+    },
+    state::AppState,
+};
+
+const TIMESTAMP_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+];
+
+#[derive(Debug, Deserialize)]
+struct Row {
+    timestamp: String,
+    action: Action,
+    #[serde(default)]
+    card: String,
+    barcode: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    /// Line of the upload the event was read from
+    pub line: u64,
+    pub timestamp: NaiveDateTime,
+    pub action: Action,
+    pub card: Option<String>,
+    pub barcode: String,
+}
+
+/// A line that could not be read.
+#[derive(Debug, Serialize)]
+pub struct Invalid {
+    pub line: u64,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EventResult {
+    #[serde(flatten)]
+    pub event: Event,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+impl EventResult {
+    pub fn is_conflict(&self) -> bool {
+        !matches!(
+            self.outcome,
+            Outcome::Success { .. } | Outcome::Found { .. }
+        )
+    }
+}
+
+/// Read events from CSV, lines that can't be read are returned separately so
+/// the rest can still be replayed.
+pub fn parse(data: &[u8]) -> (Vec<Event>, Vec<Invalid>) {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(data);
+
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => {
+            let error = Invalid {
+                line: 1,
+                error: e.to_string(),
+            };
+            return (vec![], vec![error]);
+        }
+    };
+
+    let mut events = vec![];
+    let mut invalid = vec![];
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                invalid.push(Invalid {
+                    line: e.position().map(|p| p.line()).unwrap_or_default(),
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let row: Row = match record.deserialize(Some(&headers)) {
+            Ok(row) => row,
+            Err(e) => {
+                invalid.push(Invalid {
+                    line,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let Some(timestamp) = TIMESTAMP_FORMATS
+            .iter()
+            .find_map(|f| NaiveDateTime::parse_from_str(&row.timestamp, f).ok())
+        else {
+            invalid.push(Invalid {
+                line,
+                error: format!("Unrecognised timestamp '{}'", row.timestamp),
+            });
+            continue;
+        };
+        if row.card.is_empty() && row.action != Action::Checkin {
+            invalid.push(Invalid {
+                line,
+                error: "A card is needed for checkouts and renewals".to_string(),
+            });
+            continue;
+        }
+
+        events.push(Event {
+            line,
+            timestamp,
+            action: row.action,
+            card: Some(row.card).filter(|c| !c.is_empty()),
+            barcode: row.barcode,
+        });
+    }
+
+    (events, invalid)
+}
+
+/// Apply `events` in the order they happened, as if on the day they did.
+///
+/// Events breaking the circulation rules are reported rather than stopping
+/// the replay.
+pub async fn replay(state: &AppState<Engine>, mut events: Vec<Event>) -> Result<Vec<EventResult>> {
+    // stable, so events sharing a timestamp keep the order they were recorded in
+    events.sort_by_key(|e| e.timestamp);
+
+    let mut results = vec![];
+    for event in events {
+        let outcome = apply(state, &event).await?;
+        results.push(EventResult { event, outcome });
+    }
+
+    Ok(results)
+}
+
+async fn apply(state: &AppState<Engine>, event: &Event) -> Result<Outcome> {
+    let Some(copy) = Book::get_copy_by_barcode(state, &event.barcode).await? else {
+        return Ok(Outcome::NotFound);
+    };
+    let day = event.timestamp.date();
+
+    if event.action == Action::Checkin {
+        return Circulation::checkin(state, copy.book_id, copy.id, day).await;
+    }
+
+    let card = event.card.as_deref().unwrap_or_default();
+    let Some(user) = User::get_by_card_number::<User>(state, card).await? else {
+        return Ok(Outcome::PatronNotFound);
+    };
+    match event.action {
+        Action::Checkout => Circulation::checkout(state, user.id, copy.book_id, copy.id, day).await,
+        _ => Circulation::renew(state, user.id, copy.book_id, copy.id, day).await,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::SqlitePool;
+
+    use crate::{model::circulation::BlockReason, state::test_state};
+
+    use super::*;
+
+    #[test]
+    fn parsing_events() {
+        let csv = b"timestamp,action,card,barcode
+2025-03-01 10:15:00,checkout,2001,3001
+yesterday,checkin,,3001
+2025-03-01T11:00,renew,2001,3001
+2025-03-01 12:00,checkin,,3001
+2025-03-01 12:00,lend,2001,3001
+2025-03-01 12:00,checkout,,3001
+";
+        let (events, invalid) = parse(csv);
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1].action, Action::Renewal);
+        assert_eq!(events[2].card, None);
+        assert_eq!(
+            invalid.iter().map(|i| i.line).collect::<Vec<_>>(),
+            [3, 6, 7]
+        );
+    }
+
+    #[sqlx::test(fixtures(path = "model/fixtures", scripts("users", "books")))]
+    fn replaying_events(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        Book::assign_barcodes(&state).await?;
+        User::assign_card_numbers(&state).await?;
+        let copy = Book::get_copy(&state, 1, 1).await?.barcode.unwrap();
+        let john = User::get::<User>(&state, 1).await?.card_number.unwrap();
+        let jane = User::get::<User>(&state, 2).await?.card_number.unwrap();
+
+        // recorded out of order, Jane's checkout happened while John had the copy
+        let csv = format!(
+            "timestamp,action,card,barcode
+2025-03-03 09:00,checkin,,{copy}
+2025-03-01 10:00,checkout,{john},{copy}
+2025-03-02 10:00,checkout,{jane},{copy}
+2025-03-03 10:00,checkout,{jane},{copy}
+2025-03-03 11:00,checkout,00000,{copy}
+"
+        );
+        let (events, invalid) = parse(csv.as_bytes());
+        assert!(invalid.is_empty());
+
+        let results = replay(&state, events).await?;
+        let lines: Vec<u64> = results.iter().map(|r| r.event.line).collect();
+        assert_eq!(lines, [3, 4, 2, 5, 6]);
+        assert!(!results[0].is_conflict());
+        assert_eq!(
+            results[1].outcome,
+            Outcome::Blocked {
+                action: Action::Checkout,
+                reason: BlockReason::OnLoanToAnother
+            }
+        );
+        assert!(!results[2].is_conflict());
+        assert!(!results[3].is_conflict());
+        assert_eq!(results[4].outcome, Outcome::PatronNotFound);
+
+        Ok(())
+    }
+}
@@ -1,61 +1,270 @@
 use axum::{
+    body::Bytes,
     extract::State,
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     routing::{get, post},
     Router,
 };
-use chrono::Utc;
+use serde::Deserialize;
 use serde_json::json;
 use tower_cookies::{Cookie, Cookies};
-use tracing::error;
+use tracing::{error, warn};
 
 use crate::{
-    auth::{generate_jwt, verify, Claims},
-    extractors::json::Json,
+    auth::{access_token, dummy_hash, verify, AuthError, Claims, TokenPair},
+    extractors::{client::Client, json::Json},
     model::{
-        user::{User, UserForCreate, UserForLogin},
+        login_throttle::{LoginThrottle, Throttle, ThrottleScope},
+        session::{Refresh, Session},
+        two_factor::{TwoFactor, TwoFactorPolicy},
+        user::{User, UserForCreate, UserForLogin, UserRole},
         Engine,
     },
     state::AppState,
 };
 
+use super::{two_factor::login_challenge, verification::request_verification};
+
+/// Open a session and hand out its tokens, also set as cookies for the
+/// browser app.
+pub(super) async fn issue_tokens(
+    state: &AppState<Engine>,
+    cookies: &Cookies,
+    client: &Client,
+    user_id: i64,
+    role: UserRole,
+    session: Option<(String, String)>,
+) -> crate::error::Result<TokenPair> {
+    let (session_id, refresh_token) = match session {
+        Some(session) => session,
+        None => {
+            let Client { user_agent, ip } = client;
+            Session::start(state, user_id, user_agent.as_deref(), ip.as_deref()).await?
+        }
+    };
+    let (token, expires_in) = access_token(state, user_id, role, &session_id).await?;
+
+    cookies.add(Cookie::new("token", token.clone()));
+    cookies.add(
+        Cookie::build(("refresh_token", refresh_token.clone()))
+            .path("/api")
+            .http_only(true)
+            .build(),
+    );
+    Ok(TokenPair {
+        token,
+        refresh_token,
+        expires_in,
+    })
+}
+
+/// What failed logins of `username` from `client` are counted against.
+pub(super) fn login_keys<'a>(
+    username: &'a str,
+    client: &'a Client,
+) -> Vec<(ThrottleScope, &'a str)> {
+    let mut keys = vec![(ThrottleScope::Username, username)];
+    keys.extend(client.ip.as_deref().map(|ip| (ThrottleScope::Ip, ip)));
+    keys
+}
+
+/// Turn away a login while any of its keys is backing off or locked out
+/// after failed attempts.
+pub(super) async fn check_throttle(
+    state: &AppState<Engine>,
+    keys: &[(ThrottleScope, &str)],
+) -> Option<Response> {
+    let (error, retry_after) = match LoginThrottle::check(state, keys).await {
+        Ok(Throttle::Open) => return None,
+        Ok(Throttle::Backoff { retry_after }) => (
+            "Too many failed attempts. Please try again later",
+            retry_after,
+        ),
+        Ok(Throttle::Locked { retry_after }) => (
+            "Account locked after too many failed attempts. Please try again later",
+            retry_after,
+        ),
+        Err(e) => {
+            error!("{e}");
+            return Some(
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Something is not right" })),
+                )
+                    .into_response(),
+            );
+        }
+    };
+    Some(
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            Json(json!({ "error": error, "retry_after": retry_after })),
+        )
+            .into_response(),
+    )
+}
+
+pub(super) async fn login_failed(state: &AppState<Engine>, keys: &[(ThrottleScope, &str)]) {
+    warn!("Failed login for {keys:?}");
+    if let Err(e) = LoginThrottle::failed(state, keys).await {
+        error!("{e}");
+    }
+}
+
+/// Forget earlier failures once a login went all the way through.
+pub(super) async fn login_succeeded(state: &AppState<Engine>, scope: ThrottleScope, key: &str) {
+    if let Err(e) = LoginThrottle::clear(state, scope, key).await {
+        error!("{e}");
+    }
+}
+
 async fn login(
     State(state): State<AppState<Engine>>,
     cookies: Cookies,
+    client: Client,
     Json(user): Json<UserForLogin>,
 ) -> Response {
-    if let Some(u) = User::get_by_username::<User>(&state, user.username)
-        .await
-        .unwrap()
-    {
-        match verify(&u.password, &user.password) {
-            Ok(_) => {
-                let now = Utc::now().timestamp();
-                let token = generate_jwt(
-                    &Claims {
-                        user_id: u.id,
-                        role: u.role,
-                        exp: now as usize + 3600,
-                    },
-                    &state.jwt_secret,
-                )
-                .unwrap();
-                cookies.add(Cookie::new("token", token.clone()));
-                (StatusCode::OK, Json(json!({ "token": token }))).into_response()
-            }
-            Err(_) => (
+    if let Some(response) = check_throttle(&state, &login_keys(&user.username, &client)).await {
+        return response;
+    }
+    let found = match User::get_by_username::<User>(&state, user.username.clone()).await {
+        Ok(found) => found,
+        Err(e) => {
+            error!("{e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response();
+        }
+    };
+    // a password is checked either way, so unknown usernames take as long
+    let password_hash = found.as_ref().map_or(dummy_hash(), |u| u.password.as_str());
+    let u = match (verify(password_hash, &user.password), found) {
+        (Ok(_), Some(u)) => u,
+        _ => {
+            login_failed(&state, &login_keys(&user.username, &client)).await;
+            return (
                 StatusCode::BAD_REQUEST,
                 Json(json!({ "error": "Wrong username or password" })),
             )
-                .into_response(),
+                .into_response();
         }
-    } else {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(json!({ "error": "Wrong username or password" })),
+    };
+
+    let tokens = match login_challenge(&state, &u).await {
+        Ok(Some(challenge)) => return (StatusCode::OK, Json(json!(challenge))).into_response(),
+        Ok(None) => {
+            login_succeeded(&state, ThrottleScope::Username, &u.username).await;
+            issue_tokens(&state, &cookies, &client, u.id, u.role, None).await
+        }
+        Err(e) => Err(e),
+    };
+    match tokens {
+        Ok(tokens) => (StatusCode::OK, Json(json!(tokens))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+struct RefreshRequest {
+    refresh_token: Option<String>,
+}
+
+/// Whether the policy wants a second factor `user` hasn't set up.
+async fn two_factor_missing(state: &AppState<Engine>, user: &User) -> crate::error::Result<bool> {
+    Ok(TwoFactorPolicy::requires(state, &user.role).await?
+        && !TwoFactor::is_enabled(state, user.id).await?)
+}
+
+/// Swap a refresh token, from the body or cookie, for a new pair.
+async fn refresh(
+    State(state): State<AppState<Engine>>,
+    cookies: Cookies,
+    client: Client,
+    body: Bytes,
+) -> Response {
+    // browsers send only the cookie
+    let req: RefreshRequest = serde_json::from_slice(&body).unwrap_or_default();
+    let Some(refresh_token) = req
+        .refresh_token
+        .or_else(|| cookies.get("refresh_token").map(|c| c.value().to_owned()))
+    else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "Missing refresh token" })),
         )
-            .into_response()
+            .into_response();
+    };
+
+    let Client { user_agent, ip } = &client;
+    let refreshed =
+        Session::refresh(&state, &refresh_token, user_agent.as_deref(), ip.as_deref()).await;
+    let result = match refreshed {
+        Ok(Refresh::Rotated {
+            session_id,
+            user_id,
+            refresh_token,
+        }) => match User::get::<User>(&state, user_id).await {
+            Ok(u) => match two_factor_missing(&state, &u).await {
+                Ok(false) => {
+                    let session = Some((session_id, refresh_token));
+                    issue_tokens(&state, &cookies, &client, u.id, u.role, session).await
+                }
+                // signed in before the policy, or had it reset since
+                Ok(true) => {
+                    if let Err(e) = Session::revoke(&state, &session_id).await {
+                        error!("{e}");
+                    }
+                    return (
+                        StatusCode::UNAUTHORIZED,
+                        Json(json!({
+                            "error": "Two-factor authentication is required. Please login again"
+                        })),
+                    )
+                        .into_response();
+                }
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e.into()),
+        },
+        Ok(Refresh::Reused) => {
+            warn!("Refresh token reused, session revoked");
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({ "error": "Session has ended. Please login again" })),
+            )
+                .into_response();
+        }
+        Ok(Refresh::Invalid) => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({ "error": "Invalid refresh token" })),
+            )
+                .into_response()
+        }
+        Err(e) => Err(e.into()),
+    };
+
+    match result {
+        Ok(tokens) => (StatusCode::OK, Json(json!(tokens))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
     }
 }
 
@@ -64,11 +273,17 @@ async fn register(
     Json(user): Json<UserForCreate>,
 ) -> Response {
     match User::create(&state, user).await {
-        Ok(id) => (
-            StatusCode::CREATED,
-            Json(json!({ "status": "Success", "user_id": id })),
-        )
-            .into_response(),
+        Ok(id) => {
+            // the account exists either way, the mail can be sent again later
+            if let Err(e) = request_verification(&state, id).await {
+                error!("{e}");
+            }
+            (
+                StatusCode::CREATED,
+                Json(json!({ "status": "Success", "user_id": id })),
+            )
+                .into_response()
+        }
         Err(e) => {
             error!("{e}");
             (
@@ -80,14 +295,32 @@ async fn register(
     }
 }
 
-async fn logout(cookies: Cookies) -> Response {
+/// End the session the token belongs to, so neither its access nor refresh
+/// token work any more.
+async fn logout(
+    State(state): State<AppState<Engine>>,
+    cookies: Cookies,
+    claims: Result<Claims, AuthError>,
+) -> Response {
+    if let Ok(Claims { sid: Some(sid), .. }) = claims {
+        if let Err(e) = Session::revoke(&state, &sid).await {
+            error!("{e}");
+        }
+    }
     cookies.remove("token".into());
+    cookies.remove(Cookie::build("refresh_token").path("/api").build());
     (StatusCode::OK, Json(json!({ "message": "Logout success" }))).into_response()
 }
 
+/// Public keys tokens can be checked with, empty with a shared secret.
+pub async fn jwks(State(state): State<AppState<Engine>>) -> Response {
+    (StatusCode::OK, Json(json!(state.keys.jwks()))).into_response()
+}
+
 pub fn routes() -> Router<AppState<Engine>> {
     Router::new()
         .route("/logout", get(logout))
         .route("/login", post(login))
+        .route("/refresh", post(refresh))
         .route("/register", post(register))
 }
@@ -0,0 +1,39 @@
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    barcode,
+    extractors::{json::Json, path::Path},
+    model::Engine,
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+struct PathParam {
+    code: String,
+}
+
+async fn get_barcode_svg(Path(PathParam { code }): Path<PathParam>) -> Response {
+    match barcode::code128_svg(&code) {
+        Ok(svg) => ([(header::CONTENT_TYPE, "image/svg+xml")], svg).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "Code can not be rendered as Code128" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub fn routes() -> Router<AppState<Engine>> {
+    Router::new().route("/barcode/{code}", get(get_barcode_svg))
+}
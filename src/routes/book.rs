@@ -1,26 +1,33 @@
 use axum::{
-    extract::State,
+    body::Bytes,
+    extract::{Query, State},
     http::StatusCode,
     middleware,
     response::{IntoResponse, Response},
     routing::{get, post, put},
     Router,
 };
-use chrono::{Duration, Utc};
+use axum_extra::{headers::ContentType, TypedHeader};
+use chrono::Utc;
 use serde::Deserialize;
 use serde_json::json;
-use tracing::error;
+use tracing::{error, warn};
 
 use crate::{
     auth::Claims,
     extractors::{json::Json, path::Path},
+    media,
     middlewares::role::{require_admin_role, require_issuer_admin_role},
     model::{
         book::{
-            Book, BookCopyForCreate, BookCopyForUpdate, BookForCreate, BookForUpdate, BorrowStatus,
+            Book, BookCopyForCreate, BookCopyForUpdate, BookFilter, BookForCreate, BookForUpdate,
+            BorrowStatus,
         },
-        borrowing::{Borrowing, BorrowingForCreate},
+        borrowing::Borrowing,
+        circulation::{BlockReason, Circulation, Outcome},
+        error::Error,
         review::{Review, ReviewForCreate},
+        series::Series,
         Engine,
     },
     state::AppState,
@@ -33,6 +40,12 @@ struct PathParam {
     copy_id: i64,
 }
 
+#[derive(Deserialize)]
+struct CopyStatus {
+    status: BorrowStatus,
+    reason: Option<String>,
+}
+
 async fn add_book(
     State(state): State<AppState<Engine>>,
     Json(book): Json<BookForCreate>,
@@ -57,7 +70,11 @@ async fn add_book_copy(
 ) -> Response {
     book.book_id = param.book_id;
     match Book::add_copy(&state, book).await {
-        Ok(id) => (StatusCode::CREATED, Json(json!({ "book_id": id }))).into_response(),
+        Ok(id) => (
+            StatusCode::CREATED,
+            Json(json!({ "book_id": param.book_id, "copy_id": id })),
+        )
+            .into_response(),
         Err(e) => {
             error!("{e}");
             (
@@ -70,13 +87,28 @@ async fn add_book_copy(
 }
 
 async fn get_book(State(state): State<AppState<Engine>>, Path(param): Path<PathParam>) -> Response {
-    match Book::get(&state, param.book_id).await {
-        Ok(book) => (StatusCode::OK, Json(json!({ "book": book }))).into_response(),
+    let book = match Book::get(&state, param.book_id).await {
+        Ok(book) => book,
         Err(e) => {
             error!("{e}");
-            (
+            return (
                 StatusCode::NOT_FOUND,
                 Json(json!({ "error": "Book not found" })),
+            )
+                .into_response();
+        }
+    };
+    match Series::next_for_book(&state, param.book_id).await {
+        Ok(next) => (
+            StatusCode::OK,
+            Json(json!({ "book": book, "next_in_series": next })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
             )
                 .into_response()
         }
@@ -100,8 +132,50 @@ async fn get_book_copy(
     }
 }
 
-async fn get_books(State(state): State<AppState<Engine>>) -> Response {
-    match Book::list(&state).await {
+#[derive(Deserialize)]
+struct BarcodeParam {
+    code: String,
+}
+
+async fn get_copy_by_barcode(
+    State(state): State<AppState<Engine>>,
+    Path(BarcodeParam { code }): Path<BarcodeParam>,
+) -> Response {
+    match Book::get_copy_by_barcode(&state, &code).await {
+        Ok(Some(copy)) => match Book::get(&state, copy.book_id).await {
+            Ok(book) => {
+                (StatusCode::OK, Json(json!({ "copy": copy, "book": book }))).into_response()
+            }
+            Err(e) => {
+                error!("{e}");
+                (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({ "error": "Book not found" })),
+                )
+                    .into_response()
+            }
+        },
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Book copy not found" })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn get_books(
+    State(state): State<AppState<Engine>>,
+    Query(filter): Query<BookFilter>,
+) -> Response {
+    match Book::search(&state, &filter).await {
         Ok(books) => (StatusCode::OK, Json(json!({ "books": books }))).into_response(),
         Err(e) => {
             error!("{e}");
@@ -149,6 +223,56 @@ async fn update_book(
     }
 }
 
+async fn upload_book_photo(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { book_id, .. }): Path<PathParam>,
+    TypedHeader(content_type): TypedHeader<ContentType>,
+    body: Bytes,
+) -> Response {
+    let prefix = format!("covers/{book_id}");
+    let photo = match media::store_image(
+        state.media.as_ref(),
+        &prefix,
+        &content_type.to_string(),
+        body,
+    )
+    .await
+    {
+        Ok(url) => url,
+        Err(e) => {
+            error!("{e}");
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "Photo could not be uploaded" })),
+            )
+                .into_response();
+        }
+    };
+    let book = BookForUpdate {
+        photo: Some(photo.clone()),
+        ..Default::default()
+    };
+    let old = Book::get(&state, book_id).await.ok().and_then(|b| b.photo);
+    match Book::update(&state, book_id, book).await {
+        Ok(_) => {
+            if let Some(old) = old {
+                if let Err(e) = media::delete_url(state.media.as_ref(), &old, &prefix).await {
+                    warn!("Could not remove old photo: {e}");
+                }
+            }
+            (StatusCode::OK, Json(json!({ "photo": photo }))).into_response()
+        }
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Book not found" })),
+            )
+                .into_response()
+        }
+    }
+}
+
 async fn update_book_copy(
     State(state): State<AppState<Engine>>,
     Path(PathParam { book_id, copy_id }): Path<PathParam>,
@@ -167,53 +291,83 @@ async fn update_book_copy(
     }
 }
 
+async fn set_book_copy_status(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { book_id, copy_id }): Path<PathParam>,
+    Json(CopyStatus { status, reason }): Json<CopyStatus>,
+) -> Response {
+    match Circulation::change_status(&state, book_id, copy_id, status, reason).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Copy status updated" })),
+        )
+            .into_response(),
+        Err(Error::Conflict(reason)) => {
+            (StatusCode::CONFLICT, Json(json!({ "error": reason }))).into_response()
+        }
+        Err(Error::EntityNotFound { .. }) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Book copy not found" })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
 async fn borrow_book_copy(
     State(state): State<AppState<Engine>>,
     Claims { user_id, .. }: Claims,
     Path(PathParam { book_id, copy_id }): Path<PathParam>,
 ) -> Response {
-    let due_date = Utc::now().date_naive() + Duration::weeks(1);
-    match Book::get_copy(&state, copy_id, book_id).await {
-        Ok(copy) => match copy.status {
-            Some(BorrowStatus::Available) => match Borrowing::create(
-                &state,
-                BorrowingForCreate {
-                    user_id,
-                    book_id,
-                    copy_id,
-                    due_date,
-                },
-            )
-            .await
-            {
-                Ok(_) => {
-                    (StatusCode::OK, Json(json!({ "message": "Book borrowed" }))).into_response()
-                }
-                Err(e) => {
-                    error!("{e}");
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        Json(json!({ "error": "Something is not right" })),
-                    )
-                        .into_response()
-                }
-            },
-            Some(_) => (
-                StatusCode::MISDIRECTED_REQUEST,
-                Json(json!({ "error": "Book copy is not available" })),
-            )
-                .into_response(),
-            None => (
+    let today = Utc::now().date_naive();
+    match Circulation::checkout(&state, user_id, book_id, copy_id, today).await {
+        Ok(Outcome::Success { due_date, .. }) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Book borrowed", "due_date": due_date })),
+        )
+            .into_response(),
+        Ok(
+            Outcome::Blocked {
+                reason: BlockReason::NotAvailable | BlockReason::OnLoanToAnother,
+                ..
+            }
+            | Outcome::HoldWaiting { .. },
+        ) => (
+            StatusCode::MISDIRECTED_REQUEST,
+            Json(json!({ "error": "Book copy is not available" })),
+        )
+            .into_response(),
+        // fines, loan limits and unverified emails hold the patron up
+        Ok(Outcome::Blocked { reason, .. }) => (
+            StatusCode::FORBIDDEN,
+            Json(json!({ "error": "You can't borrow books right now", "reason": reason })),
+        )
+            .into_response(),
+        Ok(Outcome::NotFound) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Book copy not found" })),
+        )
+            .into_response(),
+        Ok(outcome) => {
+            error!("Unexpected checkout outcome: {outcome:?}");
+            (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({ "error": "Something is not right" })),
             )
-                .into_response(),
-        },
+                .into_response()
+        }
         Err(e) => {
             error!("{e}");
             (
-                StatusCode::NOT_FOUND,
-                Json(json!({ "error": "Book copy not found" })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
             )
                 .into_response()
         }
@@ -293,7 +447,13 @@ pub fn routes() -> Router<AppState<Engine>> {
             post(add_book_copy).get(get_book_copies),
         )
         .route("/book/{book_id}/borrowings", get(get_book_borrowings))
+        .route("/book/{book_id}/photo", put(upload_book_photo))
         .route("/book/{book_id}/copy/{copy_id}", get(get_book_copy))
+        .route(
+            "/book/{book_id}/copy/{copy_id}/status",
+            put(set_book_copy_status),
+        )
+        .route("/copy/by-barcode/{code}", get(get_copy_by_barcode))
         .route_layer(middleware::from_fn(require_issuer_admin_role));
 
     Router::new()
@@ -308,3 +468,52 @@ pub fn routes() -> Router<AppState<Engine>> {
             get(borrow_book_copy),
         )
 }
+
+#[cfg(test)]
+mod test {
+    use sqlx::SqlitePool;
+
+    use crate::{
+        error::Result,
+        model::user::{User, UserForUpdate, UserRole},
+        state::test_state,
+    };
+
+    use super::*;
+
+    #[sqlx::test(fixtures(path = "../model/fixtures", scripts("users", "books")))]
+    fn borrowing_unverified(pool: SqlitePool) -> Result<()> {
+        let state = test_state(pool);
+        let claims = |user_id, role| Claims {
+            user_id,
+            role,
+            exp: usize::MAX,
+            jti: None,
+            sid: None,
+        };
+        let borrow = |claims, copy_id| {
+            borrow_book_copy(
+                State(state.clone()),
+                claims,
+                Path(PathParam {
+                    book_id: 1,
+                    copy_id,
+                }),
+            )
+        };
+        for (id, email) in [(1, "jdoe@localhost"), (3, "root@localhost")] {
+            let user = UserForUpdate {
+                email: Some(email.to_string()),
+                ..Default::default()
+            };
+            User::update(&state, id, user).await?;
+        }
+
+        let response = borrow(claims(1, UserRole::Member), 1).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = borrow(claims(3, UserRole::Admin), 1).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@ use axum::{
     http::StatusCode,
     middleware,
     response::{IntoResponse, Response},
-    routing::{get, put},
+    routing::{get, post, put},
     Router,
 };
 use serde::Deserialize;
@@ -16,6 +16,9 @@ use crate::{
     middlewares::role::{require_admin_role, require_issuer_admin_role},
     model::{
         borrowing::{Borrowing, BorrowingForUpdate},
+        circulation::Circulation,
+        error::Error,
+        series::Series,
         Engine,
     },
     state::AppState,
@@ -47,15 +50,28 @@ async fn get_current_user_borrowings(
     Claims { user_id, .. }: Claims,
     State(state): State<AppState<Engine>>,
 ) -> Response {
-    match Borrowing::list_by_user(&state, user_id).await {
-        Ok(borrowings) => {
-            (StatusCode::OK, Json(json!({ "borrowings": borrowings }))).into_response()
-        }
+    let borrowings = match Borrowing::list_by_user(&state, user_id).await {
+        Ok(borrowings) => borrowings,
         Err(e) => {
             error!("{e}");
-            (
+            return (
                 StatusCode::NOT_FOUND,
                 Json(json!({ "error": "Borrowing not found" })),
+            )
+                .into_response();
+        }
+    };
+    match Series::next_for_user(&state, user_id).await {
+        Ok(next) => (
+            StatusCode::OK,
+            Json(json!({ "borrowings": borrowings, "next_in_series": next })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
             )
                 .into_response()
         }
@@ -84,6 +100,31 @@ async fn update_borrowing(
     }
 }
 
+async fn declare_lost(
+    State(state): State<AppState<Engine>>,
+    Path(param): Path<PathParam>,
+) -> Response {
+    match Circulation::declare_lost(&state, param.borrowing_id).await {
+        Ok(replacement) => (StatusCode::OK, Json(json!(replacement))).into_response(),
+        Err(Error::Conflict(reason)) => {
+            (StatusCode::CONFLICT, Json(json!({ "error": reason }))).into_response()
+        }
+        Err(Error::EntityNotFound { .. }) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Borrowing not found" })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
 pub fn routes() -> Router<AppState<Engine>> {
     let admin_routes = Router::new()
         .route("/borrowing/{borrowing_id}", put(update_borrowing))
@@ -91,6 +132,7 @@ pub fn routes() -> Router<AppState<Engine>> {
 
     let restricted = Router::new()
         .route("/borrowing/{borrowing_id}", get(get_borrowing))
+        .route("/borrowing/{borrowing_id}/lost", post(declare_lost))
         .merge(admin_routes)
         .route_layer(middleware::from_fn(require_issuer_admin_role));
 
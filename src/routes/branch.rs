@@ -0,0 +1,251 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    extractors::{json::Json, path::Path},
+    middlewares::role::require_admin_role,
+    model::{
+        book::Book,
+        branch::{
+            Branch, BranchForCreate, BranchForUpdate, ShelfLocationForCreate,
+            ShelfLocationForUpdate,
+        },
+        Engine,
+    },
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+struct PathParam {
+    branch_id: i64,
+}
+
+#[derive(Deserialize)]
+struct ShelfParam {
+    shelf_id: i64,
+}
+
+async fn get_branches(State(state): State<AppState<Engine>>) -> Response {
+    match Branch::list(&state).await {
+        Ok(branches) => (StatusCode::OK, Json(json!({ "branches": branches }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn get_branch(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { branch_id }): Path<PathParam>,
+) -> Response {
+    match Branch::get(&state, branch_id).await {
+        Ok(branch) => (StatusCode::OK, Json(json!({ "branch": branch }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Branch not found" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn create_branch(
+    State(state): State<AppState<Engine>>,
+    Json(branch): Json<BranchForCreate>,
+) -> Response {
+    match Branch::create(&state, branch).await {
+        Ok(id) => (
+            StatusCode::CREATED,
+            Json(json!({ "message": "Branch added", "branch_id": id })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "Branch could not be added" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn update_branch(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { branch_id }): Path<PathParam>,
+    Json(branch): Json<BranchForUpdate>,
+) -> Response {
+    match Branch::update(&state, branch_id, branch).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Branch updated" }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Branch not found" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn delete_branch(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { branch_id }): Path<PathParam>,
+) -> Response {
+    match Branch::delete(&state, branch_id).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Branch deleted" }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Branch not found" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn get_shelves(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { branch_id }): Path<PathParam>,
+) -> Response {
+    match Branch::list_shelves(&state, branch_id).await {
+        Ok(shelves) => (StatusCode::OK, Json(json!({ "shelves": shelves }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn add_shelf(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { branch_id }): Path<PathParam>,
+    Json(shelf): Json<ShelfLocationForCreate>,
+) -> Response {
+    match Branch::add_shelf(&state, ShelfLocationForCreate { branch_id, ..shelf }).await {
+        Ok(id) => (
+            StatusCode::CREATED,
+            Json(json!({ "message": "Shelf added", "shelf_id": id })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "Shelf could not be added" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn get_shelf(
+    State(state): State<AppState<Engine>>,
+    Path(ShelfParam { shelf_id }): Path<ShelfParam>,
+) -> Response {
+    match Branch::get_shelf(&state, shelf_id).await {
+        Ok(shelf) => (StatusCode::OK, Json(json!({ "shelf": shelf }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Shelf not found" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Copies on the shelf in shelf order.
+async fn get_shelf_list(
+    State(state): State<AppState<Engine>>,
+    Path(ShelfParam { shelf_id }): Path<ShelfParam>,
+) -> Response {
+    match Book::shelf_list(&state, shelf_id).await {
+        Ok(copies) => (StatusCode::OK, Json(json!({ "copies": copies }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn update_shelf(
+    State(state): State<AppState<Engine>>,
+    Path(ShelfParam { shelf_id }): Path<ShelfParam>,
+    Json(shelf): Json<ShelfLocationForUpdate>,
+) -> Response {
+    match Branch::update_shelf(&state, shelf_id, shelf).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Shelf updated" }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Shelf not found" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn delete_shelf(
+    State(state): State<AppState<Engine>>,
+    Path(ShelfParam { shelf_id }): Path<ShelfParam>,
+) -> Response {
+    match Branch::delete_shelf(&state, shelf_id).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Shelf deleted" }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Shelf not found" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub fn routes() -> Router<AppState<Engine>> {
+    let admin_routes = Router::new()
+        .route("/branch", post(create_branch))
+        .route(
+            "/branch/{branch_id}",
+            put(update_branch).delete(delete_branch),
+        )
+        .route("/branch/{branch_id}/shelf", post(add_shelf))
+        .route("/shelf/{shelf_id}", put(update_shelf).delete(delete_shelf))
+        .route_layer(middleware::from_fn(require_admin_role));
+
+    Router::new()
+        .merge(admin_routes)
+        .route("/branches", get(get_branches))
+        .route("/branch/{branch_id}", get(get_branch))
+        .route("/branch/{branch_id}/shelves", get(get_shelves))
+        .route("/shelf/{shelf_id}", get(get_shelf))
+        .route("/shelf/{shelf_id}/copies", get(get_shelf_list))
+}
@@ -0,0 +1,105 @@
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    extractors::json::Json,
+    middlewares::role::require_issuer_admin_role,
+    model::{circulation::Circulation, user::User, Engine},
+    offline,
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+struct ScanRequest {
+    /// Patron card, leave out to check everything in
+    card: Option<String>,
+    /// Copy barcodes in the order they were scanned
+    items: Vec<String>,
+}
+
+async fn scan(State(state): State<AppState<Engine>>, Json(req): Json<ScanRequest>) -> Response {
+    let user = match &req.card {
+        Some(card) => match User::get_by_card_number::<User>(&state, card).await {
+            Ok(Some(user)) => Some(user),
+            Ok(None) => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({ "error": "User not found" })),
+                )
+                    .into_response()
+            }
+            Err(e) => {
+                error!("{e}");
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Something is not right" })),
+                )
+                    .into_response();
+            }
+        },
+        None => None,
+    };
+
+    let today = Utc::now().date_naive();
+    match Circulation::scan(&state, user.as_ref().map(|u| u.id), &req.items, today).await {
+        Ok(items) => (
+            StatusCode::OK,
+            Json(json!({ "user": user, "items": items })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Replay a CSV of circulation recorded during an outage.
+async fn upload_offline(State(state): State<AppState<Engine>>, body: Bytes) -> Response {
+    let (events, invalid) = offline::parse(&body);
+    match offline::replay(&state, events).await {
+        Ok(results) => {
+            let conflicts = results.iter().filter(|r| r.is_conflict()).count();
+            (
+                StatusCode::OK,
+                Json(json!({
+                    "processed": results.len(),
+                    "conflicts": conflicts,
+                    "results": results,
+                    "invalid": invalid,
+                })),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub fn routes() -> Router<AppState<Engine>> {
+    Router::new()
+        .route("/circulation/scan", post(scan))
+        .route("/circulation/offline", post(upload_offline))
+        .route_layer(middleware::from_fn(require_issuer_admin_role))
+}
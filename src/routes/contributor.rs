@@ -0,0 +1,147 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    extractors::{json::Json, path::Path},
+    middlewares::role::{require_admin_role, require_issuer_admin_role},
+    model::{
+        contributor::{Contributor, ContributorFilter, ContributorForUpdate, CreditForSet},
+        error::Error,
+        Engine,
+    },
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+struct PathParam {
+    contributor_id: i64,
+}
+
+#[derive(Deserialize)]
+struct BookParam {
+    book_id: i64,
+}
+
+fn error_response(e: Error) -> Response {
+    match e {
+        Error::EntityNotFound { entity, .. } => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Not found in {entity}") })),
+        )
+            .into_response(),
+        e => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn get_contributors(
+    State(state): State<AppState<Engine>>,
+    Query(filter): Query<ContributorFilter>,
+) -> Response {
+    match Contributor::list(&state, &filter).await {
+        Ok(contributors) => (
+            StatusCode::OK,
+            Json(json!({ "contributors": contributors })),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// The contributor page, with every book they are credited on.
+async fn get_contributor(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { contributor_id }): Path<PathParam>,
+) -> Response {
+    let contributor = match Contributor::get(&state, contributor_id).await {
+        Ok(contributor) => contributor,
+        Err(e) => return error_response(e),
+    };
+    match Contributor::books(&state, contributor_id).await {
+        Ok(books) => (
+            StatusCode::OK,
+            Json(json!({ "contributor": contributor, "books": books })),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn update_contributor(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { contributor_id }): Path<PathParam>,
+    Json(contributor): Json<ContributorForUpdate>,
+) -> Response {
+    match Contributor::update(&state, contributor_id, contributor).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Contributor updated" })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "Contributor could not be updated" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn get_book_contributors(
+    State(state): State<AppState<Engine>>,
+    Path(BookParam { book_id }): Path<BookParam>,
+) -> Response {
+    match Contributor::for_book(&state, book_id).await {
+        Ok(credits) => (StatusCode::OK, Json(json!({ "contributors": credits }))).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn set_book_contributors(
+    State(state): State<AppState<Engine>>,
+    Path(BookParam { book_id }): Path<BookParam>,
+    Json(credits): Json<Vec<CreditForSet>>,
+) -> Response {
+    match Contributor::set_for_book(&state, book_id, &credits).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Contributors updated" })),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn routes() -> Router<AppState<Engine>> {
+    let admin_routes = Router::new()
+        .route("/contributor/{contributor_id}", put(update_contributor))
+        .route_layer(middleware::from_fn(require_admin_role));
+
+    let issuer_routes = Router::new()
+        .route("/book/{book_id}/contributors", put(set_book_contributors))
+        .route_layer(middleware::from_fn(require_issuer_admin_role));
+
+    Router::new()
+        .merge(admin_routes)
+        .merge(issuer_routes)
+        .route("/contributors", get(get_contributors))
+        .route("/contributor/{contributor_id}", get(get_contributor))
+        .route("/book/{book_id}/contributors", get(get_book_contributors))
+}
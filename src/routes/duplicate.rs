@@ -0,0 +1,96 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    extractors::{json::Json, path::Path},
+    middlewares::role::require_admin_role,
+    model::{
+        duplicate::{Duplicate, MIN_SIMILARITY},
+        error::Error,
+        Engine,
+    },
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+struct PathParam {
+    book_id: i64,
+}
+
+#[derive(Deserialize)]
+struct ReportParams {
+    /// From 0 to 1
+    min_similarity: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct MergeRequest {
+    duplicate_id: i64,
+}
+
+async fn get_duplicates(
+    State(state): State<AppState<Engine>>,
+    Query(ReportParams { min_similarity }): Query<ReportParams>,
+) -> Response {
+    let min_similarity = min_similarity.unwrap_or(MIN_SIMILARITY);
+    match Duplicate::find(&state, min_similarity).await {
+        Ok(duplicates) => {
+            (StatusCode::OK, Json(json!({ "duplicates": duplicates }))).into_response()
+        }
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Merge the duplicate given into the book in the path.
+async fn merge_duplicate(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { book_id }): Path<PathParam>,
+    Json(MergeRequest { duplicate_id }): Json<MergeRequest>,
+) -> Response {
+    match Duplicate::merge(&state, book_id, duplicate_id).await {
+        Ok(merged) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Books merged", "merged": merged })),
+        )
+            .into_response(),
+        Err(Error::Conflict(reason)) => {
+            (StatusCode::CONFLICT, Json(json!({ "error": reason }))).into_response()
+        }
+        Err(Error::EntityNotFound { id, .. }) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Book {id} not found") })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Books could not be merged" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub fn routes() -> Router<AppState<Engine>> {
+    Router::new()
+        .route("/books/duplicates", get(get_duplicates))
+        .route("/book/{book_id}/merge", post(merge_duplicate))
+        .route_layer(middleware::from_fn(require_admin_role))
+}
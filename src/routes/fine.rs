@@ -14,7 +14,7 @@ use crate::{
     extractors::{json::Json, path::Path},
     middlewares::role::{require_admin_role, require_issuer_admin_role},
     model::{
-        fine::{Fine, FineForUpdate},
+        fine::{Fine, FineForCreate, FineForUpdate},
         Engine,
     },
     state::AppState,
@@ -66,7 +66,7 @@ async fn update_fine(
 
 async fn create_fine(
     State(state): State<AppState<Engine>>,
-    Json(fine): Json<FineForUpdate>,
+    Json(fine): Json<FineForCreate>,
 ) -> Response {
     match Fine::create(&state, fine).await {
         Ok(_) => (
@@ -0,0 +1,299 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Router,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{error, warn};
+
+use crate::{
+    auth::{generate_jwt, Claims},
+    extractors::{json::Json, path::Path},
+    middlewares::role::{require_admin_role, require_kiosk_role},
+    model::{
+        circulation::{Action, Circulation},
+        error::Error as ModelError,
+        kiosk::{Kiosk, KioskActivityForCreate, KioskForCreate, KioskForUpdate},
+        login_throttle::ThrottleScope,
+        user::{User, UserRole},
+        Engine,
+    },
+    state::AppState,
+};
+
+use super::auth::{check_throttle, login_failed, login_succeeded};
+
+/// Kiosk tokens live long, deactivating the kiosk or reissuing its token
+/// revokes them.
+const KIOSK_TOKEN_TTL: i64 = 365 * 24 * 3600;
+
+#[derive(Deserialize)]
+struct PathParam {
+    kiosk_id: i64,
+}
+
+/// A new token for the kiosk, replacing the one it had.
+async fn kiosk_token(state: &AppState<Engine>, kiosk_id: i64) -> crate::error::Result<String> {
+    let token_id = Kiosk::new_token_id(state, kiosk_id).await?;
+    let now = Utc::now().timestamp();
+    generate_jwt(
+        &Claims {
+            user_id: kiosk_id,
+            role: UserRole::Kiosk,
+            exp: (now + KIOSK_TOKEN_TTL) as usize,
+            jti: Some(token_id),
+            sid: None,
+        },
+        &state.keys,
+    )
+}
+
+async fn get_kiosks(State(state): State<AppState<Engine>>) -> Response {
+    match Kiosk::list(&state).await {
+        Ok(kiosks) => (StatusCode::OK, Json(json!({ "kiosks": kiosks }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn create_kiosk(
+    State(state): State<AppState<Engine>>,
+    Json(kiosk): Json<KioskForCreate>,
+) -> Response {
+    match Kiosk::create(&state, kiosk).await {
+        Ok(id) => match kiosk_token(&state, id).await {
+            Ok(token) => (
+                StatusCode::CREATED,
+                Json(json!({ "kiosk_id": id, "token": token })),
+            )
+                .into_response(),
+            Err(e) => {
+                error!("{e}");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Something is not right" })),
+                )
+                    .into_response()
+            }
+        },
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "Something doesn't look right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn update_kiosk(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { kiosk_id }): Path<PathParam>,
+    Json(kiosk): Json<KioskForUpdate>,
+) -> Response {
+    match Kiosk::update(&state, kiosk_id, kiosk).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Kiosk updated" }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Kiosk not found" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn reissue_kiosk_token(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { kiosk_id }): Path<PathParam>,
+) -> Response {
+    match kiosk_token(&state, kiosk_id).await {
+        Ok(token) => (StatusCode::OK, Json(json!({ "token": token }))).into_response(),
+        Err(crate::error::Error::Model(ModelError::EntityNotFound { .. })) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Kiosk not found" })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn get_kiosk_activity(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { kiosk_id }): Path<PathParam>,
+) -> Response {
+    match Kiosk::list_activity(&state, kiosk_id).await {
+        Ok(activity) => (StatusCode::OK, Json(json!({ "activity": activity }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct KioskRequest {
+    card: String,
+    pin: String,
+    /// Copy barcodes in the order they were scanned
+    items: Vec<String>,
+}
+
+async fn kiosk_checkout(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+    Json(req): Json<KioskRequest>,
+) -> Response {
+    circulate(&state, user_id, Action::Checkout, req).await
+}
+
+async fn kiosk_checkin(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+    Json(req): Json<KioskRequest>,
+) -> Response {
+    circulate(&state, user_id, Action::Checkin, req).await
+}
+
+/// Sign the patron in with card and PIN, then run the scanned items through
+/// the circulation desk, logging every step against the kiosk.
+async fn circulate(
+    state: &AppState<Engine>,
+    kiosk_id: i64,
+    action: Action,
+    req: KioskRequest,
+) -> Response {
+    // PINs are short, guessing them is slowed down per card and per kiosk
+    let kiosk = kiosk_id.to_string();
+    let keys = [
+        (ThrottleScope::Card, req.card.as_str()),
+        (ThrottleScope::Kiosk, &kiosk),
+    ];
+    if let Some(response) = check_throttle(state, &keys).await {
+        return response;
+    }
+    let user_id = match User::verify_pin(state, &req.card, &req.pin).await {
+        Ok(Some(user_id)) => {
+            login_succeeded(state, ThrottleScope::Card, &req.card).await;
+            user_id
+        }
+        Ok(None) => {
+            login_failed(state, &keys).await;
+            log(
+                state,
+                kiosk_id,
+                None,
+                action,
+                None,
+                json!({ "result": "wrong_pin" }),
+            )
+            .await;
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({ "error": "Wrong card number or PIN" })),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            error!("{e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response();
+        }
+    };
+
+    // check-ins don't need the patron beyond signing in
+    let patron = match action {
+        Action::Checkout => Some(user_id),
+        _ => None,
+    };
+    let today = Utc::now().date_naive();
+    match Circulation::scan(state, patron, &req.items, today).await {
+        Ok(items) => {
+            for item in &items {
+                log(
+                    state,
+                    kiosk_id,
+                    Some(user_id),
+                    action,
+                    Some(item.barcode.clone()),
+                    json!(item.outcome),
+                )
+                .await;
+            }
+            (StatusCode::OK, Json(json!({ "items": items }))).into_response()
+        }
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn log(
+    state: &AppState<Engine>,
+    kiosk_id: i64,
+    user_id: Option<i64>,
+    action: Action,
+    barcode: Option<String>,
+    result: serde_json::Value,
+) {
+    let activity = KioskActivityForCreate {
+        kiosk_id,
+        user_id,
+        action,
+        barcode,
+        result: result.to_string(),
+    };
+    if let Err(e) = Kiosk::log(state, activity).await {
+        warn!("Could not log kiosk activity: {e}");
+    }
+}
+
+/// Routes managing kiosks, for logged in admins.
+pub fn routes() -> Router<AppState<Engine>> {
+    Router::new()
+        .route("/kiosks", get(get_kiosks).post(create_kiosk))
+        .route("/kiosks/{kiosk_id}", put(update_kiosk))
+        .route("/kiosks/{kiosk_id}/token", post(reissue_kiosk_token))
+        .route("/kiosks/{kiosk_id}/activity", get(get_kiosk_activity))
+        .route_layer(middleware::from_fn(require_admin_role))
+}
+
+/// Routes used by the kiosks themselves, with their device token.
+pub fn device_routes(state: AppState<Engine>) -> Router<AppState<Engine>> {
+    Router::new()
+        .route("/kiosk/checkout", post(kiosk_checkout))
+        .route("/kiosk/checkin", post(kiosk_checkin))
+        .route_layer(middleware::from_fn_with_state(state, require_kiosk_role))
+}
@@ -0,0 +1,100 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    extractors::json::Json,
+    labels::{self, Label, LabelTemplate},
+    middlewares::role::require_issuer_admin_role,
+    model::{book::Book, Engine},
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+struct CopyRef {
+    book_id: i64,
+    copy_id: i64,
+}
+
+#[derive(Deserialize)]
+struct LabelRequest {
+    #[serde(default)]
+    copies: Vec<CopyRef>,
+    /// Also print every copy added on or after this date
+    since: Option<NaiveDate>,
+    #[serde(default)]
+    template: LabelTemplate,
+    /// Label positions already used on the first sheet
+    #[serde(default)]
+    skip: usize,
+}
+
+async fn print_labels(
+    State(state): State<AppState<Engine>>,
+    Json(req): Json<LabelRequest>,
+) -> Response {
+    if req.copies.is_empty() && req.since.is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "No copies selected" })),
+        )
+            .into_response();
+    }
+
+    let copies: Vec<(i64, i64)> = req.copies.iter().map(|c| (c.book_id, c.copy_id)).collect();
+    let labels: Vec<Label> = match Book::list_labels(&state, &copies, req.since).await {
+        Ok(copies) => copies
+            .into_iter()
+            .map(|c| Label {
+                title: c.title,
+                call_number: c.call_number.or(c.location),
+                barcode: c.barcode,
+            })
+            .collect(),
+        Err(e) => {
+            error!("{e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response();
+        }
+    };
+
+    match labels::render(req.template, &labels, req.skip) {
+        Ok(pdf) => (
+            [
+                (header::CONTENT_TYPE, "application/pdf"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "inline; filename=\"labels.pdf\"",
+                ),
+            ],
+            pdf,
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(json!({ "error": "A barcode can not be printed as Code128" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub fn routes() -> Router<AppState<Engine>> {
+    Router::new()
+        .route("/labels", post(print_labels))
+        .route_layer(middleware::from_fn(require_issuer_admin_role))
+}
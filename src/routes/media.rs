@@ -0,0 +1,50 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    extractors::{json::Json, path::Path},
+    model::Engine,
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+struct PathParam {
+    key: String,
+}
+
+async fn get_media(
+    State(state): State<AppState<Engine>>,
+    Path(param): Path<PathParam>,
+) -> Response {
+    match state.media.get(&param.key).await {
+        Ok(media) => (
+            [
+                (header::CONTENT_TYPE, media.content_type),
+                // browsers go by the type above and nothing else
+                (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+            ],
+            media.data,
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Media not found" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub fn routes() -> Router<AppState<Engine>> {
+    Router::new().route("/media/{*key}", get(get_media))
+}
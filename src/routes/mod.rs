@@ -4,7 +4,7 @@ use axum::{
     middleware,
     response::{IntoResponse, Response},
     routing::get,
-    Json, Router,
+    Extension, Json, Router,
 };
 use serde_json::json;
 use tracing::error;
@@ -16,13 +16,29 @@ use crate::{
 };
 
 mod auth;
+mod barcode;
 mod book;
 mod borrowing;
+mod branch;
 mod category;
+mod circulation;
+mod contributor;
+mod duplicate;
 mod fine;
+mod kiosk;
+mod label;
+mod media;
+mod notification;
+mod password;
 mod reservation;
 mod review;
+mod series;
+mod stocktake;
+mod transfer;
+mod two_factor;
 mod user;
+mod verification;
+mod work;
 
 // basic handler that responds with a hello world json
 async fn hello_world() -> Response {
@@ -65,22 +81,43 @@ pub fn routes(state: AppState<Engine>) -> Router {
     let protected_routes = Router::new()
         .route("/hello", get(hello_world))
         .merge(user::routes())
+        .merge(barcode::routes())
         .merge(book::routes())
         .merge(borrowing::routes())
+        .merge(branch::routes())
         .merge(category::routes())
+        .merge(circulation::routes())
+        .merge(contributor::routes())
+        .merge(duplicate::routes())
         .merge(fine::routes())
+        .merge(kiosk::routes())
+        .merge(label::routes())
+        .merge(media::routes())
+        .merge(notification::routes())
         .merge(review::routes())
         .merge(reservation::routes())
+        .merge(series::routes())
+        .merge(stocktake::routes())
+        .merge(transfer::routes())
+        .merge(two_factor::routes())
+        .merge(work::routes())
         .route_layer(middleware::from_fn(require_login));
 
     let api_routes = Router::new()
         .merge(protected_routes)
+        .merge(kiosk::device_routes(state.clone()))
         .merge(auth::routes())
+        .merge(password::routes())
+        .merge(two_factor::login_routes())
+        .merge(verification::routes())
         .route("/users/exists", get(user_exists))
-        .fallback(not_found);
+        .fallback(not_found)
+        // lets the claims extractor check sessions
+        .layer(Extension(state.clone()));
 
     Router::new()
         .route("/hello", get(hello_world))
+        .route("/.well-known/jwks.json", get(auth::jwks))
         .nest("/api", api_routes)
         .with_state(state)
 }
@@ -0,0 +1,112 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    auth::Claims,
+    extractors::{json::Json, path::Path},
+    model::{notification::Notification, Engine},
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+struct PathParam {
+    notification_id: i64,
+}
+
+#[derive(Deserialize)]
+struct ListParams {
+    /// Only the ones not read yet
+    #[serde(default)]
+    unread: bool,
+}
+
+async fn get_notifications(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+    Query(ListParams { unread }): Query<ListParams>,
+) -> Response {
+    match Notification::list_for_user(&state, user_id, unread).await {
+        Ok(notifications) => {
+            let unread = notifications.iter().filter(|n| n.read_at.is_none()).count();
+            (
+                StatusCode::OK,
+                Json(json!({ "notifications": notifications, "unread": unread })),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn read_notification(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+    Path(PathParam { notification_id }): Path<PathParam>,
+) -> Response {
+    match Notification::mark_read(&state, notification_id, user_id).await {
+        Ok(true) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Notification read" })),
+        )
+            .into_response(),
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Notification not found" })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn read_all_notifications(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+) -> Response {
+    match Notification::mark_all_read(&state, user_id).await {
+        Ok(read) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Notifications read", "read": read })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub fn routes() -> Router<AppState<Engine>> {
+    Router::new()
+        .route("/notifications", get(get_notifications))
+        .route("/notifications/read", put(read_all_notifications))
+        .route(
+            "/notifications/{notification_id}/read",
+            put(read_notification),
+        )
+}
@@ -0,0 +1,146 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    extractors::{client::Client, json::Json},
+    model::{
+        login_throttle::{LoginThrottle, Throttle, ThrottleScope},
+        outbox::Outbox,
+        password_reset::PasswordReset,
+        user::User,
+        Engine,
+    },
+    notify::mail::{render, Vars},
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+struct ForgotRequest {
+    email: String,
+}
+
+#[derive(Deserialize)]
+struct ResetRequest {
+    token: String,
+    password: String,
+}
+
+/// Send a reset link to the address if it belongs to a user. The answer is
+/// the same either way, so it can't be used to find out who has an account.
+///
+/// Requests are throttled by the address asked for, whether it's
+/// registered or not, and by client address.
+async fn forgot_password(
+    State(state): State<AppState<Engine>>,
+    client: Client,
+    Json(ForgotRequest { email }): Json<ForgotRequest>,
+) -> Response {
+    let email = email.trim();
+    let lowercase = email.to_lowercase();
+    let mut keys = vec![(ThrottleScope::ResetEmail, lowercase.as_str())];
+    keys.extend(client.ip.as_deref().map(|ip| (ThrottleScope::ResetIp, ip)));
+    match LoginThrottle::check(&state, &keys).await {
+        Ok(Throttle::Open) => {}
+        Ok(Throttle::Backoff { retry_after } | Throttle::Locked { retry_after }) => {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                Json(json!({
+                    "error": "Too many reset requests. Please try again later",
+                    "retry_after": retry_after
+                })),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            error!("{e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response();
+        }
+    }
+    // every request counts, there's nothing to get right
+    if let Err(e) = LoginThrottle::failed(&state, &keys).await {
+        error!("{e}");
+    }
+
+    let user = match User::get_by_email::<User>(&state, email).await {
+        Ok(user) => user,
+        Err(e) => {
+            error!("{e}");
+            None
+        }
+    };
+    if let Some(user) = user {
+        if let Err(e) = send_reset_link(&state, &user).await {
+            error!("{e}");
+        }
+    }
+    (
+        StatusCode::OK,
+        Json(json!({ "message": "If the address is registered, a reset link is on its way" })),
+    )
+        .into_response()
+}
+
+async fn send_reset_link(state: &AppState<Engine>, user: &User) -> crate::error::Result<()> {
+    let token = PasswordReset::create(state, user.id).await?;
+    let vars = Vars::new()
+        .user(user)
+        .set(
+            "link",
+            format!("{}/reset-password?token={token}", state.config.app_url),
+        )
+        .set("minutes", state.config.auth.password_reset_minutes);
+    Outbox::queue(state, render("password_reset", &user.email, &vars)?).await?;
+    Ok(())
+}
+
+async fn reset_password(
+    State(state): State<AppState<Engine>>,
+    Json(ResetRequest { token, password }): Json<ResetRequest>,
+) -> Response {
+    if password.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Password can't be empty" })),
+        )
+            .into_response();
+    }
+    match PasswordReset::consume(&state, &token, &password).await {
+        Ok(Some(_)) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Password updated. Please login again" })),
+        )
+            .into_response(),
+        Ok(None) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Invalid or expired reset link" })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub fn routes() -> Router<AppState<Engine>> {
+    Router::new()
+        .route("/password/forgot", post(forgot_password))
+        .route("/password/reset", post(reset_password))
+}
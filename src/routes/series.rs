@@ -0,0 +1,168 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    extractors::{json::Json, path::Path},
+    middlewares::role::require_admin_role,
+    model::{
+        error::Error,
+        series::{Series, SeriesForCreate, SeriesForUpdate},
+        Engine,
+    },
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+struct PathParam {
+    series_id: i64,
+}
+
+#[derive(Deserialize)]
+struct VolumeParam {
+    series_id: i64,
+    book_id: i64,
+}
+
+#[derive(Deserialize)]
+struct VolumeForSet {
+    volume: f64,
+}
+
+fn error_response(e: Error) -> Response {
+    match e {
+        Error::Conflict(reason) => {
+            (StatusCode::CONFLICT, Json(json!({ "error": reason }))).into_response()
+        }
+        Error::EntityNotFound { entity, .. } => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Not found in {entity}") })),
+        )
+            .into_response(),
+        e => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn get_all_series(State(state): State<AppState<Engine>>) -> Response {
+    match Series::list(&state).await {
+        Ok(series) => (StatusCode::OK, Json(json!({ "series": series }))).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// The series page, with its books in volume order.
+async fn get_series(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { series_id }): Path<PathParam>,
+) -> Response {
+    let series = match Series::get(&state, series_id).await {
+        Ok(series) => series,
+        Err(e) => return error_response(e),
+    };
+    match Series::volumes(&state, series_id).await {
+        Ok(volumes) => (
+            StatusCode::OK,
+            Json(json!({ "series": series, "volumes": volumes })),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn create_series(
+    State(state): State<AppState<Engine>>,
+    Json(series): Json<SeriesForCreate>,
+) -> Response {
+    match Series::create(&state, series).await {
+        Ok(id) => (
+            StatusCode::CREATED,
+            Json(json!({ "message": "Series added", "series_id": id })),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn update_series(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { series_id }): Path<PathParam>,
+    Json(series): Json<SeriesForUpdate>,
+) -> Response {
+    match Series::update(&state, series_id, series).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Series updated" }))).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn delete_series(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { series_id }): Path<PathParam>,
+) -> Response {
+    match Series::delete(&state, series_id).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Series deleted" }))).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn set_volume(
+    State(state): State<AppState<Engine>>,
+    Path(VolumeParam { series_id, book_id }): Path<VolumeParam>,
+    Json(VolumeForSet { volume }): Json<VolumeForSet>,
+) -> Response {
+    match Series::set_volume(&state, series_id, book_id, volume).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Book added to series" })),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn remove_volume(
+    State(state): State<AppState<Engine>>,
+    Path(VolumeParam { series_id, book_id }): Path<VolumeParam>,
+) -> Response {
+    match Series::remove_book(&state, series_id, book_id).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Book removed from series" })),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn routes() -> Router<AppState<Engine>> {
+    let admin_routes = Router::new()
+        .route("/series", post(create_series))
+        .route(
+            "/series/{series_id}",
+            put(update_series).delete(delete_series),
+        )
+        .route(
+            "/series/{series_id}/book/{book_id}",
+            put(set_volume).delete(remove_volume),
+        )
+        .route_layer(middleware::from_fn(require_admin_role));
+
+    Router::new()
+        .merge(admin_routes)
+        .route("/series", get(get_all_series))
+        .route("/series/{series_id}", get(get_series))
+}
@@ -0,0 +1,143 @@
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    auth::Claims,
+    extractors::{json::Json, path::Path},
+    middlewares::role::require_issuer_admin_role,
+    model::{
+        error::Error,
+        stocktake::{Stocktake, StocktakeForCreate},
+        Engine,
+    },
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+struct PathParam {
+    stocktake_id: i64,
+}
+
+#[derive(Deserialize)]
+struct CloseRequest {
+    /// Mark copies expected but not scanned as missing
+    #[serde(default)]
+    mark_missing: bool,
+}
+
+fn error_response(e: Error) -> Response {
+    match e {
+        Error::Conflict(reason) => {
+            (StatusCode::CONFLICT, Json(json!({ "error": reason }))).into_response()
+        }
+        Error::EntityNotFound { entity, .. } => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Not found in {entity}") })),
+        )
+            .into_response(),
+        e => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn get_stocktakes(State(state): State<AppState<Engine>>) -> Response {
+    match Stocktake::list(&state).await {
+        Ok(stocktakes) => {
+            (StatusCode::OK, Json(json!({ "stocktakes": stocktakes }))).into_response()
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_stocktake(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { stocktake_id }): Path<PathParam>,
+) -> Response {
+    match Stocktake::get(&state, stocktake_id).await {
+        Ok(stocktake) => (StatusCode::OK, Json(json!({ "stocktake": stocktake }))).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn create_stocktake(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+    Json(stocktake): Json<StocktakeForCreate>,
+) -> Response {
+    match Stocktake::create(&state, stocktake, user_id).await {
+        Ok(id) => (
+            StatusCode::CREATED,
+            Json(json!({ "message": "Stocktake started", "stocktake_id": id })),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Add scanned barcodes, sent as plain text with one or more per line.
+async fn upload_scans(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { stocktake_id }): Path<PathParam>,
+    body: Bytes,
+) -> Response {
+    let barcodes: Vec<String> = String::from_utf8_lossy(&body)
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    match Stocktake::scan(&state, stocktake_id, &barcodes).await {
+        Ok(scanned) => (StatusCode::OK, Json(json!(scanned))).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_report(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { stocktake_id }): Path<PathParam>,
+) -> Response {
+    match Stocktake::report(&state, stocktake_id).await {
+        Ok(report) => (StatusCode::OK, Json(json!(report))).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn close_stocktake(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { stocktake_id }): Path<PathParam>,
+    Json(CloseRequest { mark_missing }): Json<CloseRequest>,
+) -> Response {
+    match Stocktake::close(&state, stocktake_id, mark_missing).await {
+        Ok(missing) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Stocktake closed", "missing": missing })),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn routes() -> Router<AppState<Engine>> {
+    Router::new()
+        .route("/stocktakes", get(get_stocktakes))
+        .route("/stocktake", post(create_stocktake))
+        .route("/stocktake/{stocktake_id}", get(get_stocktake))
+        .route("/stocktake/{stocktake_id}/scans", post(upload_scans))
+        .route("/stocktake/{stocktake_id}/report", get(get_report))
+        .route("/stocktake/{stocktake_id}/close", post(close_stocktake))
+        .route_layer(middleware::from_fn(require_issuer_admin_role))
+}
@@ -0,0 +1,119 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    auth::Claims,
+    extractors::{json::Json, path::Path},
+    middlewares::role::require_issuer_admin_role,
+    model::{
+        error::Error,
+        transfer::{Transfer, TransferFilter, TransferForCreate},
+        Engine,
+    },
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+struct PathParam {
+    transfer_id: i64,
+}
+
+fn error_response(e: Error) -> Response {
+    match e {
+        Error::Conflict(reason) => {
+            (StatusCode::CONFLICT, Json(json!({ "error": reason }))).into_response()
+        }
+        Error::EntityNotFound { entity, .. } => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Not found in {entity}") })),
+        )
+            .into_response(),
+        e => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn get_transfers(
+    State(state): State<AppState<Engine>>,
+    Query(filter): Query<TransferFilter>,
+) -> Response {
+    match Transfer::list(&state, &filter).await {
+        Ok(transfers) => (StatusCode::OK, Json(json!({ "transfers": transfers }))).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_transfer(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { transfer_id }): Path<PathParam>,
+) -> Response {
+    match Transfer::get(&state, transfer_id).await {
+        Ok(transfer) => (StatusCode::OK, Json(json!({ "transfer": transfer }))).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn create_transfer(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+    Json(transfer): Json<TransferForCreate>,
+) -> Response {
+    match Transfer::send(&state, transfer, user_id).await {
+        Ok(id) => (
+            StatusCode::CREATED,
+            Json(json!({ "message": "Copy sent", "transfer_id": id })),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn receive_transfer(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+    Path(PathParam { transfer_id }): Path<PathParam>,
+) -> Response {
+    match Transfer::receive(&state, transfer_id, user_id).await {
+        Ok(receipt) => (StatusCode::OK, Json(json!(receipt))).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn cancel_transfer(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { transfer_id }): Path<PathParam>,
+) -> Response {
+    match Transfer::cancel(&state, transfer_id).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Transfer cancelled" })),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn routes() -> Router<AppState<Engine>> {
+    Router::new()
+        .route("/transfers", get(get_transfers))
+        .route("/transfer", post(create_transfer))
+        .route("/transfer/{transfer_id}", get(get_transfer))
+        .route("/transfer/{transfer_id}/receive", post(receive_transfer))
+        .route("/transfer/{transfer_id}/cancel", post(cancel_transfer))
+        .route_layer(middleware::from_fn(require_issuer_admin_role))
+}
@@ -0,0 +1,374 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tower_cookies::Cookies;
+use tracing::error;
+
+use crate::{
+    auth::{decode_two_factor_challenge, two_factor_challenge, Claims},
+    error::Error,
+    extractors::{client::Client, json::Json, path::Path},
+    middlewares::role::require_admin_role,
+    model::{
+        error::Error as ModelError,
+        login_throttle::ThrottleScope,
+        two_factor::{TwoFactor, TwoFactorPolicy},
+        user::User,
+        Engine,
+    },
+    state::AppState,
+    totp,
+};
+
+use super::auth::{check_throttle, issue_tokens, login_failed, login_keys, login_succeeded};
+
+#[derive(Deserialize)]
+struct PathParam {
+    user_id: i64,
+}
+
+/// Second login step, when the password alone isn't enough.
+#[derive(Serialize)]
+pub(super) struct Challenge {
+    /// `verify` for a code from the app, `enroll` when the policy wants one
+    /// but the user hasn't set it up yet
+    two_factor: &'static str,
+    challenge: String,
+}
+
+#[derive(Deserialize)]
+struct ChallengeRequest {
+    challenge: String,
+}
+
+#[derive(Deserialize)]
+struct LoginCode {
+    challenge: String,
+    code: Option<String>,
+    recovery_code: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CodeRequest {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct PolicyRequest {
+    require_staff: bool,
+}
+
+fn error_response(e: Error) -> Response {
+    match e {
+        Error::Model(ModelError::Conflict(reason)) => {
+            (StatusCode::CONFLICT, Json(json!({ "error": reason }))).into_response()
+        }
+        e => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn invalid_code() -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({ "error": "Invalid code" })),
+    )
+        .into_response()
+}
+
+/// What's left to do after the password checked out, `None` when the user
+/// can have their tokens right away.
+pub(super) async fn login_challenge(
+    state: &AppState<Engine>,
+    user: &User,
+) -> crate::error::Result<Option<Challenge>> {
+    let two_factor = if TwoFactor::is_enabled(state, user.id).await? {
+        "verify"
+    } else if TwoFactorPolicy::requires(state, &user.role).await? {
+        "enroll"
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(Challenge {
+        two_factor,
+        challenge: two_factor_challenge(state, user.id)?,
+    }))
+}
+
+/// A new secret and the ways to get it into an authenticator app.
+async fn enrollment(
+    state: &AppState<Engine>,
+    user_id: i64,
+) -> crate::error::Result<serde_json::Value> {
+    let user = User::get::<User>(state, user_id).await?;
+    let secret = TwoFactor::start(state, user_id).await?;
+    let uri = totp::uri(
+        &state.config.auth.two_factor_issuer,
+        &user.username,
+        &secret,
+    );
+    let qr = uri.as_deref().and_then(totp::qr_svg);
+    Ok(json!({ "secret": secret, "uri": uri, "qr_svg": qr }))
+}
+
+/// Set up the second factor during a login that can't finish without it.
+async fn enroll_at_login(
+    State(state): State<AppState<Engine>>,
+    Json(ChallengeRequest { challenge }): Json<ChallengeRequest>,
+) -> Response {
+    let Some(user_id) = decode_two_factor_challenge(&state, &challenge) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "Login has expired. Please login again" })),
+        )
+            .into_response();
+    };
+    match enrollment(&state, user_id).await {
+        Ok(enrollment) => (StatusCode::OK, Json(enrollment)).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Finish a login with a code from the app or a recovery code. Users
+/// enrolling as part of the login get their recovery codes with the tokens.
+async fn login_with_code(
+    State(state): State<AppState<Engine>>,
+    cookies: Cookies,
+    client: Client,
+    Json(req): Json<LoginCode>,
+) -> Response {
+    let Some(user_id) = decode_two_factor_challenge(&state, &req.challenge) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "Login has expired. Please login again" })),
+        )
+            .into_response();
+    };
+    let user = match User::get::<User>(&state, user_id).await {
+        Ok(user) => user,
+        Err(e) => return error_response(e.into()),
+    };
+    // codes can be guessed as well as passwords
+    if let Some(response) = check_throttle(&state, &login_keys(&user.username, &client)).await {
+        return response;
+    }
+
+    let required = match TwoFactorPolicy::requires(&state, &user.role).await {
+        Ok(required) => required,
+        Err(e) => return error_response(e.into()),
+    };
+    let checked = match TwoFactor::is_enabled(&state, user_id).await {
+        Ok(true) => match (&req.code, &req.recovery_code) {
+            (Some(code), _) => TwoFactor::verify(&state, user_id, code)
+                .await
+                .map(|valid| valid.then_some(None)),
+            (None, Some(code)) => TwoFactor::recover(&state, user_id, code)
+                .await
+                .map(|valid| valid.then_some(None)),
+            (None, None) => Ok(None),
+        },
+        Ok(false) if required => match &req.code {
+            Some(code) => TwoFactor::enable(&state, user_id, code)
+                .await
+                .map(|codes| codes.map(Some)),
+            None => Ok(None),
+        },
+        Ok(false) => Ok(None),
+        Err(e) => Err(e),
+    };
+    let recovery_codes = match checked {
+        Ok(Some(recovery_codes)) => recovery_codes,
+        Ok(None) => {
+            login_failed(&state, &login_keys(&user.username, &client)).await;
+            return invalid_code();
+        }
+        Err(e) => return error_response(e.into()),
+    };
+    login_succeeded(&state, ThrottleScope::Username, &user.username).await;
+
+    match issue_tokens(&state, &cookies, &client, user.id, user.role, None).await {
+        Ok(tokens) => {
+            let mut body = json!(tokens);
+            if let Some(codes) = recovery_codes {
+                body["recovery_codes"] = json!(codes);
+            }
+            (StatusCode::OK, Json(body)).into_response()
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_two_factor(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, role, .. }: Claims,
+) -> Response {
+    match TwoFactor::status(&state, user_id, &role).await {
+        Ok(status) => (StatusCode::OK, Json(json!({ "two_factor": status }))).into_response(),
+        Err(e) => error_response(e.into()),
+    }
+}
+
+async fn start_two_factor(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+) -> Response {
+    match enrollment(&state, user_id).await {
+        Ok(enrollment) => (StatusCode::OK, Json(enrollment)).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn confirm_two_factor(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+    Json(CodeRequest { code }): Json<CodeRequest>,
+) -> Response {
+    match TwoFactor::enable(&state, user_id, &code).await {
+        Ok(Some(codes)) => (
+            StatusCode::OK,
+            Json(
+                json!({ "message": "Two-factor authentication enabled", "recovery_codes": codes }),
+            ),
+        )
+            .into_response(),
+        Ok(None) => invalid_code(),
+        Err(e) => error_response(e.into()),
+    }
+}
+
+/// New recovery codes, for a code from the app so a stolen token can't
+/// take them.
+async fn regenerate_recovery_codes(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+    Json(CodeRequest { code }): Json<CodeRequest>,
+) -> Response {
+    match TwoFactor::verify(&state, user_id, &code).await {
+        Ok(true) => {}
+        Ok(false) => return invalid_code(),
+        Err(e) => return error_response(e.into()),
+    }
+    match TwoFactor::regenerate_recovery_codes(&state, user_id).await {
+        Ok(codes) => (StatusCode::OK, Json(json!({ "recovery_codes": codes }))).into_response(),
+        Err(e) => error_response(e.into()),
+    }
+}
+
+async fn disable_two_factor(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, role, .. }: Claims,
+    Json(CodeRequest { code }): Json<CodeRequest>,
+) -> Response {
+    match TwoFactorPolicy::requires(&state, &role).await {
+        Ok(false) => {}
+        Ok(true) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({ "error": "Two-factor authentication is required for your role" })),
+            )
+                .into_response()
+        }
+        Err(e) => return error_response(e.into()),
+    }
+    match TwoFactor::verify(&state, user_id, &code).await {
+        Ok(true) => {}
+        Ok(false) => return invalid_code(),
+        Err(e) => return error_response(e.into()),
+    }
+    match TwoFactor::disable(&state, user_id).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Two-factor authentication disabled" })),
+        )
+            .into_response(),
+        Err(e) => error_response(e.into()),
+    }
+}
+
+/// Take the second factor off a user who lost their device and their
+/// recovery codes, they set it up again at their next login.
+async fn reset_user_two_factor(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { user_id }): Path<PathParam>,
+) -> Response {
+    match TwoFactor::disable(&state, user_id).await {
+        Ok(true) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Two-factor authentication reset" })),
+        )
+            .into_response(),
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Two-factor authentication is not set up" })),
+        )
+            .into_response(),
+        Err(e) => error_response(e.into()),
+    }
+}
+
+async fn get_policy(State(state): State<AppState<Engine>>) -> Response {
+    match TwoFactorPolicy::get(&state).await {
+        Ok(policy) => (StatusCode::OK, Json(json!({ "policy": policy }))).into_response(),
+        Err(e) => error_response(e.into()),
+    }
+}
+
+/// Make staff sign in with a second factor, or stop doing so. Staff without
+/// one are signed out when it's turned on.
+async fn set_policy(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+    Json(PolicyRequest { require_staff }): Json<PolicyRequest>,
+) -> Response {
+    match TwoFactorPolicy::set(&state, require_staff, user_id).await {
+        Ok(sessions_ended) => (
+            StatusCode::OK,
+            Json(
+                json!({ "message": "Two-factor policy updated", "sessions_ended": sessions_ended }),
+            ),
+        )
+            .into_response(),
+        Err(e) => error_response(e.into()),
+    }
+}
+
+pub fn routes() -> Router<AppState<Engine>> {
+    let admin_routes = Router::new()
+        .route("/two-factor/policy", get(get_policy).put(set_policy))
+        .route("/user/{user_id}/two-factor", delete(reset_user_two_factor))
+        .route_layer(middleware::from_fn(require_admin_role));
+
+    Router::new()
+        .merge(admin_routes)
+        .route(
+            "/user/two-factor",
+            get(get_two_factor)
+                .post(start_two_factor)
+                .delete(disable_two_factor),
+        )
+        .route("/user/two-factor/confirm", post(confirm_two_factor))
+        .route(
+            "/user/two-factor/recovery-codes",
+            post(regenerate_recovery_codes),
+        )
+}
+
+/// The second step of a login, before there's a token to authorize with.
+pub fn login_routes() -> Router<AppState<Engine>> {
+    Router::new()
+        .route("/login/two-factor", post(login_with_code))
+        .route("/login/two-factor/enroll", post(enroll_at_login))
+}
@@ -1,27 +1,35 @@
 use axum::{
+    body::Bytes,
     extract::State,
-    http::StatusCode,
+    http::{header, StatusCode},
     middleware,
     response::{IntoResponse, Response},
-    routing::{get, put},
+    routing::{delete, get, post, put},
     Router,
 };
+use axum_extra::{headers::ContentType, TypedHeader};
 use serde::Deserialize;
 use serde_json::json;
-use tracing::error;
+use tracing::{error, warn};
 
 use crate::{
-    auth::Claims,
+    auth::{verify, Claims},
+    barcode,
     extractors::{json::Json, path::Path},
+    media,
     middlewares::role::{require_admin_role, require_issuer_admin_role},
     model::{
+        login_throttle::{LoginThrottle, ThrottleScope},
         review::Review,
-        user::{User, UserForUpdate},
+        session::Session,
+        user::{PasswordUpdate, User, UserForUpdate, Verification},
         Engine,
     },
     state::AppState,
 };
 
+use super::verification::request_verification;
+
 #[derive(Deserialize)]
 struct PathParam {
     user_id: i64,
@@ -41,6 +49,40 @@ async fn get_user(State(state): State<AppState<Engine>>, Path(param): Path<PathP
     }
 }
 
+#[derive(Deserialize)]
+struct CardParam {
+    card_number: String,
+}
+
+async fn get_user_by_card(
+    State(state): State<AppState<Engine>>,
+    Path(CardParam { card_number }): Path<CardParam>,
+) -> Response {
+    if !barcode::is_valid(&card_number) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Invalid card number" })),
+        )
+            .into_response();
+    }
+    match User::get_by_card_number::<User>(&state, &card_number).await {
+        Ok(Some(user)) => (StatusCode::OK, Json(json!({ "user": user }))).into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "User not found" })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
 async fn get_current_user(
     Claims { user_id, .. }: Claims,
     State(state): State<AppState<Engine>>,
@@ -108,6 +150,89 @@ async fn update_current_user(
     }
 }
 
+#[derive(Deserialize)]
+struct PinUpdate {
+    pin: String,
+}
+
+async fn update_current_user_pin(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+    Json(PinUpdate { pin }): Json<PinUpdate>,
+) -> Response {
+    if !(4..=8).contains(&pin.len()) || !pin.chars().all(|c| c.is_ascii_digit()) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "PIN must be 4 to 8 digits" })),
+        )
+            .into_response();
+    }
+    match User::set_pin(&state, user_id, &pin).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "PIN updated" }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "User not found" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn upload_current_user_photo(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+    TypedHeader(content_type): TypedHeader<ContentType>,
+    body: Bytes,
+) -> Response {
+    let prefix = format!("avatars/{user_id}");
+    let photo = match media::store_image(
+        state.media.as_ref(),
+        &prefix,
+        &content_type.to_string(),
+        body,
+    )
+    .await
+    {
+        Ok(url) => url,
+        Err(e) => {
+            error!("{e}");
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "Photo could not be uploaded" })),
+            )
+                .into_response();
+        }
+    };
+    let user = UserForUpdate {
+        photo: Some(photo.clone()),
+        ..Default::default()
+    };
+    let old = User::get::<User>(&state, user_id)
+        .await
+        .ok()
+        .and_then(|u| u.photo);
+    match User::update(&state, user_id, user).await {
+        Ok(_) => {
+            if let Some(old) = old {
+                if let Err(e) = media::delete_url(state.media.as_ref(), &old, &prefix).await {
+                    warn!("Could not remove old photo: {e}");
+                }
+            }
+            (StatusCode::OK, Json(json!({ "photo": photo }))).into_response()
+        }
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "User not found" })),
+            )
+                .into_response()
+        }
+    }
+}
+
 async fn get_reviews(
     State(state): State<AppState<Engine>>,
     Path(PathParam { user_id, .. }): Path<PathParam>,
@@ -125,19 +250,248 @@ async fn get_reviews(
     }
 }
 
+/// Change the password, other devices are signed out.
+async fn update_current_user_password(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, sid, .. }: Claims,
+    Json(PasswordUpdate { old, new }): Json<PasswordUpdate>,
+) -> Response {
+    if new.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Password can't be empty" })),
+        )
+            .into_response();
+    }
+    let user = match User::get::<User>(&state, user_id).await {
+        Ok(user) => user,
+        Err(e) => {
+            error!("{e}");
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "User not found" })),
+            )
+                .into_response();
+        }
+    };
+    if verify(&user.password, &old).is_err() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Wrong password" })),
+        )
+            .into_response();
+    }
+
+    match User::change_password(&state, user_id, &new, sid.as_deref()).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Password updated" })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SessionParam {
+    session_id: String,
+}
+
+/// Where the member is logged in.
+async fn get_current_user_sessions(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, sid, .. }: Claims,
+) -> Response {
+    match Session::list_for_user(&state, user_id, sid.as_deref()).await {
+        Ok(sessions) => (StatusCode::OK, Json(json!({ "sessions": sessions }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Sign out one of the member's devices.
+async fn delete_current_user_session(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+    Path(SessionParam { session_id }): Path<SessionParam>,
+) -> Response {
+    match Session::revoke_for_user(&state, &session_id, user_id).await {
+        Ok(true) => (StatusCode::OK, Json(json!({ "message": "Session ended" }))).into_response(),
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Session not found" })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Sign a user out everywhere, e.g. after their role changed or their
+/// account was disabled.
+async fn delete_user_sessions(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { user_id }): Path<PathParam>,
+) -> Response {
+    match Session::revoke_all(&state, user_id, None).await {
+        Ok(count) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Sessions ended", "count": count })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn resend_verification(
+    State(state): State<AppState<Engine>>,
+    Claims { user_id, .. }: Claims,
+) -> Response {
+    match request_verification(&state, user_id).await {
+        Ok(Verification::Send) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Verification email sent" })),
+        )
+            .into_response(),
+        Ok(Verification::Verified) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Email already verified" })),
+        )
+            .into_response(),
+        Ok(Verification::TooSoon { retry_after }) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            Json(
+                json!({ "error": "Verification email sent recently", "retry_after": retry_after }),
+            ),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Vouch for a member's address, e.g. one checked at the desk.
+async fn verify_user_email(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { user_id }): Path<PathParam>,
+) -> Response {
+    let user = match User::get::<User>(&state, user_id).await {
+        Ok(user) => user,
+        Err(e) => {
+            warn!("{e}");
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "User not found" })),
+            )
+                .into_response();
+        }
+    };
+    match User::verify_email(&state, user_id, &user.email).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Email verified" }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Let a user locked out after failed logins or kiosk PINs try again right
+/// away.
+async fn unlock_user(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { user_id }): Path<PathParam>,
+) -> Response {
+    let user = match User::get::<User>(&state, user_id).await {
+        Ok(user) => user,
+        Err(e) => {
+            warn!("{e}");
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "User not found" })),
+            )
+                .into_response();
+        }
+    };
+    let mut cleared = LoginThrottle::clear(&state, ThrottleScope::Username, &user.username).await;
+    if let (Ok(_), Some(card_number)) = (&cleared, &user.card_number) {
+        cleared = LoginThrottle::clear(&state, ThrottleScope::Card, card_number).await;
+    }
+    match cleared {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "User unlocked" }))).into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
 pub fn routes() -> Router<AppState<Engine>> {
     let admin_routes = Router::new()
         .route("/user/{user_id}", put(update_user))
+        .route("/user/{user_id}/sessions", delete(delete_user_sessions))
+        .route("/user/{user_id}/email/verify", post(verify_user_email))
+        .route("/user/{user_id}/unlock", post(unlock_user))
         .route_layer(middleware::from_fn(require_admin_role));
 
     let restricted = Router::new()
         .route("/users", get(get_users))
         .route("/user/{user_id}", get(get_user))
+        .route("/user/by-card/{card_number}", get(get_user_by_card))
         .merge(admin_routes)
         .route_layer(middleware::from_fn(require_issuer_admin_role));
 
     Router::new()
         .merge(restricted)
         .route("/user", get(get_current_user).put(update_current_user))
+        .route("/user/photo", put(upload_current_user_photo))
+        .route("/user/pin", put(update_current_user_pin))
+        .route("/user/password", put(update_current_user_password))
         .route("/user/reviews", get(get_reviews))
+        .route("/user/sessions", get(get_current_user_sessions))
+        .route("/user/email/verification", post(resend_verification))
+        .route(
+            "/user/sessions/{session_id}",
+            delete(delete_current_user_session),
+        )
 }
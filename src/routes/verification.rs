@@ -0,0 +1,88 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    auth::{decode_email_token, email_token},
+    extractors::json::Json,
+    model::{
+        outbox::Outbox,
+        user::{User, Verification},
+        Engine,
+    },
+    notify::mail::{render, Vars},
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+struct VerifyRequest {
+    token: String,
+}
+
+/// Queue the mail with the link confirming the user's address.
+async fn send_verification(state: &AppState<Engine>, user: &User) -> crate::error::Result<()> {
+    let token = email_token(state, user.id, &user.email)?;
+    let vars = Vars::new()
+        .user(user)
+        .set(
+            "link",
+            format!("{}/verify-email?token={token}", state.config.app_url),
+        )
+        .set("hours", state.config.auth.verification_hours);
+    Outbox::queue(state, render("verify_email", &user.email, &vars)?).await?;
+    Ok(())
+}
+
+/// Send the user a verification mail unless they're verified already or
+/// were sent one moments ago.
+pub(super) async fn request_verification(
+    state: &AppState<Engine>,
+    user_id: i64,
+) -> crate::error::Result<Verification> {
+    let verification = User::start_verification(state, user_id).await?;
+    if let Verification::Send = verification {
+        let user = User::get::<User>(state, user_id).await?;
+        send_verification(state, &user).await?;
+    }
+    Ok(verification)
+}
+
+async fn verify_email(
+    State(state): State<AppState<Engine>>,
+    Json(VerifyRequest { token }): Json<VerifyRequest>,
+) -> Response {
+    let Some((user_id, email)) = decode_email_token(&state, &token) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Invalid or expired verification link" })),
+        )
+            .into_response();
+    };
+    match User::verify_email(&state, user_id, &email).await {
+        Ok(true) => (StatusCode::OK, Json(json!({ "message": "Email verified" }))).into_response(),
+        Ok(false) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Invalid or expired verification link" })),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub fn routes() -> Router<AppState<Engine>> {
+    Router::new().route("/email/verify", post(verify_email))
+}
@@ -0,0 +1,124 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    extractors::{json::Json, path::Path},
+    middlewares::role::require_admin_role,
+    model::{
+        error::Error,
+        work::{Work, WorkForUpdate},
+        Engine,
+    },
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+struct PathParam {
+    work_id: i64,
+}
+
+#[derive(Deserialize)]
+struct Editions {
+    book_ids: Vec<i64>,
+}
+
+fn error_response(e: Error) -> Response {
+    match e {
+        Error::Conflict(reason) => {
+            (StatusCode::CONFLICT, Json(json!({ "error": reason }))).into_response()
+        }
+        Error::EntityNotFound { entity, .. } => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Not found in {entity}") })),
+        )
+            .into_response(),
+        e => {
+            error!("{e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Something is not right" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// The work with all its editions.
+async fn get_work(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { work_id }): Path<PathParam>,
+) -> Response {
+    let work = match Work::get(&state, work_id).await {
+        Ok(work) => work,
+        Err(e) => return error_response(e),
+    };
+    match Work::editions(&state, work_id).await {
+        Ok(editions) => (
+            StatusCode::OK,
+            Json(json!({ "work": work, "editions": editions })),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn update_work(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { work_id }): Path<PathParam>,
+    Json(work): Json<WorkForUpdate>,
+) -> Response {
+    match Work::update(&state, work_id, work).await {
+        Ok(_) => (StatusCode::OK, Json(json!({ "message": "Work updated" }))).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn merge_works(
+    State(state): State<AppState<Engine>>,
+    Json(Editions { book_ids }): Json<Editions>,
+) -> Response {
+    match Work::merge(&state, &book_ids).await {
+        Ok(work_id) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Editions grouped", "work_id": work_id })),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn split_work(
+    State(state): State<AppState<Engine>>,
+    Path(PathParam { work_id }): Path<PathParam>,
+    Json(Editions { book_ids }): Json<Editions>,
+) -> Response {
+    match Work::split(&state, work_id, &book_ids).await {
+        Ok(split) => (
+            StatusCode::OK,
+            Json(json!({ "message": "Editions split", "split": split })),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn routes() -> Router<AppState<Engine>> {
+    let admin_routes = Router::new()
+        .route("/work/{work_id}", put(update_work))
+        .route("/work/{work_id}/split", post(split_work))
+        .route("/works/merge", post(merge_works))
+        .route_layer(middleware::from_fn(require_admin_role));
+
+    Router::new()
+        .merge(admin_routes)
+        .route("/work/{work_id}", get(get_work))
+}
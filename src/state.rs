@@ -2,10 +2,24 @@ use std::sync::Arc;
 
 use sqlx::{Database, Pool};
 
+use crate::{config::Config, jwt::JwtKeys, media::MediaStore};
+
 pub type AppState<T> = Arc<AppStateInner<T>>;
 
 #[derive(Clone)]
 pub struct AppStateInner<T: Database> {
     pub pool: Pool<T>,
-    pub jwt_secret: String,
+    pub keys: JwtKeys,
+    pub config: Config,
+    pub media: Arc<dyn MediaStore>,
+}
+
+#[cfg(test)]
+pub fn test_state(pool: Pool<crate::model::Engine>) -> AppState<crate::model::Engine> {
+    Arc::new(AppStateInner {
+        pool,
+        keys: JwtKeys::from_secret("secret"),
+        config: Config::default(),
+        media: Arc::new(crate::media::LocalStore::new(std::env::temp_dir())),
+    })
 }